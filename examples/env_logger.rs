@@ -0,0 +1,18 @@
+use log::warn;
+
+fn main() {
+    let manager = kyuri::Manager::new(std::time::Duration::from_secs(1));
+    env_logger::Builder::new()
+        .filter_level(log::LevelFilter::Info)
+        .target(kyuri::integrations::env_logger_target(&manager))
+        .init();
+
+    let bar = manager.create_bar(100, "Processing", "{msg}: {bar} ({pos}/{len})", true);
+    for i in 0..=100 {
+        bar.set_pos(i);
+        if i % 10 == 0 {
+            warn!("Reached {}", i);
+        }
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+}
@@ -1,13 +1,11 @@
-use std::sync::Mutex;
-
 use tracing::warn;
 
 fn main() {
     let manager = kyuri::Manager::new(std::time::Duration::from_secs(1));
     let writer = manager.create_writer();
-    // Well, here tracing_subscriber does not support to just give a writer, so we need to wrap it with Mutex...
+    // KyuriWriter is cloneable, so each event gets its own writer, without wrapping it with Mutex
     let subscriber = tracing_subscriber::fmt()
-        .with_writer(Mutex::new(writer))
+        .with_writer(move || writer.clone())
         .finish();
     tracing::subscriber::set_global_default(subscriber).unwrap();
 
@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Parse the input as a template both ways, and render it with a bar.
+fuzz_target!(|template: &str| {
+    if let Ok(parsed) = kyuri::Template::parse(template) {
+        // Strict parsing only adds errors to lenient parsing
+        let lenient = kyuri::Template::new(template);
+        assert_eq!(format!("{:?}", parsed), format!("{:?}", lenient));
+    }
+    let manager = kyuri::Manager::new(std::time::Duration::from_secs(1))
+        .with_file(std::fs::File::create("/dev/null").unwrap())
+        .force_ansi(true);
+    let bar = manager.create_bar(100, "fuzzing", template, true);
+    bar.set_pos(150);
+    manager.draw(true);
+});
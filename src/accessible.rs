@@ -0,0 +1,74 @@
+//! Screen reader friendly output, see `Manager::with_accessible`.
+//!
+//! Each bar is printed as a sentence without glyphs, like `Download: 3 of 10, 30 percent`,
+//! when it starts, when it's finished or failed, and in between at most every interval given.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{clock, BarState, BarStatus, LockUnpoisoned};
+
+/// Bars seen, with when they were last printed, or `None` when they're done.
+pub(crate) struct AccessibleLog {
+    interval: Duration,
+    printed: BTreeMap<usize, Option<Instant>>,
+}
+
+impl AccessibleLog {
+    /// Print bars in progress at most every `interval`.
+    pub(crate) fn new(interval: Duration) -> Self {
+        AccessibleLog {
+            interval,
+            printed: BTreeMap::new(),
+        }
+    }
+
+    /// Render sentences of bars changed since the last draw.
+    pub(crate) fn render(&mut self, states: &BTreeMap<usize, Arc<Mutex<BarState>>>) -> String {
+        let mut output = String::new();
+        self.printed.retain(|id, _| states.contains_key(id));
+        let now = clock::now();
+        for (&id, state) in states {
+            let mut state = state.lock_unpoisoned();
+            if !state.visible || !state.need_redraw {
+                continue;
+            }
+            let done = state.is_done();
+            match self.printed.get(&id) {
+                Some(None) => continue,
+                Some(&Some(last))
+                    if !done && now.saturating_duration_since(last) < self.interval =>
+                {
+                    // Left marked to be printed later
+                    continue;
+                }
+                _ => {}
+            }
+            state.need_redraw = false;
+            state.drawn_pos = state.pos;
+            output.push_str(&sentence(&state));
+            output.push('\n');
+            self.printed.insert(id, (!done).then_some(now));
+        }
+        output
+    }
+}
+
+fn sentence(state: &BarState) -> String {
+    let progress = match state.status() {
+        BarStatus::Failed => "failed".to_string(),
+        BarStatus::Finished if state.len > 0 => "done".to_string(),
+        _ if state.len == 0 => state.pos.to_string(),
+        _ => {
+            let percent = state.pos.min(state.len) as u128 * 100 / state.len as u128;
+            format!("{} of {}, {} percent", state.pos, state.len, percent)
+        }
+    };
+    match state.message.is_empty() {
+        true => progress,
+        false => format!("{}: {}", state.message, progress),
+    }
+}
@@ -0,0 +1,276 @@
+//! The module contains `DrawBackend`, how frames are written to the output, selected by `Manager::with_backend`.
+//!
+//! Without a backend set, the manager picks `Ansi` when the output is a terminal, and `Append` otherwise.
+//! A custom backend gets each frame both as the rendered text and as snapshots of visible bars,
+//! so it could write a format of its own:
+//!
+//! ```
+//! use kyuri::backend::{DrawBackend, Frame};
+//!
+//! struct Percent;
+//!
+//! impl DrawBackend for Percent {
+//!     fn redraws(&self) -> bool {
+//!         false
+//!     }
+//!
+//!     fn draw(&mut self, out: &mut dyn std::io::Write, frame: &Frame<'_>) -> std::io::Result<()> {
+//!         for bar in frame.bars {
+//!             writeln!(out, "{}%", (bar.pos * 100).checked_div(bar.len).unwrap_or(0))?;
+//!         }
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let manager = kyuri::Manager::new(std::time::Duration::from_secs(1)).with_backend(Percent);
+//! ```
+//!
+//! Bytes written by backends are still recorded by `Manager::with_recording`, and encoded by `Manager::set_encoder`.
+
+use std::io::{self, Write};
+
+use crate::{
+    count_lines, lsp::json_string, record, BarSnapshot, BarStatus, Encoder, CLEAR_ANSI, UP_ANSI,
+};
+
+/// A frame to be written by a `DrawBackend`.
+#[derive(Debug, Clone, Copy)]
+pub struct Frame<'a> {
+    /// Rendered log lines and bars, each line ending with `\n`, after `Manager::set_frame_filter`.
+    ///
+    /// When the backend does not redraw, only bars changed since the last frame are rendered.
+    pub text: &'a str,
+    /// Visible bars in the order of creation, or empty for built-in backends selected by the manager.
+    pub bars: &'a [BarSnapshot],
+    /// The width of the terminal in columns.
+    pub width: usize,
+}
+
+/// How frames are written to the output.
+pub trait DrawBackend: Send {
+    /// Whether each frame replaces the last one, like in a terminal.
+    ///
+    /// When true, all visible bars are rendered in each frame, with colors and effects,
+    /// and `clear` is called before each frame and around `Manager::suspend`.
+    fn redraws(&self) -> bool;
+
+    /// Write `frame` to `out`.
+    fn draw(&mut self, out: &mut dyn Write, frame: &Frame<'_>) -> io::Result<()>;
+
+    /// Remove the last frame from `out`. The default does nothing.
+    fn clear(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        let _ = out;
+        Ok(())
+    }
+
+    /// Restore `out` after the last frame, when the manager is dropped or its output is replaced.
+    /// The default does nothing.
+    fn finish(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        let _ = out;
+        Ok(())
+    }
+}
+
+/// Redraw bars in place with ANSI escape sequences, the default for terminals.
+#[derive(Debug, Default)]
+pub struct Ansi {
+    /// Lines the last frame takes.
+    lines: usize,
+}
+
+impl Ansi {
+    /// Forget the last frame, when it's not to be cleared.
+    pub(crate) fn reset(&mut self) {
+        self.lines = 0;
+    }
+
+    #[cfg(all(test, not(loom)))]
+    pub(crate) fn lines(&self) -> usize {
+        self.lines
+    }
+}
+
+impl DrawBackend for Ansi {
+    fn redraws(&self) -> bool {
+        true
+    }
+
+    fn draw(&mut self, out: &mut dyn Write, frame: &Frame<'_>) -> io::Result<()> {
+        // Even when the write failed, as part of the frame could have been written
+        self.lines = count_lines(frame.text, frame.width);
+        out.write_all(frame.text.as_bytes())
+    }
+
+    fn clear(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        let clear = format!("{}{}", UP_ANSI, CLEAR_ANSI).repeat(self.lines);
+        self.lines = 0;
+        out.write_all(clear.as_bytes())
+    }
+}
+
+/// Redraw bars in place with commands of [crossterm](https://docs.rs/crossterm), with the `crossterm` feature.
+///
+/// Unlike `Ansi`, lines are counted with the size of the terminal detected by crossterm, and the legacy Windows
+/// console without ANSI support is also handled. Bars could be drawn in the alternate screen, left when finished:
+///
+/// ```no_run
+/// let manager = kyuri::Manager::new(std::time::Duration::from_secs(1))
+///     .with_backend(kyuri::backend::Crossterm::new().alternate_screen(true));
+/// ```
+#[cfg(feature = "crossterm")]
+#[derive(Debug, Default)]
+pub struct Crossterm {
+    lines: usize,
+    alternate_screen: bool,
+    entered: bool,
+}
+
+#[cfg(feature = "crossterm")]
+impl Crossterm {
+    /// Draw in the main screen, like `Ansi`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether to draw in the alternate screen, entered before the first frame.
+    pub fn alternate_screen(mut self, enabled: bool) -> Self {
+        self.alternate_screen = enabled;
+        self
+    }
+}
+
+#[cfg(feature = "crossterm")]
+impl DrawBackend for Crossterm {
+    fn redraws(&self) -> bool {
+        true
+    }
+
+    fn draw(&mut self, out: &mut dyn Write, frame: &Frame<'_>) -> io::Result<()> {
+        use crossterm::{cursor, terminal, QueueableCommand};
+
+        if self.alternate_screen && !self.entered {
+            out.queue(terminal::EnterAlternateScreen)?
+                .queue(cursor::MoveTo(0, 0))?;
+            self.entered = true;
+        }
+        let width = terminal::size().map_or(frame.width, |(columns, _)| columns as usize);
+        self.lines = count_lines(frame.text, width);
+        out.write_all(frame.text.as_bytes())
+    }
+
+    fn clear(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        use crossterm::{cursor, terminal, QueueableCommand};
+
+        if self.lines > 0 {
+            let lines = self.lines.min(u16::MAX as usize) as u16;
+            self.lines = 0;
+            out.queue(cursor::MoveToPreviousLine(lines))?
+                .queue(terminal::Clear(terminal::ClearType::FromCursorDown))?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        use crossterm::{terminal, QueueableCommand};
+
+        if self.entered {
+            self.entered = false;
+            self.lines = 0;
+            out.queue(terminal::LeaveAlternateScreen)?;
+        }
+        Ok(())
+    }
+}
+
+/// Append lines of changed bars, the default when the output is not a terminal.
+#[derive(Debug, Default)]
+pub struct Append;
+
+impl DrawBackend for Append {
+    fn redraws(&self) -> bool {
+        false
+    }
+
+    fn draw(&mut self, out: &mut dyn Write, frame: &Frame<'_>) -> io::Result<()> {
+        out.write_all(frame.text.as_bytes())
+    }
+}
+
+/// Write each frame as a line of JSON with the state of visible bars, for other programs to read:
+///
+/// ```text
+/// {"bars": [{"message": "Download", "pos": 30, "len": 100, "elapsed": 1.5, "status": "in_progress"}]}
+/// ```
+#[derive(Debug, Default)]
+pub struct Json;
+
+impl DrawBackend for Json {
+    fn redraws(&self) -> bool {
+        false
+    }
+
+    fn draw(&mut self, out: &mut dyn Write, frame: &Frame<'_>) -> io::Result<()> {
+        let mut line = json_snapshot(frame.bars);
+        line.push('\n');
+        out.write_all(line.as_bytes())
+    }
+}
+
+/// The line written by `Json`, without the newline.
+pub(crate) fn json_snapshot(bars: &[BarSnapshot]) -> String {
+    let bars: Vec<String> = bars
+        .iter()
+        .map(|bar| {
+            format!(
+                r#"{{"message": {}, "pos": {}, "len": {}, "elapsed": {}, "status": "{}"}}"#,
+                json_string(&bar.message),
+                bar.pos,
+                bar.len,
+                bar.elapsed.as_secs_f64(),
+                status_name(&bar.status)
+            )
+        })
+        .collect();
+    format!("{{\"bars\": [{}]}}", bars.join(", "))
+}
+
+fn status_name(status: &BarStatus) -> &'static str {
+    match status {
+        BarStatus::New => "new",
+        BarStatus::InProgress => "in_progress",
+        BarStatus::Finished => "finished",
+        BarStatus::Overflowed => "overflowed",
+        BarStatus::Failed => "failed",
+    }
+}
+
+/// The output as given to backends, which records and encodes what they write.
+///
+/// Backends are expected to write whole strings, as the encoder takes text.
+pub(crate) struct Sink<'a> {
+    pub(crate) out: &'a mut dyn Write,
+    pub(crate) recorder: Option<&'a mut record::Recorder>,
+    pub(crate) encoder: Option<&'a Encoder>,
+}
+
+impl Write for Sink<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        let text = String::from_utf8_lossy(buf);
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record(&text);
+        }
+        match self.encoder {
+            Some(encoder) => self.out.write_all(&encoder(&text)),
+            None => self.out.write_all(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
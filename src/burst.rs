@@ -0,0 +1,50 @@
+//! A token bucket for unforced draws within the interval, see `Manager::with_burst`.
+//!
+//! A token is refilled per interval, and each unforced draw spends one, including those after the
+//! interval: unforced draws within the interval are allowed while tokens are left, so changes after
+//! an idle period are drawn immediately, and a busy stream settles to the interval.
+
+use std::time::{Duration, Instant};
+
+pub(crate) struct TokenBucket {
+    capacity: u32,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket, or a disabled one with capacity 0.
+    pub(crate) fn new(capacity: u32) -> Self {
+        TokenBucket {
+            capacity,
+            tokens: capacity as f64,
+            refilled_at: Instant::now(),
+        }
+    }
+
+    /// Whether a token is left, after refilling those earned since the last call.
+    pub(crate) fn available(&mut self, now: Instant, interval: Duration) -> bool {
+        self.refill(now, interval);
+        self.tokens >= 1.0
+    }
+
+    /// Spend a token for an unforced draw, or what's left of one, so that the bucket refills only while idle.
+    pub(crate) fn spend(&mut self, now: Instant, interval: Duration) {
+        self.refill(now, interval);
+        self.tokens = (self.tokens - 1.0).max(0.0);
+    }
+
+    fn refill(&mut self, now: Instant, interval: Duration) {
+        if self.capacity == 0 {
+            return;
+        }
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        let earned = if interval.is_zero() {
+            f64::INFINITY
+        } else {
+            elapsed.as_secs_f64() / interval.as_secs_f64()
+        };
+        self.tokens = (self.tokens + earned).min(self.capacity as f64);
+        self.refilled_at = now;
+    }
+}
@@ -0,0 +1,159 @@
+//! The module contains `Checklist`, a list of steps rendered above the progress bars.
+//!
+//! Each step is rendered as a line with an icon showing its state, like:
+//!
+//! ```text
+//! ✅ Resolve deps (0:00:01)
+//! ⏳ Build (0:00:12)
+//! 🔲 Test
+//! ```
+
+use std::{
+    sync::{Arc, Mutex, Weak},
+    time::Instant,
+};
+
+use crate::{clock, duration_to_human, BarState, LockUnpoisoned, ManagerInner};
+
+#[cfg(not(feature = "ascii_only"))]
+mod icons {
+    pub(super) const PENDING_TEMPLATE: &str = "🔲 {msg}";
+    pub(super) const RUNNING_TEMPLATE: &str = "⏳ {msg} ({elapsed})";
+    pub(super) const DONE: &str = "✅";
+    pub(super) const FAILED: &str = "❌";
+}
+
+// Like `Theme::ascii`
+#[cfg(feature = "ascii_only")]
+mod icons {
+    pub(super) const PENDING_TEMPLATE: &str = "[  ] {msg}";
+    pub(super) const RUNNING_TEMPLATE: &str = "[>>] {msg} ({elapsed})";
+    pub(super) const DONE: &str = "[OK]";
+    pub(super) const FAILED: &str = "[XX]";
+}
+
+use icons::*;
+
+/// A list of steps, created by `Manager::create_checklist`.
+///
+/// Steps of all checklists of a manager are drawn in their own region above the bars (below log lines),
+/// by the order of creation. When output is not a terminal, a step is written when it changes, like a bar.
+/// They are removed from the manager when the checklist and all `Step` handles are dropped.
+pub struct Checklist {
+    manager: Weak<ManagerInner>,
+    steps: Mutex<Vec<Step>>,
+}
+
+struct StepInner {
+    manager: Weak<ManagerInner>,
+    /// `None` when added after the manager is dropped.
+    state: Option<Arc<Mutex<BarState>>>,
+    started_at: Mutex<Option<Instant>>,
+}
+
+/// A handle for a step in a `Checklist`.
+#[derive(Clone)]
+pub struct Step {
+    inner: Arc<StepInner>,
+}
+
+impl Checklist {
+    pub(crate) fn new(manager: Weak<ManagerInner>) -> Self {
+        Checklist {
+            manager,
+            steps: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Add a pending step named `name`. This makes a forced draw.
+    ///
+    /// When the manager is dropped, the step would not be able to be interacted with.
+    pub fn add_step(&self, name: &str) -> Step {
+        let state = self.manager.upgrade().map(|manager| {
+            let state = manager.new_state(1, name, PENDING_TEMPLATE.into(), true, None);
+            let state = Arc::new(Mutex::new(state));
+            manager.checklist.lock_unpoisoned().push(state.clone());
+            manager.mark_redraw();
+            manager.draw(true);
+            state
+        });
+        let step = Step {
+            inner: Arc::new(StepInner {
+                manager: self.manager.clone(),
+                state,
+                started_at: Mutex::new(None),
+            }),
+        };
+        self.steps.lock_unpoisoned().push(step.clone());
+        step
+    }
+}
+
+impl Step {
+    /// Mark the step as running. The elapsed time is shown after its name. This makes a forced draw.
+    pub fn start(&self) {
+        let now = clock::now();
+        *self.inner.started_at.lock_unpoisoned() = Some(now);
+        self.update(|state| {
+            state.created_at = now;
+            state.template = RUNNING_TEMPLATE.into();
+        });
+    }
+
+    /// Mark the step as done. The time it took is shown after its name. This makes a forced draw.
+    pub fn done(&self) {
+        self.finish(DONE, false);
+    }
+
+    /// Mark the step as failed. The time it took is shown after its name. This makes a forced draw.
+    ///
+    /// The effect set by `Manager::set_failed_effect` applies to the step.
+    pub fn fail(&self) {
+        self.finish(FAILED, true);
+    }
+
+    fn finish(&self, icon: &str, failed: bool) {
+        let template = match *self.inner.started_at.lock_unpoisoned() {
+            Some(started_at) => format!(
+                "{icon} {{msg}} ({})",
+                duration_to_human(clock::elapsed(started_at))
+            ),
+            None => format!("{icon} {{msg}}"),
+        };
+        self.update(|state| {
+            state.template = template.into();
+            state.failed = failed;
+            state.pos = state.len;
+            state.update_finished();
+        });
+    }
+
+    /// Change the state of the step, and force a draw.
+    fn update(&self, f: impl FnOnce(&mut BarState)) {
+        let (Some(manager), Some(state)) = (self.inner.manager.upgrade(), &self.inner.state) else {
+            return;
+        };
+        let mut state = state.lock_unpoisoned();
+        f(&mut state);
+        state.mark_changed(true);
+        // Drop state before drawing, deadlock otherwise!
+        std::mem::drop(state);
+        manager.mark_redraw();
+        manager.draw(true);
+    }
+}
+
+impl Drop for StepInner {
+    /// Remove the step from the manager, and make a draw like dropping a bar.
+    fn drop(&mut self) {
+        let (Some(manager), Some(state)) = (self.manager.upgrade(), &self.state) else {
+            return;
+        };
+        manager
+            .checklist
+            .lock_unpoisoned()
+            .retain(|step| !Arc::ptr_eq(step, state));
+        manager.mark_redraw();
+        manager.draw_event();
+    }
+}
@@ -0,0 +1,162 @@
+//! The module contains `CiMode`, an output mode for CI logs, set by `Manager::with_ci_mode`.
+//!
+//! When output is not a terminal, each bar is wrapped in a collapsible group of the CI service,
+//! and only printed when it starts, at each 10% milestone (see `Manager::with_milestones`), and when it's finished
+//! or failed:
+//!
+//! ```text
+//! ::group::Downloading
+//! Downloading 0/100
+//! Downloading 10/100
+//! ...
+//! Downloading 100/100
+//! ::endgroup::
+//! ```
+//!
+//! Groups of CI services do not nest, so lines of bars running at the same time end up in the
+//! group started first.
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::{BarState, LockUnpoisoned, RenderOptions};
+
+/// Markers of collapsible groups in CI logs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CiMode {
+    /// `::group::` and `::endgroup::` of GitHub Actions.
+    GitHub,
+    /// `section_start` and `section_end` of GitLab CI.
+    GitLab,
+    /// `travis_fold:start` and `travis_fold:end` of Travis CI.
+    Travis,
+}
+
+impl CiMode {
+    /// Detect the CI service from environment variables set by it, or `None` when not in a known one.
+    pub fn detect() -> Option<Self> {
+        let is_set = |name| std::env::var_os(name).is_some_and(|value| !value.is_empty());
+        if is_set("GITHUB_ACTIONS") {
+            Some(CiMode::GitHub)
+        } else if is_set("GITLAB_CI") {
+            Some(CiMode::GitLab)
+        } else if is_set("TRAVIS") {
+            Some(CiMode::Travis)
+        } else {
+            None
+        }
+    }
+
+    fn start(self, id: usize, header: &str) -> String {
+        match self {
+            CiMode::GitHub => format!("::group::{}\n", header),
+            CiMode::GitLab => format!(
+                "\x1b[0Ksection_start:{}:kyuri_{}\r\x1b[0K{}\n",
+                unix_time(),
+                id,
+                header
+            ),
+            CiMode::Travis => format!("travis_fold:start:kyuri_{}\r\x1b[0K{}\n", id, header),
+        }
+    }
+
+    fn end(self, id: usize) -> String {
+        match self {
+            CiMode::GitHub => "::endgroup::\n".to_string(),
+            CiMode::GitLab => format!("\x1b[0Ksection_end:{}:kyuri_{}\r\x1b[0K\n", unix_time(), id),
+            CiMode::Travis => format!("travis_fold:end:kyuri_{}\r\x1b[0K\n", id),
+        }
+    }
+}
+
+fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// The default step of milestones in percent.
+pub(crate) const DEFAULT_MILESTONE_STEP: u8 = 10;
+
+/// Bars seen, with the milestone (in steps) last printed, or `None` when the group has ended.
+///
+/// Without a mode, only milestones are printed, for `Manager::with_tqdm_style` and `Manager::with_milestones`.
+pub(crate) struct CiLog {
+    mode: Option<CiMode>,
+    /// In percent, from 1 to 100.
+    step: u8,
+    started: BTreeMap<usize, Option<u64>>,
+}
+
+impl CiLog {
+    pub(crate) fn new(mode: Option<CiMode>, step: u8) -> Self {
+        CiLog {
+            mode,
+            step: step.clamp(1, 100),
+            started: BTreeMap::new(),
+        }
+    }
+
+    pub(crate) fn mode(&self) -> Option<CiMode> {
+        self.mode
+    }
+
+    /// Render starts, milestones and ends of bars since the last draw.
+    pub(crate) fn render(
+        &mut self,
+        states: &BTreeMap<usize, Arc<Mutex<BarState>>>,
+        options: &RenderOptions,
+    ) -> String {
+        let mut output = String::new();
+        // Dropped bars could not be rendered anymore, so their groups just end
+        let dropped: Vec<usize> = self
+            .started
+            .keys()
+            .filter(|id| !states.contains_key(id))
+            .copied()
+            .collect();
+        for id in dropped {
+            if let Some(Some(_)) = self.started.remove(&id) {
+                output.push_str(&self.end(id));
+            }
+        }
+        for (&id, state) in states {
+            let mut state = state.lock_unpoisoned();
+            if !state.visible || !state.need_redraw {
+                continue;
+            }
+            state.need_redraw = false;
+            state.drawn_pos = state.pos;
+            let milestone = (state.pos.min(state.len) as u128 * 100)
+                .checked_div(state.len as u128 * self.step as u128)
+                .unwrap_or(0) as u64;
+            let done = state.is_done();
+            match self.started.get(&id) {
+                None => output.push_str(&self.start(id, &state.message)),
+                Some(None) => continue,
+                Some(&Some(last)) if last >= milestone && !done => continue,
+                Some(Some(_)) => {}
+            }
+            output.push_str(&state.render(options));
+            output.push('\n');
+            if done {
+                output.push_str(&self.end(id));
+                self.started.insert(id, None);
+            } else {
+                self.started.insert(id, Some(milestone));
+            }
+        }
+        output
+    }
+
+    fn start(&self, id: usize, header: &str) -> String {
+        self.mode
+            .map_or(String::new(), |mode| mode.start(id, header))
+    }
+
+    fn end(&self, id: usize) -> String {
+        self.mode.map_or(String::new(), |mode| mode.end(id))
+    }
+}
@@ -0,0 +1,42 @@
+//! The clock of bars, which could be mocked on the current thread for deterministic frames (see `test::render_frames`).
+
+use std::{
+    cell::Cell,
+    time::{Duration, Instant},
+};
+
+thread_local! {
+    static MOCKED: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// The current time, or the mocked time on this thread.
+pub(crate) fn now() -> Instant {
+    MOCKED.with(Cell::get).unwrap_or_else(Instant::now)
+}
+
+/// The time since `since`, by `now`.
+pub(crate) fn elapsed(since: Instant) -> Duration {
+    now().saturating_duration_since(since)
+}
+
+/// Mocks the clock on this thread until dropped, starting from the current time.
+pub(crate) struct MockClock {
+    previous: Option<Instant>,
+}
+
+impl MockClock {
+    pub(crate) fn new() -> Self {
+        let previous = MOCKED.with(|mocked| mocked.replace(Some(Instant::now())));
+        MockClock { previous }
+    }
+
+    pub(crate) fn advance(&self, duration: Duration) {
+        MOCKED.with(|mocked| mocked.set(mocked.get().map(|now| now + duration)));
+    }
+}
+
+impl Drop for MockClock {
+    fn drop(&mut self) {
+        MOCKED.with(|mocked| mocked.set(self.previous));
+    }
+}
@@ -0,0 +1,190 @@
+//! The module contains `ControlSocket`, a Unix domain socket to query and control a `Manager` at runtime.
+//!
+//! The protocol is line based. Each command is a line, and its response ends with a line of `ok`,
+//! or `error: <reason>`. Supported commands:
+//!
+//! - `list`: List bars, one per line: `<id> <visible> <pos> <len> <message>`.
+//! - `snapshot`: Render visible bars as plain text, like when output is not a terminal.
+//! - `hide <id>`, `show <id>`: Set the visibility of a bar. `all` could be used as id.
+//! - `interval <milliseconds>`: Set the minimum interval between two unforced draws.
+//!
+//! For example, `echo list | socat - UNIX-CONNECT:/tmp/app.sock`.
+//!
+//! A command could be at most 4 KiB, and a client which stays idle or doesn't read responses for
+//! 5 seconds is disconnected.
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    os::unix::net::{UnixListener, UnixStream},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Weak,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{LockUnpoisoned, ManagerInner};
+
+/// How often the listener checks whether it shall stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// The most bytes read for a command line.
+const MAX_COMMAND: u64 = 4 * 1024;
+/// How long a client could stay idle, or not read responses, before being disconnected.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A handle for the control socket created by `Manager::serve_control_socket`.
+///
+/// When dropped, the socket stops listening and its file is removed.
+pub struct ControlSocket {
+    path: PathBuf,
+    stopped: Arc<AtomicBool>,
+    // thread join requires ownership of the thread, so an Option is used to take it out
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl ControlSocket {
+    pub(crate) fn new(manager: Weak<ManagerInner>, path: &Path) -> std::io::Result<Self> {
+        let listener = UnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stopped2 = stopped.clone();
+        let thread = thread::spawn(move || {
+            while !stopped2.load(Ordering::Acquire) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        // A thread for each connection, so an idle client doesn't block others,
+                        // nor dropping the socket
+                        let manager = manager.clone();
+                        let stopped = stopped2.clone();
+                        thread::spawn(move || {
+                            let _ = handle_connection(&manager, &stopped, stream);
+                        });
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                        thread::sleep(POLL_INTERVAL);
+                    }
+                    Err(_) => break,
+                }
+                if manager.strong_count() == 0 {
+                    break;
+                }
+            }
+        });
+        Ok(ControlSocket {
+            path: path.to_path_buf(),
+            stopped,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Release);
+        if let Some(t) = self.thread.take() {
+            let _ = t.join();
+        }
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Serve commands until the client closes the connection, stays idle for `CLIENT_TIMEOUT`,
+/// sends a command longer than `MAX_COMMAND`, or the socket is dropped.
+fn handle_connection(
+    manager: &Weak<ManagerInner>,
+    stopped: &AtomicBool,
+    stream: UnixStream,
+) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let len = (&mut reader).take(MAX_COMMAND).read_line(&mut line)?;
+        if len == 0 || stopped.load(Ordering::Acquire) {
+            break;
+        }
+        if len as u64 == MAX_COMMAND && !line.ends_with('\n') {
+            writeln!(writer, "error: command too long")?;
+            break;
+        }
+        let Some(manager) = manager.upgrade() else {
+            writeln!(writer, "error: manager is dropped")?;
+            break;
+        };
+        // Respond after the command returns, so that a client not reading doesn't hold any lock
+        match handle_command(&manager, line.trim()) {
+            Ok(response) => writeln!(writer, "{}ok", response)?,
+            Err(e) => writeln!(writer, "error: {}", e)?,
+        }
+    }
+    Ok(())
+}
+
+/// Run a command, and return its response before the line of `ok`.
+fn handle_command(manager: &Arc<ManagerInner>, command: &str) -> Result<String, String> {
+    use std::fmt::Write as _;
+
+    let (command, arg) = command.split_once(' ').unwrap_or((command, ""));
+    let mut response = String::new();
+    match command {
+        "list" => {
+            let states = manager.states.lock_unpoisoned();
+            for (id, state) in states.iter() {
+                let state = state.lock_unpoisoned();
+                writeln!(
+                    response,
+                    "{} {} {} {} {}",
+                    id, state.visible, state.pos, state.len, state.message
+                )
+                .map_err(|e| e.to_string())?;
+            }
+        }
+        "snapshot" => {
+            let options = manager.render_options(false);
+            let states = manager.states.lock_unpoisoned();
+            for state in states.values() {
+                let state = state.lock_unpoisoned();
+                if state.visible {
+                    writeln!(response, "{}", state.render(&options)).map_err(|e| e.to_string())?;
+                }
+            }
+        }
+        "hide" | "show" => {
+            let visible = command == "show";
+            let states = manager.states.lock_unpoisoned();
+            let targets: Vec<_> = if arg == "all" {
+                states.values().cloned().collect()
+            } else {
+                let id: usize = arg.parse().map_err(|_| format!("invalid id {:?}", arg))?;
+                vec![states
+                    .get(&id)
+                    .cloned()
+                    .ok_or_else(|| format!("no bar with id {}", id))?]
+            };
+            std::mem::drop(states);
+            for state in targets {
+                let mut state = state.lock_unpoisoned();
+                if state.visible != visible {
+                    state.visible = visible;
+                    state.need_redraw = true;
+                }
+            }
+            manager.mark_redraw();
+            manager.draw(true);
+        }
+        "interval" => {
+            let millis: u64 = arg
+                .parse()
+                .map_err(|_| format!("invalid interval {:?}", arg))?;
+            manager.set_interval(Duration::from_millis(millis));
+        }
+        _ => return Err(format!("unknown command {:?}", command)),
+    }
+    Ok(response)
+}
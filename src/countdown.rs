@@ -0,0 +1,109 @@
+//! The module contains `Countdown`, a handle for a countdown started by `Bar::countdown`.
+
+use std::{
+    sync::{Arc, Condvar, Mutex, Weak},
+    time::{Duration, Instant},
+};
+
+use crate::{clock, LockUnpoisoned, ManagerInner, PosSource};
+
+/// A handle to wait for or cancel a countdown on a bar.
+///
+/// It could be cloned, so that one thread waits for the countdown while another cancels it.
+#[derive(Clone)]
+pub struct Countdown {
+    id: usize,
+    /// The generation of the countdown on the bar, as a newer countdown may be started on it.
+    generation: u64,
+    manager: Weak<ManagerInner>,
+    deadline: Instant,
+    cancelled: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl Countdown {
+    pub(crate) fn new(
+        id: usize,
+        generation: u64,
+        manager: Weak<ManagerInner>,
+        duration: Duration,
+    ) -> Self {
+        Countdown {
+            id,
+            generation,
+            manager,
+            deadline: clock::now() + duration,
+            cancelled: Arc::new((Mutex::new(false), Condvar::new())),
+        }
+    }
+
+    /// Cancel the countdown. Threads waiting for it would return immediately.
+    ///
+    /// The bar stops counting at once, even if no thread is waiting.
+    pub fn cancel(&self) {
+        let (lock, cvar) = &*self.cancelled;
+        *lock.lock_unpoisoned() = true;
+        cvar.notify_all();
+        self.stop(false);
+    }
+
+    /// Return whether the countdown has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancelled.0.lock_unpoisoned()
+    }
+
+    /// Block until the countdown elapses or is cancelled. Returns true if it elapsed.
+    ///
+    /// When the ticker is not enabled, this makes unforced draws while waiting, so the bar is still animated.
+    /// After that the bar stops counting, and could be used as a normal bar again.
+    pub fn wait(&self) -> bool {
+        let (lock, cvar) = &*self.cancelled;
+        let elapsed = loop {
+            let now = clock::now();
+            if now >= self.deadline {
+                break true;
+            }
+            let interval = match self.manager.upgrade() {
+                Some(manager) => manager.interval(),
+                None => self.deadline - now,
+            };
+            let cancelled = lock.lock_unpoisoned();
+            let (cancelled, _) = cvar
+                .wait_timeout_while(cancelled, interval.min(self.deadline - now), |c| !*c)
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            if *cancelled {
+                break false;
+            }
+            std::mem::drop(cancelled);
+            if let Some(manager) = self.manager.upgrade() {
+                manager.draw(false);
+            }
+        };
+        self.stop(elapsed);
+        elapsed
+    }
+
+    /// Wait for the countdown in a new thread, and call `callback` with the result of `wait`.
+    pub fn on_finish<F: FnOnce(bool) + Send + 'static>(&self, callback: F) {
+        let countdown = self.clone();
+        std::thread::spawn(move || callback(countdown.wait()));
+    }
+
+    fn stop(&self, elapsed: bool) {
+        let Some(manager) = self.manager.upgrade() else {
+            return;
+        };
+        if elapsed {
+            // Show the final state before stopping the timer
+            manager.draw(true);
+        }
+        let Some(state) = manager.states.lock_unpoisoned().get(&self.id).cloned() else {
+            return;
+        };
+        let mut state = state.lock_unpoisoned();
+        // A newer countdown on the bar is not ours to stop
+        if state.countdown == self.generation && matches!(state.source, Some(PosSource::Elapsed)) {
+            state.source = None;
+            state.reverse = false;
+        }
+    }
+}
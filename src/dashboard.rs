@@ -0,0 +1,190 @@
+//! The module contains `Dashboard`, an HTTP server showing progress of a `Manager` in a browser,
+//! with the `http_dashboard` feature.
+//!
+//! Long jobs without a terminal (like on a server) could be monitored with `Manager::serve_http`:
+//!
+//! - `GET /`: A page of visible bars, refreshed every second.
+//! - `GET /snapshot.json`: Visible bars as JSON, like a line of `backend::Json`.
+//!
+//! The server is plain HTTP/1.1 without authentication, closing each connection after the response,
+//! so it should listen on a loopback or otherwise trusted address.
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Weak,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::{backend::json_snapshot, gui, LockUnpoisoned, ManagerInner};
+
+/// How often the listener checks whether it shall stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// The most bytes read for the request line and headers of a connection.
+const MAX_REQUEST: u64 = 8 * 1024;
+
+/// A handle for the server started by `Manager::serve_http`.
+///
+/// When dropped, the server stops listening.
+pub struct Dashboard {
+    addr: SocketAddr,
+    stopped: Arc<AtomicBool>,
+    // thread join requires ownership of the thread, so an Option is used to take it out
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Dashboard {
+    pub(crate) fn new(
+        manager: Weak<ManagerInner>,
+        addr: impl ToSocketAddrs,
+    ) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let stopped = Arc::new(AtomicBool::new(false));
+        let stopped2 = stopped.clone();
+        let thread = thread::spawn(move || {
+            while !stopped2.load(Ordering::Acquire) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        // A thread for each connection, so a slow client doesn't block others
+                        let manager = manager.clone();
+                        thread::spawn(move || {
+                            let _ = handle_connection(&manager, stream);
+                        });
+                    }
+                    Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                        thread::sleep(POLL_INTERVAL);
+                    }
+                    Err(_) => break,
+                }
+                if manager.strong_count() == 0 {
+                    break;
+                }
+            }
+        });
+        Ok(Dashboard {
+            addr,
+            stopped,
+            thread: Some(thread),
+        })
+    }
+
+    /// The address listened on, like for the port chosen when binding port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::Release);
+        if let Some(t) = self.thread.take() {
+            let _ = t.join();
+        }
+    }
+}
+
+fn handle_connection(manager: &Weak<ManagerInner>, stream: TcpStream) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream.take(MAX_REQUEST));
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // Headers are not used, but shall end within the limit
+    let mut header = request.clone();
+    while header.ends_with('\n') && !header.trim_end().is_empty() {
+        header.clear();
+        reader.read_line(&mut header)?;
+    }
+    let too_large = reader.get_ref().limit() == 0 && !header.ends_with('\n');
+    let mut parts = request.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let (status, content_type, body) = match (method, path, manager.upgrade()) {
+        _ if too_large => (
+            "431 Request Header Fields Too Large",
+            "text/plain",
+            "request too large\n".to_string(),
+        ),
+        (_, _, None) => (
+            "503 Service Unavailable",
+            "text/plain",
+            "manager is dropped\n".to_string(),
+        ),
+        ("GET", "/", Some(manager)) => ("200 OK", "text/html; charset=utf-8", page(&manager)),
+        ("GET", "/snapshot.json", Some(manager)) => ("200 OK", "application/json", json(&manager)),
+        ("GET", _, _) => ("404 Not Found", "text/plain", "not found\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "method not allowed\n".to_string(),
+        ),
+    };
+    write!(
+        writer,
+        concat!(
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\n",
+            "Cache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+        ),
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    writer.flush()
+}
+
+fn json(manager: &ManagerInner) -> String {
+    let states = manager.states.lock_unpoisoned();
+    let bars: Vec<_> = states
+        .values()
+        .map(|state| state.lock_unpoisoned())
+        .filter(|state| state.visible)
+        .map(|state| state.snapshot())
+        .collect();
+    json_snapshot(&bars)
+}
+
+fn page(manager: &ManagerInner) -> String {
+    let snapshot = {
+        let states = manager.states.lock_unpoisoned();
+        gui::snapshot(&states, &manager.plain_options(&states))
+    };
+    let mut page = String::from(concat!(
+        "<!DOCTYPE html>\n",
+        "<html><head><meta charset=\"utf-8\"><meta http-equiv=\"refresh\" content=\"1\">",
+        "<title>Progress</title></head><body>\n",
+    ));
+    for bar in &snapshot.bars {
+        // Without a value, a progress element shows a busy indicator
+        let value = bar
+            .fraction
+            .map_or(String::new(), |fraction| format!(" value=\"{}\"", fraction));
+        page.push_str(&format!(
+            "<div><progress{}></progress> <code>{}</code></div>\n",
+            value,
+            escape(&bar.text)
+        ));
+    }
+    page.push_str("</body></html>\n");
+    page
+}
+
+fn escape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            c => result.push(c),
+        }
+    }
+    result
+}
@@ -0,0 +1,30 @@
+//! The module contains encoders for `Manager::set_encoder`, for terminals not in UTF-8,
+//! like legacy code pages of Windows consoles.
+//!
+//! Characters not representable are replaced by `?` as many as their widths, so lines keep their widths
+//! and clearing the last frame in ANSI mode is not affected.
+//!
+//! Other encodings could be supported by a custom encoder, like with the `encoding_rs` crate.
+
+use crate::char_width;
+
+/// ASCII only.
+pub fn ascii(frame: &str) -> Vec<u8> {
+    encode(frame, |c| u8::try_from(c).ok().filter(u8::is_ascii))
+}
+
+/// ISO-8859-1 (Latin-1), whose characters are the first 256 ones of Unicode.
+pub fn latin1(frame: &str) -> Vec<u8> {
+    encode(frame, |c| u8::try_from(c).ok())
+}
+
+fn encode(frame: &str, byte: impl Fn(char) -> Option<u8>) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(frame.len());
+    for c in frame.chars() {
+        match byte(c) {
+            Some(b) => bytes.push(b),
+            None => bytes.extend(std::iter::repeat(b'?').take(char_width(c))),
+        }
+    }
+    bytes
+}
@@ -0,0 +1,130 @@
+//! The module contains `EtaStrategy`, the ways to estimate `{eta}` set by `Bar::set_eta_strategy`.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// A custom ETA function, called with the position, the length and the elapsed time.
+pub type EtaFn = dyn Fn(u64, u64, Duration) -> Option<Duration> + Send + Sync;
+
+/// How the remaining time (`{eta}` and `Bar::eta`) is estimated.
+#[derive(Default)]
+pub enum EtaStrategy {
+    /// By the average speed since the bar is created. This is the default.
+    #[default]
+    Linear,
+    /// By the speed in the recent duration, which follows the changes of speed.
+    RecentWindow(Duration),
+    /// By the speed smoothed with a scalar Kalman filter, which follows the changes of speed but is less noisy.
+    Kalman,
+    /// By a custom function, returning `None` when unknown.
+    Custom(Box<EtaFn>),
+}
+
+impl std::fmt::Debug for EtaStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EtaStrategy::Linear => write!(f, "Linear"),
+            EtaStrategy::RecentWindow(window) => {
+                f.debug_tuple("RecentWindow").field(window).finish()
+            }
+            EtaStrategy::Kalman => write!(f, "Kalman"),
+            EtaStrategy::Custom(_) => write!(f, "Custom(..)"),
+        }
+    }
+}
+
+/// Speeds are measured by the Kalman filter no more often than this, as positions updated too closely are noisy.
+const KALMAN_MIN_INTERVAL: Duration = Duration::from_millis(100);
+/// Relative variance of the change of speed between measurements.
+const KALMAN_PROCESS_NOISE: f64 = 0.05;
+/// Relative variance of a measured speed.
+const KALMAN_MEASUREMENT_NOISE: f64 = 0.5;
+
+/// An `EtaStrategy` with the samples it needs.
+#[derive(Default)]
+pub(crate) struct EtaEstimator {
+    strategy: EtaStrategy,
+    /// Positions in the window of `RecentWindow`, with the one just before the window.
+    samples: VecDeque<(Instant, u64)>,
+    /// The last measurement of `Kalman`.
+    last_sample: Option<(Instant, u64)>,
+    /// The speed estimated by `Kalman`, and its relative variance.
+    kalman: Option<(f64, f64)>,
+}
+
+impl EtaEstimator {
+    pub(crate) fn new(strategy: EtaStrategy) -> Self {
+        EtaEstimator {
+            strategy,
+            ..Default::default()
+        }
+    }
+
+    /// Record the position, when it's changed.
+    pub(crate) fn record(&mut self, now: Instant, pos: u64) {
+        match self.strategy {
+            EtaStrategy::RecentWindow(window) => {
+                self.samples.push_back((now, pos));
+                // Keep one sample before the window, to measure the speed of the whole window
+                while self.samples.len() > 2 && now - self.samples[1].0 > window {
+                    self.samples.pop_front();
+                }
+            }
+            EtaStrategy::Kalman => {
+                let Some((last_time, last_pos)) = self.last_sample else {
+                    self.last_sample = Some((now, pos));
+                    return;
+                };
+                let dt = now - last_time;
+                if dt < KALMAN_MIN_INTERVAL {
+                    return;
+                }
+                let measured = pos.saturating_sub(last_pos) as f64 / dt.as_secs_f64();
+                self.kalman = Some(match self.kalman {
+                    None => (measured, KALMAN_MEASUREMENT_NOISE),
+                    Some((speed, variance)) => {
+                        let variance = variance + KALMAN_PROCESS_NOISE;
+                        let gain = variance / (variance + KALMAN_MEASUREMENT_NOISE);
+                        (speed + gain * (measured - speed), variance * (1.0 - gain))
+                    }
+                });
+                self.last_sample = Some((now, pos));
+            }
+            EtaStrategy::Linear | EtaStrategy::Custom(_) => {}
+        }
+    }
+
+    /// Estimate the remaining time, or `None` when unknown.
+    pub(crate) fn estimate(&self, pos: u64, len: u64, elapsed: Duration) -> Option<Duration> {
+        let remaining = len.saturating_sub(pos) as f64;
+        let speed = match &self.strategy {
+            EtaStrategy::Custom(f) => return f(pos, len, elapsed),
+            EtaStrategy::Linear => {
+                if pos == 0 {
+                    return None;
+                }
+                pos as f64 / elapsed.as_secs_f64()
+            }
+            EtaStrategy::RecentWindow(_) => {
+                let (first_time, first_pos) = *self.samples.front()?;
+                let dt = crate::clock::elapsed(first_time);
+                pos.saturating_sub(first_pos) as f64 / dt.as_secs_f64()
+            }
+            EtaStrategy::Kalman => match self.kalman {
+                Some((speed, _)) => speed,
+                // Not measured yet
+                None if pos == 0 => return None,
+                None => pos as f64 / elapsed.as_secs_f64(),
+            },
+        };
+        if remaining == 0.0 {
+            return Some(Duration::ZERO);
+        }
+        if speed.is_nan() || speed <= 0.0 {
+            return None;
+        }
+        Some(Duration::try_from_secs_f64(remaining / speed).unwrap_or(Duration::MAX))
+    }
+}
@@ -0,0 +1,358 @@
+//! The module contains `Update`, plain messages to drive bars from other threads and languages,
+//! sent through `Manager::updater_channel`, and C functions of the `ffi` feature.
+//!
+//! Updates are queued, and applied to bars before the next draw, by the ticker thread with `Manager::set_ticker`.
+//! Bars are addressed by `Bar::id`:
+//!
+//! ```
+//! use kyuri::ffi::{Update, UpdateKind};
+//!
+//! let manager = kyuri::Manager::new(std::time::Duration::from_secs(1));
+//! let bar = manager.create_bar(100, "Encoding", "{msg}: {pos}/{len}", true);
+//! let updater = manager.updater_channel();
+//! let id = bar.id();
+//! std::thread::spawn(move || {
+//!     updater.send(Update { id, kind: UpdateKind::Inc, value: 10 }).unwrap();
+//! })
+//! .join()
+//! .unwrap();
+//! manager.draw(true);
+//! assert_eq!(bar.get_pos(), 10);
+//! ```
+//!
+//! With the `ffi` feature, C callers could send updates with these functions, after a `Sender` is handed over
+//! with `updater_into_raw`:
+//!
+//! ```c
+//! typedef struct KyuriUpdater KyuriUpdater;
+//! bool kyuri_updater_send(const KyuriUpdater *updater, size_t id, uint32_t kind, uint64_t value);
+//! KyuriUpdater *kyuri_updater_clone(const KyuriUpdater *updater);
+//! void kyuri_updater_free(KyuriUpdater *updater);
+//! ```
+//!
+//! And take snapshots of bars to render in their own UI, after a manager is handed over with `manager_into_raw`:
+//!
+//! ```c
+//! typedef struct KyuriManager KyuriManager;
+//! typedef struct {
+//!     size_t id;
+//!     uint64_t pos;
+//!     uint64_t len;
+//!     double elapsed;     // In seconds
+//!     uint32_t status;    // 0: new, 1: in progress, 2: finished, 3: overflowed, 4: failed
+//!     bool visible;
+//!     const char *message;
+//!     const char *text;   // The template rendered without colors
+//! } KyuriBarSnapshot;
+//! typedef struct {
+//!     const KyuriBarSnapshot *bars;
+//!     size_t len;
+//! } KyuriSnapshot;
+//! KyuriSnapshot *kyuri_manager_snapshot(const KyuriManager *manager);
+//! void kyuri_snapshot_free(KyuriSnapshot *snapshot);
+//! void kyuri_manager_free(KyuriManager *manager);
+//! ```
+
+#[cfg(feature = "ffi")]
+use std::{
+    ffi::{c_char, CString},
+    sync::Weak,
+};
+
+#[cfg(feature = "ffi")]
+use crate::{style::strip_ansi, BarStatus, Manager};
+use crate::{BarState, LockUnpoisoned, ManagerInner};
+
+/// A change to a bar, like a call of the method of the same name on `Bar`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Update {
+    /// `Bar::id` of the bar. Updates of bars dropped are ignored.
+    pub id: usize,
+    /// The change.
+    pub kind: UpdateKind,
+    /// The argument of the change, with 0 as false and others as true for `Fail` and `SetVisible`.
+    pub value: u64,
+}
+
+/// The kind of `Update`, with the values of `kind` in C.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateKind {
+    /// `Bar::inc`.
+    Inc = 0,
+    /// `Bar::set_pos`.
+    SetPos = 1,
+    /// `Bar::set_len`.
+    SetLen = 2,
+    /// `Bar::finish`, ignoring the value.
+    Finish = 3,
+    /// `Bar::fail`.
+    Fail = 4,
+    /// `Bar::set_visible`.
+    SetVisible = 5,
+}
+
+impl UpdateKind {
+    /// The kind of the value in C, or `None` when unknown.
+    pub fn from_raw(kind: u32) -> Option<Self> {
+        Some(match kind {
+            0 => UpdateKind::Inc,
+            1 => UpdateKind::SetPos,
+            2 => UpdateKind::SetLen,
+            3 => UpdateKind::Finish,
+            4 => UpdateKind::Fail,
+            5 => UpdateKind::SetVisible,
+            _ => return None,
+        })
+    }
+}
+
+impl Update {
+    /// Apply to the bar, without drawing.
+    fn apply(self, state: &mut BarState) {
+        match self.kind {
+            UpdateKind::Inc | UpdateKind::SetPos | UpdateKind::Finish => {
+                let pos = match self.kind {
+                    UpdateKind::Inc => state.pos.saturating_add(self.value),
+                    UpdateKind::SetPos => self.value,
+                    _ => state.len,
+                };
+                state.update_pos(pos);
+                state.materialize();
+                state.mark_changed(true);
+            }
+            UpdateKind::SetLen => {
+                state.len = self.value;
+                state.update_finished();
+                state.mark_changed(true);
+            }
+            UpdateKind::Fail => {
+                state.failed = self.value != 0;
+                state.notify_finished();
+                state.mark_changed(false);
+            }
+            UpdateKind::SetVisible => {
+                state.lazy = false;
+                state.visible = self.value != 0;
+                state.need_redraw = true;
+            }
+        }
+    }
+}
+
+/// Apply updates queued by senders of `Manager::updater_channel`.
+pub(crate) fn apply_updates(manager: &ManagerInner) {
+    let updater = manager.updater.lock_unpoisoned();
+    let Some((_, receiver)) = updater.as_ref() else {
+        return;
+    };
+    for update in receiver.try_iter() {
+        let Some(state) = manager.states.lock_unpoisoned().get(&update.id).cloned() else {
+            continue;
+        };
+        update.apply(&mut state.lock_unpoisoned());
+        manager.mark_redraw();
+    }
+}
+
+/// A `Sender` of updates owned by C callers.
+#[cfg(feature = "ffi")]
+pub struct KyuriUpdater(std::sync::mpsc::Sender<Update>);
+
+/// Hand `sender` over to C callers, who free it with `kyuri_updater_free`.
+#[cfg(feature = "ffi")]
+pub fn updater_into_raw(sender: std::sync::mpsc::Sender<Update>) -> *mut KyuriUpdater {
+    Box::into_raw(Box::new(KyuriUpdater(sender)))
+}
+
+/// Queue an update. Returns false when `kind` is unknown or the manager is dropped.
+///
+/// # Safety
+///
+/// `updater` must be from `updater_into_raw` or `kyuri_updater_clone`, not freed, and not used by other threads
+/// at the same time: use `kyuri_updater_clone` for each thread.
+#[cfg(feature = "ffi")]
+#[no_mangle]
+pub unsafe extern "C" fn kyuri_updater_send(
+    updater: *const KyuriUpdater,
+    id: usize,
+    kind: u32,
+    value: u64,
+) -> bool {
+    let (Some(updater), Some(kind)) = (updater.as_ref(), UpdateKind::from_raw(kind)) else {
+        return false;
+    };
+    updater.0.send(Update { id, kind, value }).is_ok()
+}
+
+/// Clone the updater, like for another thread.
+///
+/// # Safety
+///
+/// `updater` must be from `updater_into_raw` or `kyuri_updater_clone`, and not freed.
+#[cfg(feature = "ffi")]
+#[no_mangle]
+pub unsafe extern "C" fn kyuri_updater_clone(updater: *const KyuriUpdater) -> *mut KyuriUpdater {
+    match updater.as_ref() {
+        Some(updater) => updater_into_raw(updater.0.clone()),
+        None => std::ptr::null_mut(),
+    }
+}
+
+/// Free the updater. Null is ignored.
+///
+/// # Safety
+///
+/// `updater` must be from `updater_into_raw` or `kyuri_updater_clone`, and not freed before.
+#[cfg(feature = "ffi")]
+#[no_mangle]
+pub unsafe extern "C" fn kyuri_updater_free(updater: *mut KyuriUpdater) {
+    if !updater.is_null() {
+        drop(Box::from_raw(updater));
+    }
+}
+
+/// A manager referenced by C callers, who free it with `kyuri_manager_free`.
+///
+/// The manager is not kept alive by the reference: snapshots of a dropped manager have no bars.
+#[cfg(feature = "ffi")]
+pub struct KyuriManager(Weak<ManagerInner>);
+
+/// Hand a reference of `manager` over to C callers, who free it with `kyuri_manager_free`.
+#[cfg(feature = "ffi")]
+pub fn manager_into_raw(manager: &Manager) -> *mut KyuriManager {
+    Box::into_raw(Box::new(KyuriManager(std::sync::Arc::downgrade(
+        &manager.inner,
+    ))))
+}
+
+/// A bar in `KyuriSnapshot`. Strings are valid until the snapshot is freed.
+#[cfg(feature = "ffi")]
+#[repr(C)]
+#[derive(Debug)]
+pub struct KyuriBarSnapshot {
+    /// `Bar::id`.
+    pub id: usize,
+    /// The position.
+    pub pos: u64,
+    /// The total length.
+    pub len: u64,
+    /// The elapsed time in seconds, like `{elapsed}`.
+    pub elapsed: f64,
+    /// `BarStatus` as 0 for `New`, 1 for `InProgress`, 2 for `Finished`, 3 for `Overflowed` and 4 for `Failed`.
+    pub status: u32,
+    /// Whether the bar is visible.
+    pub visible: bool,
+    /// The message, in UTF-8 and ending with NUL.
+    pub message: *const c_char,
+    /// The template rendered without colors, in UTF-8 and ending with NUL.
+    pub text: *const c_char,
+}
+
+/// All bars of a manager in the order of creation, returned by `kyuri_manager_snapshot`.
+#[cfg(feature = "ffi")]
+#[repr(C)]
+#[derive(Debug)]
+pub struct KyuriSnapshot {
+    /// The first bar.
+    pub bars: *const KyuriBarSnapshot,
+    /// The number of bars.
+    pub len: usize,
+}
+
+/// `KyuriSnapshot` and what its pointers point to, freed together.
+#[cfg(feature = "ffi")]
+#[repr(C)]
+struct OwnedSnapshot {
+    // The first field, so a pointer to this is also one to the snapshot
+    snapshot: KyuriSnapshot,
+    bars: Vec<KyuriBarSnapshot>,
+    strings: Vec<CString>,
+}
+
+#[cfg(feature = "ffi")]
+fn c_string(s: &str) -> CString {
+    CString::new(s.replace('\0', "")).expect("NUL removed")
+}
+
+/// Take a snapshot of all bars, to be freed with `kyuri_snapshot_free`. Returns null when `manager` is null.
+///
+/// # Safety
+///
+/// `manager` must be from `manager_into_raw`, and not freed.
+#[cfg(feature = "ffi")]
+#[no_mangle]
+pub unsafe extern "C" fn kyuri_manager_snapshot(
+    manager: *const KyuriManager,
+) -> *mut KyuriSnapshot {
+    let Some(manager) = manager.as_ref() else {
+        return std::ptr::null_mut();
+    };
+    let mut owned = Box::new(OwnedSnapshot {
+        snapshot: KyuriSnapshot {
+            bars: std::ptr::null(),
+            len: 0,
+        },
+        bars: Vec::new(),
+        strings: Vec::new(),
+    });
+    if let Some(manager) = manager.0.upgrade() {
+        let states = manager.states.lock_unpoisoned();
+        let options = manager.plain_options(&states);
+        for (&id, state) in states.iter() {
+            let state = state.lock_unpoisoned();
+            // Moving a CString does not move its buffer, so pointers stay valid
+            let message = c_string(&state.message);
+            let text = c_string(&strip_ansi(&state.render(&options)));
+            owned.bars.push(KyuriBarSnapshot {
+                id,
+                pos: state.pos,
+                len: state.len,
+                elapsed: state.elapsed().as_secs_f64(),
+                status: match state.status() {
+                    BarStatus::New => 0,
+                    BarStatus::InProgress => 1,
+                    BarStatus::Finished => 2,
+                    BarStatus::Overflowed => 3,
+                    BarStatus::Failed => 4,
+                },
+                visible: state.visible,
+                message: message.as_ptr(),
+                text: text.as_ptr(),
+            });
+            owned.strings.extend([message, text]);
+        }
+    }
+    owned.snapshot = KyuriSnapshot {
+        bars: owned.bars.as_ptr(),
+        len: owned.bars.len(),
+    };
+    Box::into_raw(owned).cast()
+}
+
+/// Free the snapshot. Null is ignored.
+///
+/// # Safety
+///
+/// `snapshot` must be from `kyuri_manager_snapshot`, and not freed before.
+#[cfg(feature = "ffi")]
+#[no_mangle]
+pub unsafe extern "C" fn kyuri_snapshot_free(snapshot: *mut KyuriSnapshot) {
+    if !snapshot.is_null() {
+        drop(Box::from_raw(snapshot.cast::<OwnedSnapshot>()));
+    }
+}
+
+/// Free the reference of the manager. Null is ignored.
+///
+/// # Safety
+///
+/// `manager` must be from `manager_into_raw`, and not freed before.
+#[cfg(feature = "ffi")]
+#[no_mangle]
+pub unsafe extern "C" fn kyuri_manager_free(manager: *mut KyuriManager) {
+    if !manager.is_null() {
+        drop(Box::from_raw(manager));
+    }
+}
@@ -0,0 +1,106 @@
+//! The module contains `ManagerSnapshot`, bars of a manager as plain values for GUI progress widgets.
+//!
+//! A desktop frontend (like with [egui](https://github.com/emilk/egui)) could mirror the progress shown in the
+//! terminal: take a snapshot with `Manager::gui_snapshot` when painting, and repaint when `Manager::subscribe`
+//! notifies a new frame:
+//!
+//! ```
+//! let manager = kyuri::Manager::new(std::time::Duration::from_secs(1));
+//! let changes = manager.subscribe();
+//! let bar = manager.create_bar(100, "Download", "{msg}: {pos}/{len}", true);
+//! bar.set_pos(30);
+//! manager.draw(true);
+//!
+//! // In the GUI thread, when notified
+//! changes.recv().unwrap();
+//! for bar in manager.gui_snapshot().bars {
+//!     // Like `egui::ProgressBar::new(fraction).text(&bar.percent_text)`
+//!     let fraction = bar.fraction.unwrap_or(0.0);
+//!     assert_eq!((fraction, bar.percent_text.as_str()), (0.3, "30%"));
+//! }
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    duration_to_human, style::strip_ansi, BarState, BarStatus, LockUnpoisoned, RenderOptions,
+};
+
+/// Visible bars of a manager at one moment, returned by `Manager::gui_snapshot`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ManagerSnapshot {
+    /// Visible bars in the order of creation.
+    pub bars: Vec<GuiBar>,
+    /// The progress of all visible bars with a length, from 0 to 1, or `None` when there are no such bars.
+    pub fraction: Option<f32>,
+}
+
+/// A bar as shown by GUI progress widgets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GuiBar {
+    /// Unique among bars of the manager, for keying widgets.
+    pub id: usize,
+    /// The message.
+    pub message: String,
+    /// The progress from 0 to 1, or `None` when the length is 0, for a busy indicator instead.
+    pub fraction: Option<f32>,
+    /// The progress in percent, like `30%`, or empty when the length is 0.
+    pub percent_text: String,
+    /// The position and length, like `30/100`.
+    pub progress_text: String,
+    /// The elapsed time, like `0:01:05`.
+    pub elapsed_text: String,
+    /// The estimated remaining time in the same format, or `None` when the position is 0.
+    pub eta_text: Option<String>,
+    /// The template rendered without colors, like the line in the terminal.
+    pub text: String,
+    /// The state of the bar.
+    pub status: BarStatus,
+}
+
+impl GuiBar {
+    fn new(id: usize, state: &BarState, options: &RenderOptions) -> Self {
+        let fraction = fraction(state.pos, state.len);
+        GuiBar {
+            id,
+            message: state.message.clone(),
+            fraction,
+            percent_text: fraction.map_or(String::new(), |fraction| {
+                format!("{}%", (fraction * 100.0).floor())
+            }),
+            progress_text: format!("{}/{}", state.pos, state.len),
+            elapsed_text: duration_to_human(state.elapsed()),
+            eta_text: state.eta().map(duration_to_human),
+            text: strip_ansi(&state.render(options)).into_owned(),
+            status: state.status(),
+        }
+    }
+}
+
+fn fraction(pos: u64, len: u64) -> Option<f32> {
+    (len > 0).then(|| (pos.min(len) as f64 / len as f64) as f32)
+}
+
+pub(crate) fn snapshot(
+    states: &BTreeMap<usize, Arc<Mutex<BarState>>>,
+    options: &RenderOptions,
+) -> ManagerSnapshot {
+    let (mut pos, mut len) = (0u64, 0u64);
+    let mut bars = Vec::new();
+    for (&id, state) in states {
+        let state = state.lock_unpoisoned();
+        if !state.visible {
+            continue;
+        }
+        pos = pos.saturating_add(state.pos.min(state.len));
+        len = len.saturating_add(state.len);
+        bars.push(GuiBar::new(id, &state, options));
+    }
+    ManagerSnapshot {
+        bars,
+        fraction: fraction(pos, len),
+    }
+}
@@ -0,0 +1,16 @@
+//! The module contains helpers for integrating with other libraries.
+//!
+//! Each of them is behind a feature with the same name as the library, and the module is only
+//! available with one of them (currently `env_logger`).
+
+/// Create an `env_logger::Target` writing through a `KyuriWriter`, so that log lines do not break progress bars.
+///
+/// ```no_run
+/// let manager = kyuri::Manager::new(std::time::Duration::from_secs(1));
+/// env_logger::Builder::from_default_env()
+///     .target(kyuri::integrations::env_logger_target(&manager))
+///     .init();
+/// ```
+pub fn env_logger_target(manager: &crate::Manager) -> env_logger::Target {
+    env_logger::Target::Pipe(Box::new(manager.create_writer()))
+}
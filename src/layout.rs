@@ -0,0 +1,100 @@
+//! The module contains `Column`, for placing bars side by side with `Manager::set_split`.
+//!
+//! With the split layout, bars in the left column and bars in the right column are drawn in rows next to each other,
+//! like downloads on the left and uploads on the right:
+//!
+//! ```text
+//! a.iso: [=====     ] (50/100) | c.tar: [=         ] (1/10)
+//! b.iso: [==        ] (20/100) |
+//! ```
+//!
+//! When both columns do not fit in the terminal, the narrower one keeps its width if it fits in half of the terminal,
+//! and the other one is truncated, so no line wraps.
+
+use crate::{char_width, string_width};
+
+/// Between the left and the right column.
+const SEPARATOR: &str = " | ";
+
+/// The column of a bar in the split layout, set by `Bar::set_column`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Column {
+    /// The default.
+    #[default]
+    Left,
+    /// Drawn in order with other bars when the split layout is off.
+    Right,
+}
+
+/// Lay out lines of both columns in rows, each ending with `\n` and not wider than `term_col`.
+pub(crate) fn split(left: &[String], right: &[String], term_col: usize) -> String {
+    let mut result = String::new();
+    let available = term_col.saturating_sub(SEPARATOR.len());
+    if right.is_empty() || available < 2 {
+        // Nothing to split, or no room to do so
+        for line in left.iter().chain(right) {
+            result.push_str(line);
+            result.push('\n');
+        }
+        return result;
+    }
+    let widest = |lines: &[String]| lines.iter().map(|l| string_width(l)).max().unwrap_or(0);
+    let (left_width, right_width) = (widest(left), widest(right));
+    let half = available / 2;
+    let left_width = if left_width + right_width <= available || left_width <= half {
+        left_width
+    } else if right_width <= available - half {
+        available - right_width
+    } else {
+        half
+    };
+    let right_width = available - left_width;
+    for row in 0..left.len().max(right.len()) {
+        match right.get(row) {
+            Some(line) => {
+                let left_line = left.get(row).map_or("", String::as_str);
+                result.push_str(&fit(left_line, left_width));
+                result.push_str(SEPARATOR);
+                result.push_str(fit(line, right_width).trim_end_matches(' '));
+            }
+            None => result.push_str(&fit(&left[row], left_width)),
+        }
+        result.push('\n');
+    }
+    result
+}
+
+/// Truncate `line` to `width` columns and pad it with spaces to that width, keeping escape sequences.
+fn fit(line: &str, width: usize) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut used = 0;
+    let mut truncated = false;
+    let mut chars = line.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch == '\x1b' && chars.peek() == Some(&'[') {
+            // Escape sequences take no width, like in `strip_ansi`
+            result.push(ch);
+            result.extend(chars.next());
+            for ch in chars.by_ref() {
+                result.push(ch);
+                if ('\x40'..='\x7e').contains(&ch) {
+                    break;
+                }
+            }
+            continue;
+        }
+        let w = char_width(ch);
+        if used + w > width {
+            truncated = true;
+            break;
+        }
+        result.push(ch);
+        used += w;
+    }
+    if truncated && line.contains('\x1b') {
+        // Styles of the truncated part should not leak into the other column
+        result.push_str("\x1b[0m");
+    }
+    result.extend(std::iter::repeat(' ').take(width - used));
+    result
+}
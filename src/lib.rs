@@ -6,7 +6,7 @@
 //! - The `Manager` (like `MultiProgress` in indicatif) manages all progress bar management and rendering.
 //! - Friendly to writing to files.
 //! - Predictable about when it would draw.
-//! - Custom integrations with other libraries (an example: examples/tracing.rs)
+//! - Custom integrations with other libraries (an example: examples/tracing.rs, and helpers in `integrations`)
 //!
 //! ## Examples
 //!
@@ -30,36 +30,140 @@
 //!
 //! Tags in template looks like `{something}`. Supported tags:
 //! - `{msg}`, `{message}`: The message of the bar.
-//! - `{elapsed}`, `{elapsed_precise}`: The elapsed time (H:MM:SS).
+//! - `{field:NAME}`: The field `NAME` of the bar set by `Bar::set_field`, empty if not set.
+//! - `{elapsed}`, `{elapsed_precise}`: The elapsed time (H:MM:SS). It stops when the bar is finished.
 //! - `{bytes}`: The current position in bytes (power-of-two, `KiB`, `MiB`, ...).
 //! - `{pos}`: The current position.
 //! - `{total_bytes}`: The total length in bytes (power-of-two, `KiB`, `MiB`, ...).
 //! - `{total}`, `{len}`: The total length.
 //! - `{bytes_per_sec}`, `{bytes_per_second}`: The current speed in bytes per second.
-//! - `{eta}`: The estimated time of arrival (H:MM:SS).
-//! - `{bar}`, `{barNUM}`: The progress bar. The `NUM` is the size of the bar, default is 20.
+//! - `{bytes:unit=UNIT}`, `{total_bytes:unit=UNIT}`, `{bytes_per_sec:unit=UNIT}`: Like above, but always in `UNIT`
+//!   (`B`, `KiB`, `MiB`, `GiB`, `TiB`, `PiB` or `EiB`), so that columns don't jump between units as transfers progress.
+//!   `{mib_pos}` and `{mib_total}` are short for `{bytes:unit=MiB}` and `{total_bytes:unit=MiB}`, likewise for
+//!   `kib_`, `gib_`, `tib_`, `pib_` and `eib_`.
+//! - `{bytes:.N}`, `{mib_pos:.N}`, `{bytes_per_sec:unit=MiB:.N}` and so on: Any tag in bytes (including `{all_bytes}`
+//!   and others below) with `N` decimals, from 0 to 9. The default is 2, and could be changed with
+//!   `Manager::set_byte_precision`.
+//! - `{eta}`: The estimated time of arrival (H:MM:SS). How it is estimated could be changed with `Bar::set_eta_strategy`.
+//! - `{bar}`, `{barNUM}`: The progress bar. The `NUM` is the size of the bar, default is 20, and at most `MAX_BAR_WIDTH`.
+//!   When pos > len (overflowed), the part beyond len is drawn with overflowed cells (`!` by default) instead of the end of the bar,
+//!   up to another `NUM` cells, like `[==========!!!!!` for `{bar10}` with pos = 15 and len = 10.
+//! - `{thread}`: The name of the thread which created the bar or last called `inc`/`set_pos` on it, or its id if unnamed.
+//! - `{thread_id}`: The number of the thread above, assigned in the order threads first use bars.
+//! - `{all_bytes}`, `{all_total_bytes}`, `{all_bytes_per_sec}`: The sums of `{bytes}`, `{total_bytes}` and
+//!   `{bytes_per_sec}` of all bars (only bars in progress for the speed), for a "total" bar or footer.
+//!   A bar with len = 0 adds nothing, so it could be used as the footer.
+//! - `{active_bars}`: The number of bars in progress (pos < len, and not failed).
 //! - `{state_emoji}`: The state emoji of the bar. ✅ for finished, 🆕 for new, 💥 for overflowed, ⏳ for in progress.
+//! - `{spinner}`: A spinner advancing with time, which stops when the bar is finished. Frames could be changed
+//!   with `Theme::spinner_frames` or `Bar::set_spinner_frames`.
+//! - `{bell}`: The terminal bell (`\a`), only in the first frame after the bar is finished, and only in ANSI mode.
+//!
+//! The style of `{bar}` and icons of `{state_emoji}` could be changed with `Manager::with_theme`.
+//!
+//! Style tags like `{red}`, `{bold}`, `{color:208}`, `{#ff8800}` and `{reset}` are also supported. See `style` for details.
+//!
+//! Bars in the same group (`Bar::set_align_group`) pad `{msg}` and right-align numbers to line up with each other.
 //!
 //! Doubled `{` and `}` would not be interpreted as tags.
+//!
+//! Templates are parsed leniently by default (unknown tags are kept as text). Use `Template::parse` to parse strictly,
+//! and `Template::from_env` or `Manager::with_templates_from` to let end users customize templates.
+//! Templates known at compile time could be checked by the build with `template!`.
 
 #![warn(missing_docs)]
 
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, HashMap, VecDeque},
     sync::{
-        atomic::{AtomicBool, AtomicUsize},
+        atomic::{AtomicBool, AtomicU64, AtomicUsize},
         Arc, Mutex, Weak,
     },
 };
 
+/// Lock a mutex, recovering the guard when it's poisoned.
+///
+/// A panic in a thread holding a lock (like in a source or callback) should not cascade panics
+/// through every other thread using the manager, and states are still valid after such panics.
+pub(crate) trait LockUnpoisoned<T: ?Sized> {
+    fn lock_unpoisoned(&self) -> std::sync::MutexGuard<'_, T>;
+}
+
+impl<T: ?Sized> LockUnpoisoned<T> for Mutex<T> {
+    fn lock_unpoisoned(&self) -> std::sync::MutexGuard<'_, T> {
+        self.lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+mod accessible;
+pub mod backend;
+mod burst;
+pub mod checklist;
+pub mod ci;
+mod clock;
+#[cfg(all(unix, feature = "control_socket"))]
+pub mod control;
+pub mod countdown;
+#[cfg(feature = "http_dashboard")]
+pub mod dashboard;
+pub mod encoding;
+pub mod eta;
+pub mod ffi;
+pub mod gui;
+#[cfg(feature = "env_logger")]
+pub mod integrations;
+pub mod layout;
+mod lsp;
+pub mod overall;
+pub mod panel;
+pub mod persist;
+pub mod progress;
+#[cfg(feature = "python")]
+pub mod python;
+mod record;
+mod redraw;
+pub mod replay;
+pub mod segmented;
+pub mod style;
+pub mod subrange;
 mod template;
+pub mod test;
+pub mod theme;
 mod ticker;
+#[cfg(feature = "process_title")]
+mod title;
+mod tqdm;
+#[cfg(feature = "ratatui")]
+pub mod tui;
+#[cfg(all(test, not(loom)))]
+mod vt;
 pub mod writer;
-use template::{Template, TemplatePart};
+use backend::DrawBackend;
+use redraw::{LastDraw, RedrawFlag};
+use style::{
+    strip_ansi, ColorSupport, Effect, Gradient, Style, DEFAULT_FOREGROUND_ANSI, RESET_ANSI,
+};
+#[doc(hidden)]
+pub use template::validate as __validate_template;
+use template::{ByteFormat, TemplatePart};
+pub use template::{Template, TemplateError, MAX_BAR_WIDTH, MAX_BYTE_PRECISION, MAX_TEMPLATE_LEN};
 use termsize::get_width;
+use theme::Theme;
 use ticker::Ticker;
+#[cfg(all(unix, feature = "terminal_lock"))]
+mod termlock;
 mod termsize;
 
+/// The one-line template of collapsed bars, unless set by `Bar::set_collapsed_template`.
+const DEFAULT_COLLAPSED_TEMPLATE: &str = "{msg} ({pos}/{len})";
+
+/// How long each frame of `{spinner}` is shown, unless set by `Bar::set_spinner_frames`.
+const DEFAULT_SPINNER_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// How often a lost terminal is checked again, to go back to ANSI mode when the output is a terminal.
+const TERMINAL_RECHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
 const CLEAR_ANSI: &str = "\r\x1b[K";
 const UP_ANSI: &str = "\x1b[F";
 
@@ -67,11 +171,176 @@ pub(crate) struct BarState {
     len: u64,
     pos: u64,
     message: String,
+    /// Set by `Bar::set_field`, shown by `{field:NAME}`.
+    fields: BTreeMap<String, String>,
+    /// Whether the bar was finished when last drawn, so that `{bell}` rings only once.
+    bell_rung: bool,
     template: Template,
     created_at: std::time::Instant,
     visible: bool,
     /// Note that need_redraw for individual bars would only be respected when output is not a terminal.
     need_redraw: bool,
+    /// When set, the position is read from here on each draw instead of being set by the user.
+    source: Option<PosSource>,
+    /// Fill `{bar}` by the remaining part instead of the finished part.
+    reverse: bool,
+    /// The generation of the countdown started by `Bar::countdown`, so that a `Countdown` only stops its own.
+    countdown: u64,
+    /// Color of the fill of `{bar}`.
+    gradient: Option<Gradient>,
+    /// Marked by `Bar::fail`.
+    failed: bool,
+    /// Set by `Bar::retrying`, rendered instead of the template until `Bar::resume_progress`.
+    retry: Option<Retry>,
+    /// When pos was last changed, to detect stalled bars.
+    last_progress_at: std::time::Instant,
+    /// The effect applied when last drawn.
+    effect_shown: Option<Effect>,
+    /// Frames and the duration of each frame of `{spinner}`, set by `Bar::set_spinner_frames`.
+    spinner: Option<(Vec<String>, std::time::Duration)>,
+    /// The tick of `{spinner}` when last drawn.
+    spinner_shown: u64,
+    /// Bars in the same alignment group share column widths.
+    align_group: Option<usize>,
+    /// Set by `Bar::set_column`.
+    column: layout::Column,
+    /// Created by `Manager::create_lazy_bar`, and not yet shown by `materialize`.
+    lazy: bool,
+    /// Render with `collapsed_template` instead of `template`.
+    collapsed: bool,
+    collapsed_template: Template,
+    /// Render with this instead of `template` when finished.
+    finished_template: Option<Template>,
+    /// Render with this instead of `template` when output is not a terminal.
+    non_terminal_template: Option<Template>,
+    /// Set by `Bar::set_detail`, shown under the bar with `Verbosity::Detailed`.
+    detail: Option<Template>,
+    /// When pos reached len, to freeze `{elapsed}` of finished bars.
+    finished_at: Option<std::time::Instant>,
+    /// The thread which created the bar, or last changed its position with `Bar::inc` or `Bar::set_pos`.
+    thread: ThreadTag,
+    /// Notified when finished or failed, for `Bar::wait_finished`.
+    finished_cvar: Arc<std::sync::Condvar>,
+    /// Woken when finished or failed, for `Bar::finished_future`.
+    finished_wakers: Vec<std::task::Waker>,
+    eta_estimator: eta::EtaEstimator,
+    /// Set by `Bar::set_log_level`.
+    log_changes: LogChanges,
+    /// Set by `Bar::set_min_delta` and `Bar::set_min_percent_delta`.
+    min_delta: u64,
+    min_percent_delta: f64,
+    /// The position when last drawn.
+    drawn_pos: u64,
+    /// Registered by `Bar::on_progress_at`.
+    progress_callbacks: Vec<ProgressCallback>,
+    /// Callbacks with the thresholds crossed, to be run after locks are released.
+    crossed: Vec<(Arc<ProgressCallbackFn>, u8)>,
+    /// Shared with `ManagerInner::progress_crossed`, set when `crossed` is not empty.
+    progress_crossed: Arc<AtomicBool>,
+}
+
+type ProgressCallbackFn = dyn Fn(u8) + Send + Sync;
+
+/// Percentage thresholds not crossed yet, and the callback.
+struct ProgressCallback {
+    thresholds: Vec<u8>,
+    callback: Arc<ProgressCallbackFn>,
+}
+
+/// Set by `Bar::retrying`.
+struct Retry {
+    attempt: u32,
+    max: u32,
+    /// When the next attempt starts.
+    at: std::time::Instant,
+    /// The countdown in seconds when last drawn.
+    shown: u64,
+}
+
+impl Retry {
+    /// Seconds until the next attempt, rounded up, so that 0 is only shown when it's due.
+    fn countdown(&self) -> u64 {
+        let remaining = self.at.saturating_duration_since(clock::now());
+        remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0)
+    }
+
+    fn render(&self, message: &str) -> String {
+        let countdown = match self.countdown() {
+            0 => "retrying now".to_string(),
+            secs => format!("retrying in {}s", secs),
+        };
+        format!(
+            "{}: attempt {}/{} failed, {}",
+            message, self.attempt, self.max, countdown
+        )
+    }
+}
+
+type FrameFilter = dyn Fn(String) -> String + Send + Sync;
+type Encoder = dyn Fn(&str) -> Vec<u8> + Send + Sync;
+
+/// Set by `Manager::set_heartbeat`.
+struct Heartbeat {
+    idle: std::time::Duration,
+    /// When anything was last written.
+    last_output: std::time::Instant,
+}
+
+impl Heartbeat {
+    fn due(&self, now: std::time::Instant) -> bool {
+        now.saturating_duration_since(self.last_output) >= self.idle
+    }
+}
+
+/// Like `still running, 42% after 0:10:00`, or `None` when no bar is in progress.
+fn heartbeat_line(states: &BTreeMap<usize, Arc<Mutex<BarState>>>) -> Option<String> {
+    let (mut pos, mut len) = (0u128, 0u128);
+    let mut elapsed = std::time::Duration::ZERO;
+    let mut running = false;
+    for state in states.values() {
+        let state = state.lock_unpoisoned();
+        running |= matches!(state.status(), BarStatus::New | BarStatus::InProgress);
+        pos += state.pos.min(state.len) as u128;
+        len += state.len as u128;
+        elapsed = elapsed.max(state.elapsed());
+    }
+    if !running {
+        return None;
+    }
+    let elapsed = duration_to_human(elapsed);
+    Some(match (pos * 100).checked_div(len) {
+        Some(percent) => format!("still running, {}% after {}\n", percent, elapsed),
+        None => format!("still running after {}\n", elapsed),
+    })
+}
+
+/// External sources of a bar position.
+pub(crate) enum PosSource {
+    Atomic(Arc<AtomicU64>),
+    Callback(Box<dyn Fn() -> u64 + Send>),
+    /// Milliseconds elapsed since `created_at`, capped at `len`.
+    Elapsed,
+    /// The sum of positions of segments, of a `SegmentedBar`.
+    Segments(Arc<segmented::Segments>),
+    /// The weighted progress of member bars, of an `OverallBar`; also updates the len.
+    /// It is computed by `poll_sources`, as it needs other states.
+    Overall(Arc<overall::Members>),
+}
+
+impl BarState {
+    /// Get the position from the external source, if any.
+    fn source_pos(&self) -> Option<u64> {
+        Some(match self.source.as_ref()? {
+            PosSource::Atomic(atomic) => atomic.load(std::sync::atomic::Ordering::Acquire),
+            PosSource::Callback(callback) => callback(),
+            PosSource::Segments(segments) => segments.pos(),
+            PosSource::Overall(_) => return None,
+            PosSource::Elapsed => {
+                let elapsed = clock::elapsed(self.created_at).as_millis();
+                elapsed.min(self.len as u128) as u64
+            }
+        })
+    }
 }
 
 fn duration_to_human(duration: std::time::Duration) -> String {
@@ -82,43 +351,474 @@ fn duration_to_human(duration: std::time::Duration) -> String {
     format!("{}:{:02}:{:02}", hours, minutes, seconds)
 }
 
-fn bytes_to_human(bytes: u64) -> String {
-    const KB: u64 = 1024;
-    const MB: u64 = KB * 1024;
-    const GB: u64 = MB * 1024;
-    const TB: u64 = GB * 1024;
+fn bytes_to_human(bytes: u64, precision: usize) -> String {
+    // u64::MAX is 16 EiB
+    const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    // Values like 1023.999 would be shown as 1024.00 otherwise
+    let limit = 1024.0 - 0.5 / 10f64.powi(precision as i32);
+    while value >= limit && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.*} {}", precision, value, UNITS[unit])
+}
+
+/// The widest `format_bytes` of values up to `max`.
+fn bytes_width(max: u64, format: &ByteFormat, default_precision: usize) -> usize {
+    let precision = format.precision.unwrap_or(default_precision);
+    match format.unit {
+        Some(unit) => unit.format(max, precision).len(),
+        // The widest values are just below the next unit, like `1023.99 KiB`
+        None if max >= 1024 => format!("{:.*} KiB", precision, 1023.0).len(),
+        None => bytes_to_human(max, precision).len(),
+    }
+}
+
+/// Format `bytes` as specified by a byte tag, or with `default_precision` when its precision is not given.
+fn format_bytes(bytes: u64, format: &ByteFormat, default_precision: usize) -> String {
+    let precision = format.precision.unwrap_or(default_precision);
+    match format.unit {
+        Some(unit) => unit.format(bytes, precision),
+        None => bytes_to_human(bytes, precision),
+    }
+}
+
+/// A thread with its number for `{thread_id}`.
+///
+/// Numbers are assigned by kyuri in the order threads first use bars, as `ThreadId::as_u64` is unstable,
+/// and the `Debug` output of `ThreadId` is not a stable format.
+#[derive(Clone)]
+struct ThreadTag {
+    thread: std::thread::Thread,
+    number: u64,
+}
+
+impl ThreadTag {
+    fn current() -> Self {
+        static NEXT_NUMBER: AtomicU64 = AtomicU64::new(1);
+        thread_local! {
+            static NUMBER: u64 = NEXT_NUMBER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+        ThreadTag {
+            thread: std::thread::current(),
+            number: NUMBER.with(|number| *number),
+        }
+    }
+}
+
+/// Unicode FIRST STRONG ISOLATE, used with POP DIRECTIONAL ISOLATE to isolate bidi text.
+const FSI: char = '\u{2068}';
+/// Unicode POP DIRECTIONAL ISOLATE.
+const PDI: char = '\u{2069}';
+
+/// The display width of `s`, as kyuri measures it when drawing.
+///
+/// ANSI escape sequences and bidi isolates are ignored.
+/// With the `unicode` feature the width of wide characters is respected, otherwise each `char` counts as one column.
+pub fn string_width(s: &str) -> usize {
+    let s = strip_ansi(s);
+    #[cfg(feature = "unicode")]
+    {
+        unicode_width::UnicodeWidthStr::width(s.as_ref())
+    }
 
-    if bytes < KB {
-        format!("{} B", bytes)
-    } else if bytes < MB {
-        format!("{:.2} KiB", bytes as f64 / KB as f64)
-    } else if bytes < GB {
-        format!("{:.2} MiB", bytes as f64 / MB as f64)
-    } else if bytes < TB {
-        format!("{:.2} GiB", bytes as f64 / GB as f64)
-    } else {
-        format!("{:.2} TiB", bytes as f64 / TB as f64)
+    #[cfg(not(feature = "unicode"))]
+    {
+        // Bidi isolates are invisible
+        s.chars().filter(|&c| c != FSI && c != PDI).count()
     }
 }
 
-fn string_width(s: &str) -> usize {
+/// Width of a character in columns, in the same way as `string_width`.
+fn char_width(c: char) -> usize {
     #[cfg(feature = "unicode")]
     {
-        unicode_width::UnicodeWidthStr::width(s)
+        unicode_width::UnicodeWidthChar::width(c).unwrap_or(0)
     }
 
     #[cfg(not(feature = "unicode"))]
     {
-        s.chars().count()
+        usize::from(c != FSI && c != PDI && !c.is_control())
+    }
+}
+
+/// Count the lines `s` takes in a terminal with `term_col` columns,
+/// that is, how many lines the cursor moves down when writing `s` from the start of a line.
+fn count_lines(s: &str, term_col: usize) -> usize {
+    let term_col = term_col.max(1);
+    let mut newlines = 0;
+    let mut col = 0;
+    for c in strip_ansi(s).chars() {
+        match c {
+            '\n' => {
+                newlines += 1;
+                col = 0;
+            }
+            '\r' => col = 0,
+            c => {
+                let width = char_width(c);
+                // Wrap before a character not fitting in the line, like a wide character in the last column.
+                // A full line wraps only when more is written, so "\n" right after it doesn't make an empty line.
+                if col > 0 && col + width > term_col {
+                    newlines += 1;
+                    col = 0;
+                }
+                col += width;
+            }
+        }
+    }
+    newlines
+}
+
+/// Manager-wide options that affect how a bar is rendered.
+pub(crate) struct RenderOptions {
+    /// Wrap `{msg}` with Unicode isolates.
+    isolate_bidi: bool,
+    theme: Arc<Theme>,
+    /// Style tags are degraded to this.
+    color: ColorSupport,
+    effects: Effects,
+    /// How many times bars have been drawn in ANSI mode, for pulse effects.
+    frame: usize,
+    /// Shared column widths of alignment groups in this frame.
+    alignments: HashMap<usize, Alignment>,
+    /// Whether the output is a terminal (ANSI mode).
+    is_terminal: bool,
+    /// Set by `Manager::with_non_terminal_template`.
+    non_terminal_template: Option<Arc<Template>>,
+    /// Set by `Manager::set_verbosity`.
+    verbosity: Verbosity,
+    /// Totals of all bars in this frame.
+    aggregate: Aggregate,
+    /// Set by `Manager::with_tqdm_style`.
+    tqdm: bool,
+    /// Set by `Manager::set_byte_precision`.
+    byte_precision: usize,
+    /// Set by `Manager::set_stable_width`.
+    stable_width: bool,
+}
+
+/// Widest `{msg}`, `{pos}`, `{len}`, `{bytes}` and `{total_bytes}` of bars in an alignment group.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Alignment {
+    message: usize,
+    pos: usize,
+    total: usize,
+    bytes: usize,
+    total_bytes: usize,
+}
+
+impl Alignment {
+    fn widen(&mut self, state: &BarState, precision: usize) {
+        self.message = self.message.max(string_width(&state.message));
+        self.pos = self.pos.max(state.pos.to_string().len());
+        self.total = self.total.max(state.len.to_string().len());
+        self.bytes = self.bytes.max(bytes_to_human(state.pos, precision).len());
+        self.total_bytes = self
+            .total_bytes
+            .max(bytes_to_human(state.len, precision).len());
+    }
+}
+
+/// Totals of all bars in a frame, for `{all_bytes}`, `{all_total_bytes}`, `{all_bytes_per_sec}` and `{active_bars}`.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct Aggregate {
+    bytes: u64,
+    total_bytes: u64,
+    bytes_per_sec: f64,
+    active_bars: usize,
+}
+
+impl Aggregate {
+    fn add(&mut self, state: &BarState) {
+        self.bytes = self.bytes.saturating_add(state.pos);
+        self.total_bytes = self.total_bytes.saturating_add(state.len);
+        // Only bars in progress, as rates of finished bars are averages of the past
+        if state.pos < state.len && !state.failed {
+            self.bytes_per_sec += state.per_sec();
+            self.active_bars += 1;
+        }
     }
 }
 
+/// Effects for bars in certain states.
+#[derive(Clone, Default)]
+pub(crate) struct Effects {
+    /// Applied when the position has not changed for the duration, before finished.
+    stalled: Option<(std::time::Duration, Effect)>,
+    failed: Option<Effect>,
+}
+
 impl BarState {
-    pub fn render(&self) -> String {
+    /// Mark the bar to be redrawn after a change, when `log_changes` allows.
+    /// `position` is whether pos or len is changed.
+    fn mark_changed(&mut self, position: bool) {
+        let logged = match self.log_changes {
+            LogChanges::All => true,
+            LogChanges::PositionOnly => position,
+            LogChanges::Nothing => false,
+        };
+        if logged {
+            self.need_redraw = true;
+        }
+    }
+
+    /// Whether the position moved enough since last drawn, for `Bar::set_min_delta`. Finishing is always enough.
+    fn delta_reached(&self) -> bool {
+        if self.pos == self.len {
+            return true;
+        }
+        let delta = self.pos.abs_diff(self.drawn_pos);
+        delta >= self.min_delta
+            && (self.min_percent_delta <= 0.0
+                || delta as f64 * 100.0 / self.len as f64 >= self.min_percent_delta)
+    }
+
+    /// Show a lazy bar on its first progress, with `{elapsed}` starting from now. Returns whether it was lazy.
+    fn materialize(&mut self) -> bool {
+        if !self.lazy {
+            return false;
+        }
+        self.lazy = false;
+        self.visible = true;
+        self.need_redraw = true;
+        self.created_at = clock::now();
+        self.last_progress_at = self.created_at;
+        true
+    }
+
+    /// Set the position, and record the time of progress.
+    fn update_pos(&mut self, pos: u64) {
+        if pos != self.pos {
+            self.last_progress_at = clock::now();
+            self.eta_estimator.record(self.last_progress_at, pos);
+        }
+        self.pos = pos;
+        self.update_finished();
+    }
+
+    /// Collect callbacks of thresholds crossed, after pos or len is changed.
+    fn check_progress_callbacks(&mut self) {
+        if self.progress_callbacks.is_empty() {
+            return;
+        }
+        let percent = (self.pos as u128 * 100)
+            .checked_div(self.len as u128)
+            .unwrap_or(100);
+        for progress_callback in self.progress_callbacks.iter_mut() {
+            progress_callback.thresholds.retain(|&threshold| {
+                if percent >= threshold as u128 {
+                    self.crossed
+                        .push((progress_callback.callback.clone(), threshold));
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+        self.progress_callbacks
+            .retain(|progress_callback| !progress_callback.thresholds.is_empty());
+        if !self.crossed.is_empty() {
+            self.progress_crossed
+                .store(true, std::sync::atomic::Ordering::Release);
+        }
+    }
+
+    /// Record or clear the time of finishing, after pos or len is changed.
+    fn update_finished(&mut self) {
+        self.check_progress_callbacks();
+        self.finished_at = if self.pos == self.len {
+            self.notify_finished();
+            self.finished_at.or_else(|| Some(clock::now()))
+        } else {
+            None
+        };
+    }
+
+    /// Wake up `Bar::wait_finished` and `Bar::finished_future` to check the state again.
+    fn notify_finished(&mut self) {
+        self.finished_cvar.notify_all();
+        for waker in self.finished_wakers.drain(..) {
+            waker.wake();
+        }
+    }
+
+    /// `Some(true)` when finished, `Some(false)` when failed, and `None` otherwise.
+    fn finished_or_failed(&self) -> Option<bool> {
+        if self.failed {
+            Some(false)
+        } else if self.pos == self.len {
+            Some(true)
+        } else {
+            None
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.pos == self.len && !self.failed
+    }
+
+    /// Whether the bar is failed, or finished with a len, when it's no longer reported by
+    /// `with_accessible`, `with_ci_mode` or `set_lsp_progress`. Bars without a len are counters, never done.
+    pub(crate) fn is_done(&self) -> bool {
+        match self.status() {
+            BarStatus::Failed => true,
+            BarStatus::Finished => self.len > 0,
+            _ => false,
+        }
+    }
+
+    fn status(&self) -> BarStatus {
+        if self.failed {
+            BarStatus::Failed
+        } else if self.pos == self.len {
+            BarStatus::Finished
+        } else if self.pos == 0 {
+            BarStatus::New
+        } else if self.pos > self.len {
+            BarStatus::Overflowed
+        } else {
+            // 0 < self.pos < self.len
+            BarStatus::InProgress
+        }
+    }
+
+    /// The time since created, until finished.
+    fn elapsed(&self) -> std::time::Duration {
+        self.finished_at
+            .unwrap_or_else(clock::now)
+            .saturating_duration_since(self.created_at)
+    }
+
+    /// The average speed since created, or 0 when no time has elapsed.
+    fn per_sec(&self) -> f64 {
+        let elapsed = self.elapsed().as_secs_f64();
+        if elapsed == 0.0 {
+            return 0.0;
+        }
+        self.pos as f64 / elapsed
+    }
+
+    /// The estimated remaining time by the ETA strategy, or `None` when unknown.
+    fn eta(&self) -> Option<std::time::Duration> {
+        self.eta_estimator
+            .estimate(self.pos, self.len, self.elapsed())
+    }
+
+    fn snapshot(&self) -> BarSnapshot {
+        BarSnapshot {
+            pos: self.pos,
+            len: self.len,
+            message: self.message.clone(),
+            elapsed: self.elapsed(),
+            visible: self.visible,
+            status: self.status(),
+        }
+    }
+
+    /// How many frames `{spinner}` has advanced, which stops when the bar is finished.
+    fn spinner_tick(&self) -> u64 {
+        let interval = self
+            .spinner
+            .as_ref()
+            .map_or(DEFAULT_SPINNER_INTERVAL, |(_, interval)| *interval);
+        let ticks = self
+            .elapsed()
+            .as_nanos()
+            .checked_div(interval.as_nanos())
+            .unwrap_or(0);
+        ticks.min(u64::MAX as u128) as u64
+    }
+
+    /// The effect that applies to the bar now.
+    fn active_effect(&self, effects: &Effects) -> Option<Effect> {
+        if self.failed {
+            return effects.failed;
+        }
+        match effects.stalled {
+            Some((after, effect))
+                if self.pos < self.len && clock::elapsed(self.last_progress_at) >= after =>
+            {
+                Some(effect)
+            }
+            _ => None,
+        }
+    }
+
+    /// The template to render with, according to the state of the bar and the output.
+    fn active_template<'a>(&'a self, options: &'a RenderOptions) -> &'a Template {
+        if self.collapsed {
+            return &self.collapsed_template;
+        }
+        if let (true, Some(template)) = (self.is_finished(), &self.finished_template) {
+            return template;
+        }
+        if !options.is_terminal {
+            if let Some(template) = &self.non_terminal_template {
+                return template;
+            }
+            if let Some(template) = &options.non_terminal_template {
+                return template;
+            }
+        }
+        &self.template
+    }
+
+    pub fn render(&self, options: &RenderOptions) -> String {
+        if let Some(retry) = &self.retry {
+            return match options.isolate_bidi {
+                true => retry.render(&format!("{}{}{}", FSI, self.message, PDI)),
+                false => retry.render(&self.message),
+            };
+        }
+        if options.tqdm && !options.is_terminal {
+            return tqdm::render(self);
+        }
+        let align = self
+            .align_group
+            .and_then(|group| options.alignments.get(&group))
+            .copied()
+            .unwrap_or_default();
         let mut result = String::new();
-        let elapsed = std::time::Instant::now() - self.created_at;
-        let bytes_per_second = self.pos as f64 / elapsed.as_secs_f64();
-        for part in self.template.parts.iter() {
+        let mut styled = false;
+        let elapsed = self.elapsed();
+        let bytes_per_second = self.per_sec();
+        // Widths reserved by `Manager::set_stable_width` for values up to `max`, or 0
+        let stable = |max: u64, format: &ByteFormat| match options.stable_width {
+            true => bytes_width(max, format, options.byte_precision),
+            false => 0,
+        };
+        // Speeds in automatic units are up to like `1023.99 KiB/s`, and up to the length in fixed units
+        let stable_rate = |len: u64, format: &ByteFormat| match (options.stable_width, format.unit)
+        {
+            (false, _) => 0,
+            (true, None) => bytes_width(u64::MAX, format, options.byte_precision) + "/s".len(),
+            (true, Some(_)) => bytes_width(len, format, options.byte_precision) + "/s".len(),
+        };
+        // The detail block goes under the bar, unless collapsed
+        let detail = match (&self.detail, options.verbosity) {
+            (Some(detail), Verbosity::Detailed) if !self.collapsed => detail.parts.as_slice(),
+            _ => &[],
+        };
+        let newline = [TemplatePart::Newline];
+        let separator = if detail.is_empty() {
+            &[][..]
+        } else {
+            &newline[..]
+        };
+        for part in self
+            .active_template(options)
+            .parts
+            .iter()
+            .chain(separator)
+            .chain(detail)
+        {
             match part {
                 TemplatePart::Text(text) => {
                     result.push_str(text);
@@ -127,202 +827,1175 @@ impl BarState {
                     result.push('\n');
                 }
                 TemplatePart::Message => {
-                    result.push_str(&self.message);
+                    if options.isolate_bidi {
+                        result.push(FSI);
+                        result.push_str(&self.message);
+                        result.push(PDI);
+                    } else {
+                        result.push_str(&self.message);
+                    }
+                    let padding = align.message.saturating_sub(string_width(&self.message));
+                    result.extend(std::iter::repeat(' ').take(padding));
+                }
+                TemplatePart::Field(name) => {
+                    let value = self.fields.get(name).map_or("", String::as_str);
+                    if options.isolate_bidi {
+                        result.push(FSI);
+                        result.push_str(value);
+                        result.push(PDI);
+                    } else {
+                        result.push_str(value);
+                    }
                 }
                 TemplatePart::Elapsed => {
                     result.push_str(&duration_to_human(elapsed));
                 }
-                TemplatePart::Bytes => {
-                    result.push_str(&bytes_to_human(self.pos));
+                TemplatePart::Thread => match self.thread.thread.name() {
+                    Some(name) => result.push_str(name),
+                    None => result.push_str(&self.thread.number.to_string()),
+                },
+                TemplatePart::ThreadId => {
+                    result.push_str(&self.thread.number.to_string());
+                }
+                TemplatePart::Bytes(format) => {
+                    let bytes = format_bytes(self.pos, format, options.byte_precision);
+                    let width = match format.unit {
+                        None => align.bytes,
+                        Some(_) => 0,
+                    };
+                    let width = width.max(stable(self.len, format));
+                    result.push_str(&format!("{:>1$}", bytes, width));
                 }
                 TemplatePart::Pos => {
-                    result.push_str(&self.pos.to_string());
+                    let width = match options.stable_width {
+                        true => align.pos.max(self.len.to_string().len()),
+                        false => align.pos,
+                    };
+                    result.push_str(&format!("{:>1$}", self.pos, width));
                 }
-                TemplatePart::TotalBytes => {
-                    result.push_str(&bytes_to_human(self.len));
+                TemplatePart::TotalBytes(format) => {
+                    let bytes = format_bytes(self.len, format, options.byte_precision);
+                    match format.unit {
+                        None => result.push_str(&format!("{:>1$}", bytes, align.total_bytes)),
+                        Some(_) => result.push_str(&bytes),
+                    }
                 }
                 TemplatePart::Total => {
-                    result.push_str(&self.len.to_string());
+                    result.push_str(&format!("{:>1$}", self.len, align.total));
                 }
-                TemplatePart::BytesPerSecond => {
-                    result.push_str(&format!("{}/s", bytes_to_human(bytes_per_second as u64)));
+                TemplatePart::BytesPerSecond(format) => {
+                    let bytes_per_second = bytes_per_second as u64;
+                    let bytes = format_bytes(bytes_per_second, format, options.byte_precision);
+                    let width = stable_rate(self.len, format);
+                    result.push_str(&format!("{:>1$}", format!("{}/s", bytes), width));
                 }
-                TemplatePart::Eta => {
-                    if self.pos == 0 {
-                        result.push_str("Unknown");
-                    } else {
-                        let eta = (self.len - self.pos) as f64 / bytes_per_second;
-                        result.push_str(&duration_to_human(std::time::Duration::from_secs(
-                            eta as u64,
-                        )));
-                    }
+                TemplatePart::AllBytes(format) => {
+                    let bytes = options.aggregate.bytes;
+                    let bytes = format_bytes(bytes, format, options.byte_precision);
+                    let width = stable(options.aggregate.total_bytes, format);
+                    result.push_str(&format!("{:>1$}", bytes, width));
+                }
+                TemplatePart::AllTotalBytes(format) => {
+                    let bytes = options.aggregate.total_bytes;
+                    result.push_str(&format_bytes(bytes, format, options.byte_precision));
+                }
+                TemplatePart::AllBytesPerSecond(format) => {
+                    let bytes_per_second = options.aggregate.bytes_per_sec as u64;
+                    let bytes = format_bytes(bytes_per_second, format, options.byte_precision);
+                    let width = stable_rate(options.aggregate.total_bytes, format);
+                    result.push_str(&format!("{:>1$}", format!("{}/s", bytes), width));
+                }
+                TemplatePart::ActiveBars => {
+                    result.push_str(&options.aggregate.active_bars.to_string());
                 }
+                TemplatePart::Eta => match self.eta() {
+                    Some(eta) => result.push_str(&duration_to_human(eta)),
+                    None => result.push_str("Unknown"),
+                },
                 TemplatePart::Bar(size) => {
-                    let filled = (self.pos as f64 / self.len as f64 * *size as f64) as usize;
+                    if let Some(PosSource::Segments(segments)) = &self.source {
+                        result.push_str(&segments.render_bar(*size, &options.theme));
+                        continue;
+                    }
+                    let pos = if self.reverse {
+                        self.len.saturating_sub(self.pos)
+                    } else {
+                        self.pos
+                    };
+                    // At most `size` overflowed cells, also when len == 0 (pos / len is infinite)
+                    let filled =
+                        ((pos as f64 / self.len as f64 * *size as f64) as usize).min(*size * 2);
+                    let theme = &options.theme;
+                    let fill_color = self.gradient.as_ref().and_then(|gradient| {
+                        let color = gradient.at(self.pos as f64 / self.len as f64);
+                        Style::Foreground(color).to_ansi(options.color)
+                    });
                     if *size >= filled {
                         let empty = *size - filled;
-                        result.push('[');
+                        result.push_str(&theme.bar_left);
+                        if let Some(fill_color) = &fill_color {
+                            result.push_str(fill_color);
+                        }
                         for _ in 0..filled {
-                            result.push('=');
+                            result.push_str(&theme.bar_filled);
+                        }
+                        if fill_color.is_some() {
+                            result.push_str(DEFAULT_FOREGROUND_ANSI);
                         }
                         for _ in 0..empty {
-                            result.push(' ');
+                            result.push_str(&theme.bar_empty);
                         }
-                        result.push(']');
+                        result.push_str(&theme.bar_right);
                     } else {
                         let overflowed = filled - *size;
-                        result.push('[');
+                        result.push_str(&theme.bar_left);
+                        if let Some(fill_color) = &fill_color {
+                            result.push_str(fill_color);
+                        }
                         for _ in 0..*size {
-                            result.push('=');
+                            result.push_str(&theme.bar_filled);
+                        }
+                        if fill_color.is_some() {
+                            result.push_str(DEFAULT_FOREGROUND_ANSI);
                         }
                         for _ in 0..overflowed {
-                            result.push('!');
+                            result.push_str(&theme.bar_overflowed);
                         }
                     }
                 }
+                TemplatePart::Spinner => {
+                    let frames = match &self.spinner {
+                        Some((frames, _)) if !frames.is_empty() => frames,
+                        _ => &options.theme.spinner_frames,
+                    };
+                    if !frames.is_empty() {
+                        let index = self.spinner_tick() % frames.len() as u64;
+                        result.push_str(&frames[index as usize]);
+                    }
+                }
+                TemplatePart::Bell => {
+                    if options.is_terminal && self.is_finished() && !self.bell_rung {
+                        result.push('\x07');
+                    }
+                }
                 TemplatePart::StateEmoji => {
-                    if self.pos == self.len {
-                        result.push('✅');
-                    } else if self.pos == 0 {
-                        result.push('🆕');
-                    } else if self.pos > self.len {
-                        result.push('💥');
-                    } else {
-                        // 0 < self.pos < self.len
-                        result.push('⏳');
+                    let theme = &options.theme;
+                    result.push_str(match self.status() {
+                        BarStatus::Failed => &theme.icon_failed,
+                        BarStatus::Finished => &theme.icon_finished,
+                        BarStatus::New => &theme.icon_new,
+                        BarStatus::Overflowed => &theme.icon_overflowed,
+                        BarStatus::InProgress => &theme.icon_in_progress,
+                    });
+                }
+                TemplatePart::Style(style) => {
+                    if let Some(ansi) = style.to_ansi(options.color) {
+                        result.push_str(&ansi);
+                        styled = true;
                     }
                 }
             }
         }
+        let effect = self
+            .active_effect(&options.effects)
+            .and_then(|effect| effect.to_ansi(options.frame, options.color));
+        if let Some(effect) = effect {
+            // Apply to each line, as the clearing is line by line
+            result = format!(
+                "{}{}{}",
+                effect,
+                result.replace('\n', &format!("{}\n{}", RESET_ANSI, effect)),
+                RESET_ANSI
+            );
+        } else if styled {
+            // Don't leak styles to following lines
+            result.push_str(RESET_ANSI);
+        }
         result
     }
 }
 
+/// The error returned by fallible methods of `Bar` (like `Bar::try_set_pos`), when the manager has been dropped
+/// and the update is discarded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ManagerGone;
+
+impl std::fmt::Display for ManagerGone {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the manager of the progress bar has been dropped")
+    }
+}
+
+impl std::error::Error for ManagerGone {}
+
 /// A handle for users to control a progress bar created by `Manager`.
 pub struct Bar {
     id: usize,
     manager: Weak<ManagerInner>,
 }
 
+#[cfg(not(loom))]
+thread_local! {
+    /// Addresses of managers drawing or suspended on this thread, to detect reentrancy.
+    static RENDERING: std::cell::RefCell<Vec<usize>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+#[cfg(loom)]
+loom::thread_local! {
+    /// Loom's, for each thread modeled by loom to have its own.
+    static RENDERING: std::cell::RefCell<Vec<usize>> = std::cell::RefCell::new(Vec::new());
+}
+
+/// Marks the manager as drawing or suspended on this thread until dropped.
+struct RenderGuard(usize);
+
+impl RenderGuard {
+    /// Returns `None` if the manager is already drawing or suspended on this thread.
+    fn enter(manager: &ManagerInner) -> Option<Self> {
+        let addr = manager as *const _ as usize;
+        RENDERING.with(|rendering| {
+            let mut rendering = rendering.borrow_mut();
+            if rendering.contains(&addr) {
+                return None;
+            }
+            rendering.push(addr);
+            Some(RenderGuard(addr))
+        })
+    }
+}
+
+impl Drop for RenderGuard {
+    fn drop(&mut self) {
+        RENDERING.with(|rendering| rendering.borrow_mut().retain(|&addr| addr != self.0));
+    }
+}
+
+/// Output of `suspend` called inside a draw or `suspend` on this thread, where the output is locked.
+///
+/// It's kept in a temporary file (as `Out` could only be implemented by std types),
+/// until written to the output by the outer draw or `suspend`.
+struct Deferred {
+    // taken out when dropped, as the file is removed only after it's closed on Windows
+    file: Option<std::fs::File>,
+    path: std::path::PathBuf,
+}
+
+impl Deferred {
+    /// Create the file, and another handle to it for the closure of `suspend`.
+    fn create() -> std::io::Result<(Self, Box<dyn Out>)> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "kyuri-deferred-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        ));
+        // Removed when dropped, even if failing afterwards
+        let mut deferred = Deferred { file: None, path };
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&deferred.path)?;
+        let out = Box::new(file.try_clone()?);
+        deferred.file = Some(file);
+        Ok((deferred, out))
+    }
+
+    fn write_to(&mut self, out: &mut Box<dyn Out>) -> std::io::Result<()> {
+        use std::io::Seek;
+        if let Some(file) = self.file.as_mut() {
+            file.seek(std::io::SeekFrom::Start(0))?;
+            std::io::copy(file, out)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Deferred {
+    fn drop(&mut self) {
+        std::mem::drop(self.file.take());
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
 /// Lock order:
 /// - last_draw
 /// - out
 /// - states
+/// - log_lines
+/// - checklist
+/// - terminal_lost
+/// - ansi
+/// - env_ansi
+/// - terminal_lock
+/// - effects
+/// - process_title
+/// - stats
+/// - deferred
 pub(crate) struct ManagerInner {
     states: Mutex<BTreeMap<usize, Arc<Mutex<BarState>>>>,
     ansi: Mutex<Option<bool>>,
-    interval: std::time::Duration,
+    interval: Mutex<std::time::Duration>,
     pub(crate) out: Arc<Mutex<Box<dyn Out>>>,
     ticker: Mutex<Option<Ticker>>,
     force_when_finished: AtomicBool,
+    /// Set by `with_force_debounce`.
+    force_debounce: Mutex<std::time::Duration>,
+    /// Set by `with_burst`.
+    burst: Mutex<burst::TokenBucket>,
+    /// Set by `set_frame_filter`.
+    frame_filter: Mutex<Option<Box<FrameFilter>>>,
+    /// Set by `set_encoder`.
+    encoder: Mutex<Option<Box<Encoder>>>,
+    /// Set by `set_heartbeat`.
+    heartbeat: Mutex<Option<Heartbeat>>,
+    /// Set by `with_recording`.
+    recorder: Mutex<Option<record::Recorder>>,
+    /// Set by `with_backend`, or `None` to pick `backend::Ansi` or `backend::Append` by the output.
+    backend: Mutex<Option<Box<dyn backend::DrawBackend>>>,
+    /// Used when no backend is set and it's ANSI mode.
+    ansi_backend: Mutex<backend::Ansi>,
+    /// The last sequence number of frames written when output is not a terminal,
+    /// or `None` when not enabled by `with_sequence_numbers`.
+    sequence: Mutex<Option<u64>>,
+    /// Set by `with_accessible`, which also keeps the manager out of ANSI mode.
+    accessible: Mutex<Option<accessible::AccessibleLog>>,
+    /// Set by `set_panel`.
+    panel: Mutex<Option<panel::Panel>>,
+    /// Set by `set_split`.
+    split: Mutex<bool>,
+    /// Created by `updater_channel`, drained before each draw.
+    updater: Mutex<
+        Option<(
+            std::sync::mpsc::Sender<ffi::Update>,
+            std::sync::mpsc::Receiver<ffi::Update>,
+        )>,
+    >,
+    /// Added by `subscribe`, notified after each frame.
+    subscribers: Mutex<Vec<std::sync::mpsc::SyncSender<()>>>,
+    /// Set by `set_status_line`, a bar never in `states`.
+    status_line: Mutex<Option<BarState>>,
+    verbosity: Mutex<Verbosity>,
+    stats: Mutex<DrawStats>,
+    /// Set by `with_render_thread`.
+    render_thread: AtomicBool,
+    isolate_bidi: AtomicBool,
+    byte_precision: AtomicUsize,
+    stable_width: AtomicBool,
+    /// Last lines logged by `log_line`, rendered above bars in ANSI mode.
+    log_lines: Mutex<VecDeque<String>>,
+    /// Steps of checklists created by `create_checklist`, drawn between log lines and bars.
+    checklist: Mutex<Vec<Arc<Mutex<BarState>>>>,
+    log_capacity: AtomicUsize,
+    /// Named templates loaded by `with_templates_from`.
+    templates: Mutex<HashMap<String, Template>>,
+    theme: Mutex<Arc<Theme>>,
+    /// Color support set by `with_color_support`, or `None` to use the detected one.
+    color: Mutex<Option<ColorSupport>>,
+    detected_color: ColorSupport,
+    effects: Mutex<Effects>,
+    frame: AtomicUsize,
+    /// Set by `with_non_terminal_template`.
+    non_terminal_template: Mutex<Option<Arc<Template>>>,
+    #[cfg(feature = "process_title")]
+    process_title: Mutex<Option<title::ProcessTitle>>,
+    lsp_progress: Mutex<Option<lsp::LspProgress>>,
+    /// Set by `with_ci_mode`, or `with_tqdm_style` and `with_milestones` for milestones.
+    ci_log: Mutex<Option<ci::CiLog>>,
+    /// Set by `with_milestones`.
+    milestone_step: Mutex<Option<u8>>,
+    /// Set by `with_tqdm_style`.
+    tqdm: Mutex<bool>,
+    #[cfg(all(unix, feature = "terminal_lock"))]
+    terminal_lock: Mutex<Option<termlock::TerminalLock>>,
+    target_kind: Mutex<TargetKind>,
+    /// Set by `with_path`, for `reopen_output`.
+    output_path: Mutex<Option<std::path::PathBuf>>,
+    /// When writing in ANSI mode failed (like after the terminal hung up) or last checked since, to stay in
+    /// append mode until the output is a terminal again.
+    terminal_lost: Mutex<Option<std::time::Instant>>,
+    /// Set by `KYURI_DISABLE`: nothing is drawn.
+    disabled: AtomicBool,
+    /// Output of nested `suspend`s, not written yet.
+    deferred: Mutex<Vec<Deferred>>,
+    /// Set by `KYURI_FORCE_ANSI`, taking precedence over `ansi` set by builders.
+    env_ansi: Mutex<Option<bool>>,
+    /// Set by `set_max_bars`.
+    max_bars: Mutex<Option<usize>>,
+
+    /// Set when any bar has callbacks of `Bar::on_progress_at` to run.
+    progress_crossed: Arc<AtomicBool>,
 
     // interval states
     next_id: AtomicUsize,
-    last_draw: Mutex<std::time::Instant>,
-    last_lines: AtomicUsize,
-    need_redraw: AtomicBool,
+    last_draw: LastDraw,
+    need_redraw: RedrawFlag,
 }
 
 impl ManagerInner {
+    pub(crate) fn interval(&self) -> std::time::Duration {
+        *self.interval.lock_unpoisoned()
+    }
+
+    /// Whether this thread is drawing or suspended by this manager, where drawing again would deadlock.
+    pub(crate) fn is_rendering(&self) -> bool {
+        RENDERING.with(|rendering| rendering.borrow().contains(&(self as *const _ as usize)))
+    }
+
+    /// Set the interval, and let the ticker pick it up now.
+    pub(crate) fn set_interval(&self, interval: std::time::Duration) {
+        *self.interval.lock_unpoisoned() = interval;
+        if let Some(ticker) = &*self.ticker.lock_unpoisoned() {
+            ticker.reschedule();
+        }
+    }
+
     pub(crate) fn is_ticker_enabled(&self) -> bool {
-        self.ticker.lock().unwrap().is_some()
+        self.ticker.lock_unpoisoned().is_some()
     }
 
     /// This is expected to be called only when it's ANSI mode.
-    pub(crate) fn clear_existing(&self, out: &mut Box<dyn Out>) {
-        for _ in 0..self.last_lines.load(std::sync::atomic::Ordering::Acquire) {
-            let _ = out.write_all(format!("{}{}", UP_ANSI, CLEAR_ANSI).as_bytes());
+    pub(crate) fn clear_existing(&self, out: &mut Box<dyn Out>) -> std::io::Result<()> {
+        self.with_sink(out, |sink| match self.backend.lock_unpoisoned().as_mut() {
+            Some(backend) => backend.clear(sink),
+            None => self.ansi_backend.lock_unpoisoned().clear(sink),
+        })
+    }
+
+    /// Let the backend set by `with_backend` restore `out` after the last frame.
+    fn finish_backend(&self, out: &mut Box<dyn Out>) -> std::io::Result<()> {
+        self.with_sink(out, |sink| match self.backend.lock_unpoisoned().as_mut() {
+            Some(backend) => backend.finish(sink),
+            None => Ok(()),
+        })
+    }
+
+    /// Run `f` with `out` as given to backends, recording and encoding what's written to it.
+    fn with_sink<R>(
+        &self,
+        out: &mut Box<dyn Out>,
+        f: impl FnOnce(&mut backend::Sink<'_>) -> R,
+    ) -> R {
+        let mut recorder = self.recorder.lock_unpoisoned();
+        let encoder = self.encoder.lock_unpoisoned();
+        let mut sink = backend::Sink {
+            out,
+            recorder: recorder.as_mut(),
+            encoder: encoder.as_deref(),
+        };
+        f(&mut sink)
+    }
+
+    /// Copy `data` written to the output to the recording of `with_recording`, if any.
+    fn record(&self, data: &str) {
+        if let Some(recorder) = self.recorder.lock_unpoisoned().as_mut() {
+            recorder.record(data);
+        }
+    }
+
+    /// Replace the output and forced ANSI mode, and return the old ones.
+    /// Bars are cleared from the old output, and drawn on the new one.
+    fn swap_target(
+        &self,
+        out: Box<dyn Out>,
+        kind: TargetKind,
+        ansi: Option<bool>,
+    ) -> (Box<dyn Out>, TargetKind, Option<bool>) {
+        // Pending changes belong to the old output
+        self.draw(true);
+        let mut current = self.out.lock_unpoisoned();
+        if self.is_terminal(&mut current) {
+            let _ = self.clear_existing(&mut current);
         }
+        let _ = self.finish_backend(&mut current);
+        let _ = current.flush();
+        self.ansi_backend.lock_unpoisoned().reset();
+        *self.terminal_lost.lock_unpoisoned() = None;
+        let old = std::mem::replace(&mut *current, out);
+        let old_kind = std::mem::replace(&mut *self.target_kind.lock_unpoisoned(), kind);
+        let old_ansi = std::mem::replace(&mut *self.ansi.lock_unpoisoned(), ansi);
+        // Draw all bars on the new output, even when it's not a terminal
+        for state in self.states.lock_unpoisoned().values() {
+            state.lock_unpoisoned().mark_changed(true);
+        }
+        std::mem::drop(current);
+        self.mark_redraw();
+        self.draw(true);
+        (old, old_kind, old_ansi)
+    }
+
+    /// Whether writing in ANSI mode failed, checking every `TERMINAL_RECHECK_INTERVAL` whether the output is a
+    /// terminal again, like after being reattached.
+    fn is_terminal_lost(&self, out: &mut Box<dyn Out>) -> bool {
+        let mut lost = self.terminal_lost.lock_unpoisoned();
+        let Some(since) = *lost else {
+            return false;
+        };
+        let now = clock::now();
+        if now.saturating_duration_since(since) < TERMINAL_RECHECK_INTERVAL {
+            return true;
+        }
+        if out.is_terminal() {
+            *lost = None;
+            return false;
+        }
+        *lost = Some(now);
+        true
     }
 
     pub(crate) fn is_terminal(&self, out: &mut Box<dyn Out>) -> bool {
-        let ansi = self.ansi.lock().unwrap();
-        match *ansi {
+        if self.accessible.lock_unpoisoned().is_some() || self.is_terminal_lost(out) {
+            return false;
+        }
+        if let Some(backend) = self.backend.lock_unpoisoned().as_ref() {
+            return backend.redraws();
+        }
+        let ansi = self.ansi.lock_unpoisoned();
+        let is_terminal = match self.env_ansi.lock_unpoisoned().or(*ansi) {
             None => out.is_terminal(),
             Some(force) => force,
+        };
+        #[cfg(all(unix, feature = "terminal_lock"))]
+        if is_terminal {
+            if let Some(lock) = self.terminal_lock.lock_unpoisoned().as_mut() {
+                // Fall back to append mode when another process is rendering live
+                return lock.try_hold();
+            }
         }
+        is_terminal
     }
 
-    pub(crate) fn draw_inner(
+    pub(crate) fn render_options(&self, is_terminal: bool) -> RenderOptions {
+        let color = if is_terminal {
+            self.color.lock_unpoisoned().unwrap_or(self.detected_color)
+        } else {
+            ColorSupport::None
+        };
+        RenderOptions {
+            isolate_bidi: self.isolate_bidi.load(std::sync::atomic::Ordering::Acquire),
+            theme: self.theme.lock_unpoisoned().clone(),
+            color,
+            effects: self.effects.lock_unpoisoned().clone(),
+            frame: self.frame.load(std::sync::atomic::Ordering::Acquire),
+            alignments: HashMap::new(),
+            is_terminal,
+            non_terminal_template: self.non_terminal_template.lock_unpoisoned().clone(),
+            verbosity: *self.verbosity.lock_unpoisoned(),
+            aggregate: Aggregate::default(),
+            tqdm: *self.tqdm.lock_unpoisoned(),
+            byte_precision: self
+                .byte_precision
+                .load(std::sync::atomic::Ordering::Acquire),
+            stable_width: self.stable_width.load(std::sync::atomic::Ordering::Acquire),
+        }
+    }
+
+    /// `render_options`, with alignment groups of visible bars measured, and totals of all bars.
+    fn frame_options(
         &self,
         states: &BTreeMap<usize, Arc<Mutex<BarState>>>,
-        out: &mut Box<dyn Out>,
         is_terminal: bool,
-    ) {
-        let mut newlines = 0;
+    ) -> RenderOptions {
+        let mut options = self.render_options(is_terminal);
         for state in states.values() {
-            let mut state = state.lock().unwrap();
-            if !state.visible {
-                continue;
+            let state = state.lock_unpoisoned();
+            if let (true, Some(group)) = (state.visible, state.align_group) {
+                let precision = options.byte_precision;
+                options
+                    .alignments
+                    .entry(group)
+                    .or_default()
+                    .widen(&state, precision);
             }
-            if !is_terminal && !state.need_redraw {
-                continue;
-            }
-            let outstr = format!("{}\n", state.render());
-            if is_terminal {
-                let splits = outstr.split('\n');
-                let term_col = get_width(out.as_ref()) as usize;
-                for i in splits {
-                    let width = string_width(i);
-                    newlines += width / term_col;
-                    if width % term_col != 0 {
-                        newlines += 1;
+            options.aggregate.add(&state);
+        }
+        options
+    }
+
+    /// `frame_options` of ANSI mode without colors, for frontends other than the terminal.
+    fn plain_options(&self, states: &BTreeMap<usize, Arc<Mutex<BarState>>>) -> RenderOptions {
+        let mut options = self.frame_options(states, true);
+        options.color = ColorSupport::None;
+        options
+    }
+
+    /// Render log lines, checklist steps and visible bars as drawn in ANSI mode, without drawing.
+    pub(crate) fn render_frame(&self) -> String {
+        let states = self.states.lock_unpoisoned();
+        let options = self.frame_options(&states, true);
+        let mut frame = String::new();
+        for line in self.log_lines.lock_unpoisoned().iter() {
+            frame.push_str(line);
+            frame.push('\n');
+        }
+        for step in self.checklist.lock_unpoisoned().iter() {
+            frame.push_str(&step.lock_unpoisoned().render(&options));
+            frame.push('\n');
+        }
+        for state in states.values() {
+            let state = state.lock_unpoisoned();
+            if state.visible {
+                frame.push_str(&state.render(&options));
+                frame.push('\n');
+            }
+        }
+        match self.frame_filter.lock_unpoisoned().as_ref() {
+            Some(filter) => filter(frame),
+            None => frame,
+        }
+    }
+
+    pub(crate) fn draw_inner(
+        &self,
+        states: &BTreeMap<usize, Arc<Mutex<BarState>>>,
+        out: &mut Box<dyn Out>,
+        is_terminal: bool,
+    ) -> std::io::Result<()> {
+        let options = self.frame_options(states, is_terminal);
+        // The frame is assembled and then written at once, after `set_frame_filter`
+        let mut frame = String::new();
+        let ci_log = match is_terminal {
+            true => None,
+            false => match self.accessible.lock_unpoisoned().as_mut() {
+                Some(accessible) => Some(accessible.render(states)),
+                None => self
+                    .ci_log
+                    .lock_unpoisoned()
+                    .as_mut()
+                    .map(|ci_log| ci_log.render(states, &options)),
+            },
+        };
+        if let Some(ci_log) = ci_log {
+            frame = ci_log;
+        } else {
+            let term_col = get_width(out.as_ref()) as usize;
+            self.assemble_frame(states, &options, term_col, &mut frame);
+        }
+        if let Some(heartbeat) = self.heartbeat.lock_unpoisoned().as_mut() {
+            let now = clock::now();
+            if is_terminal || !frame.is_empty() {
+                heartbeat.last_output = now;
+            } else if heartbeat.due(now) {
+                frame = heartbeat_line(states).unwrap_or_default();
+                heartbeat.last_output = now;
+            }
+        }
+        if let (false, Some(sequence)) = (is_terminal, self.sequence.lock_unpoisoned().as_mut()) {
+            if !frame.is_empty() {
+                *sequence += 1;
+                frame = frame
+                    .split_inclusive('\n')
+                    .map(|line| format!("[{}] {}", sequence, line))
+                    .collect();
+            }
+        }
+        if let Some(filter) = self.frame_filter.lock_unpoisoned().as_ref() {
+            frame = filter(frame);
+        }
+        let mut custom = self.backend.lock_unpoisoned();
+        // Snapshots are only taken for custom backends, as built-in ones write the text
+        let bars: Vec<BarSnapshot> = match custom.is_some() {
+            true => states
+                .values()
+                .map(|state| state.lock_unpoisoned())
+                .filter(|state| state.visible)
+                .map(|state| state.snapshot())
+                .collect(),
+            false => Vec::new(),
+        };
+        let frame = backend::Frame {
+            text: &frame,
+            bars: &bars,
+            width: get_width(out.as_ref()) as usize,
+        };
+        let result = self.with_sink(out, |sink| match custom.as_mut() {
+            Some(custom) => custom.draw(sink, &frame),
+            None if is_terminal => self.ansi_backend.lock_unpoisoned().draw(sink, &frame),
+            None => {
+                // Nothing is to be cleared when back in ANSI mode, like when the output becomes a terminal again
+                self.ansi_backend.lock_unpoisoned().reset();
+                backend::Append.draw(sink, &frame)
+            }
+        });
+        if is_terminal {
+            self.frame.fetch_add(1, std::sync::atomic::Ordering::AcqRel);
+        }
+        result
+    }
+
+    /// Render log lines (in ANSI mode), checklist steps and bars to draw into `frame`.
+    fn assemble_frame(
+        &self,
+        states: &BTreeMap<usize, Arc<Mutex<BarState>>>,
+        options: &RenderOptions,
+        term_col: usize,
+        frame: &mut String,
+    ) {
+        let is_terminal = options.is_terminal;
+        if is_terminal {
+            for line in self.log_lines.lock_unpoisoned().iter() {
+                frame.push_str(line);
+                frame.push('\n');
+            }
+        }
+        // Like bars, steps are only written when changed if output is not a terminal
+        for step in self.checklist.lock_unpoisoned().iter() {
+            let mut step = step.lock_unpoisoned();
+            if is_terminal || step.need_redraw {
+                frame.push_str(&step.render(options));
+                frame.push('\n');
+                step.need_redraw = false;
+            }
+        }
+        let max_bars = match is_terminal {
+            true => *self.max_bars.lock_unpoisoned(),
+            false => None,
+        };
+        let panel = match is_terminal {
+            true => self.panel.lock_unpoisoned().clone(),
+            false => None,
+        };
+        let split = is_terminal && *self.split.lock_unpoisoned();
+        let (mut left, mut right) = (Vec::new(), Vec::new());
+        let bars_start = frame.len();
+        let (mut drawn, mut more) = (0, 0);
+        for state in states.values() {
+            let mut state = state.lock_unpoisoned();
+            if !state.visible {
+                continue;
+            }
+            if !is_terminal && !state.need_redraw {
+                continue;
+            }
+            if max_bars.is_some_and(|max| drawn >= max) {
+                more += 1;
+                continue;
+            }
+            drawn += 1;
+            let rendered = state.render(options);
+            if split {
+                let column = match state.column {
+                    layout::Column::Left => &mut left,
+                    layout::Column::Right => &mut right,
+                };
+                column.extend(rendered.lines().map(String::from));
+            } else {
+                frame.push_str(&rendered);
+                frame.push('\n');
+            }
+            state.need_redraw = false;
+            state.drawn_pos = state.pos;
+            state.effect_shown = state.active_effect(&options.effects);
+            state.bell_rung = state.is_finished();
+            state.spinner_shown = state.spinner_tick();
+            if let Some(retry) = &mut state.retry {
+                retry.shown = retry.countdown();
+            }
+        }
+        if split {
+            frame.push_str(&layout::split(&left, &right, term_col));
+        }
+        if more > 0 {
+            frame.push_str(&format!("... and {} more\n", more));
+        }
+        if let (Some(panel), true) = (panel, drawn > 0) {
+            frame.insert_str(bars_start, &panel.top(term_col));
+            frame.push_str(&panel.bottom(term_col));
+        }
+        if let (true, Some(status_line)) =
+            (is_terminal, self.status_line.lock_unpoisoned().as_ref())
+        {
+            frame.push_str(&status_line.render(options));
+            frame.push('\n');
+        }
+    }
+
+    pub(crate) fn create_bar(
+        self: &Arc<Self>,
+        len: u64,
+        message: &str,
+        template: Template,
+        visible: bool,
+        source: Option<PosSource>,
+    ) -> Bar {
+        self.insert_bar(self.new_state(len, message, template, visible, source))
+    }
+
+    /// Add a bar with `bar_state` prepared, drawn (with a forced draw) if visible.
+    fn insert_bar(self: &Arc<Self>, bar_state: BarState) -> Bar {
+        let id = self
+            .next_id
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let visible = bar_state.visible;
+        let bar_state = Arc::new(Mutex::new(bar_state));
+
+        self.states.lock_unpoisoned().insert(id, bar_state.clone());
+
+        if visible {
+            self.mark_redraw();
+            self.draw_event();
+        }
+
+        Bar {
+            manager: Arc::downgrade(self),
+            id,
+        }
+    }
+
+    fn new_state(
+        &self,
+        len: u64,
+        message: &str,
+        template: Template,
+        visible: bool,
+        source: Option<PosSource>,
+    ) -> BarState {
+        let mut bar_state = BarState {
+            len,
+            pos: 0,
+            message: message.to_string(),
+            template,
+            created_at: clock::now(),
+            visible,
+            need_redraw: true,
+            source,
+            reverse: false,
+            countdown: 0,
+            gradient: None,
+            align_group: None,
+            column: layout::Column::Left,
+            lazy: false,
+            collapsed: false,
+            collapsed_template: Template::new(DEFAULT_COLLAPSED_TEMPLATE),
+            finished_template: None,
+            non_terminal_template: None,
+            finished_at: None,
+            thread: ThreadTag::current(),
+            finished_cvar: Arc::new(std::sync::Condvar::new()),
+            finished_wakers: Vec::new(),
+            eta_estimator: eta::EtaEstimator::default(),
+            log_changes: LogChanges::All,
+            fields: BTreeMap::new(),
+            // A bar created finished (len = 0) never transitions to finished
+            bell_rung: len == 0,
+            detail: None,
+            min_delta: 0,
+            min_percent_delta: 0.0,
+            drawn_pos: 0,
+            progress_callbacks: Vec::new(),
+            crossed: Vec::new(),
+            progress_crossed: self.progress_crossed.clone(),
+            failed: false,
+            last_progress_at: clock::now(),
+            effect_shown: None,
+            spinner: None,
+            spinner_shown: 0,
+            retry: None,
+        };
+        bar_state.pos = bar_state.source_pos().unwrap_or(0);
+        bar_state
+    }
+
+    /// Update positions of bars with external sources, and mark redraw if any of them changed.
+    ///
+    /// Also mark redraw if any effect needs to be shown, changed or pulsed.
+    pub(crate) fn poll_sources(&self) {
+        ffi::apply_updates(self);
+        let effects = self.effects.lock_unpoisoned().clone();
+        // Sources may update other bars, which locks states
+        let states = self.states.lock_unpoisoned().clone();
+        for (id, bar_state) in &states {
+            let mut state = bar_state.lock_unpoisoned();
+            let mut len = state.len;
+            let mut pos = state.source_pos();
+            if let Some(PosSource::Overall(members)) = &state.source {
+                // Members are locked one at a time, so don't hold this state meanwhile
+                let members = members.clone();
+                drop(state);
+                let progress = members.progress(*id, &states);
+                state = bar_state.lock_unpoisoned();
+                (pos, len) = (Some(progress.0), progress.1);
+            }
+            if len != state.len {
+                state.len = len;
+                state.update_finished();
+                state.mark_changed(true);
+                self.mark_redraw();
+            }
+            if let Some(pos) = pos {
+                if pos != state.pos {
+                    state.update_pos(pos);
+                    if state.delta_reached() {
+                        state.mark_changed(true);
+                        self.mark_redraw();
                     }
                 }
             }
-            let _ = out.write_all(outstr.as_bytes());
-            state.need_redraw = false;
+            let effect = state.active_effect(&effects);
+            if effect != state.effect_shown || effect.is_some_and(|e| e.is_pulse()) {
+                // Effects are only visible in ANSI mode, so the bar itself is not marked
+                self.mark_redraw();
+            }
+            // Likewise for spinners, which advance with time
+            if state.visible
+                && state.spinner_tick() != state.spinner_shown
+                && state
+                    .template
+                    .parts
+                    .iter()
+                    .any(|part| matches!(part, TemplatePart::Spinner))
+            {
+                self.mark_redraw();
+            }
+            // And the countdown of retrying bars
+            if state.visible
+                && state
+                    .retry
+                    .as_ref()
+                    .is_some_and(|retry| retry.countdown() != retry.shown)
+            {
+                self.mark_redraw();
+            }
         }
-        if is_terminal {
-            self.last_lines
-                .store(newlines, std::sync::atomic::Ordering::Release);
+        // The status line is likely to change with time, like `{elapsed}`
+        if self.status_line.lock_unpoisoned().is_some() {
+            self.mark_redraw();
+        }
+        // So are running steps of checklists
+        if self.checklist.lock_unpoisoned().iter().any(|step| {
+            let step = step.lock_unpoisoned();
+            !step.is_finished()
+                && step
+                    .template
+                    .parts
+                    .iter()
+                    .any(|part| matches!(part, TemplatePart::Elapsed))
+        }) {
+            self.mark_redraw();
+        }
+        if self
+            .heartbeat
+            .lock_unpoisoned()
+            .as_ref()
+            .is_some_and(|heartbeat| heartbeat.due(clock::now()))
+        {
+            self.mark_redraw();
         }
     }
 
     pub(crate) fn mark_redraw(&self) {
-        self.need_redraw
-            .store(true, std::sync::atomic::Ordering::Release);
+        self.need_redraw.mark();
     }
 
     pub(crate) fn draw(&self, force: bool) {
-        if !force && self.is_ticker_enabled() {
-            return;
-        }
-        let now = std::time::Instant::now();
-        let mut last_draw = self.last_draw.lock().unwrap();
-        if !force && now - *last_draw < self.interval {
-            return;
+        let _ = self.try_draw(force, false);
+        self.run_progress_callbacks();
+    }
+
+    /// A forced draw caused by bar events like creation and finishing, debounced by `force_debounce` in ANSI mode.
+    ///
+    /// With `with_render_thread`, the ticker thread is woken up to draw instead.
+    pub(crate) fn draw_event(&self) {
+        if self
+            .render_thread
+            .load(std::sync::atomic::Ordering::Acquire)
+        {
+            if let Some(ticker) = &*self.ticker.lock_unpoisoned() {
+                ticker.nudge();
+                return;
+            }
         }
+        self.draw_event_now();
+    }
+
+    /// `draw_event` on the current thread.
+    pub(crate) fn draw_event_now(&self) {
+        let _ = self.try_draw(true, true);
+        self.run_progress_callbacks();
+    }
 
+    /// Run callbacks of `Bar::on_progress_at` whose thresholds are crossed, without holding any locks,
+    /// so that callbacks could update bars.
+    fn run_progress_callbacks(&self) {
         if !self
-            .need_redraw
+            .progress_crossed
             .swap(false, std::sync::atomic::Ordering::AcqRel)
         {
             return;
         }
-        let mut out = self.out.lock().unwrap();
-        let states = self.states.lock().unwrap();
+        let states: Vec<_> = self.states.lock_unpoisoned().values().cloned().collect();
+        for state in states {
+            let crossed = std::mem::take(&mut state.lock_unpoisoned().crossed);
+            for (callback, threshold) in crossed {
+                callback(threshold);
+            }
+        }
+    }
+
+    /// Whether drawing is disabled by `KYURI_DISABLE`.
+    fn is_disabled(&self) -> bool {
+        self.disabled.load(std::sync::atomic::Ordering::Acquire)
+    }
+
+    /// `draw` (or `draw_event` when `event`), but returns the first IO error when writing.
+    pub(crate) fn try_draw(&self, force: bool, event: bool) -> std::io::Result<()> {
+        if !force && self.is_ticker_enabled() {
+            return Ok(());
+        }
+        if self.is_disabled() {
+            return Ok(());
+        }
+        let Some(_guard) = RenderGuard::enter(self) else {
+            // Called inside a draw or `suspend` on this thread (like by a source or in the closure),
+            // with locks held: defer the draw to the next one instead of deadlocking.
+            self.mark_redraw();
+            return Ok(());
+        };
+        let started = std::time::Instant::now();
+        let now = clock::now();
+        let mut last_draw = self.last_draw.lock();
+        if !force
+            && now.saturating_duration_since(*last_draw) < self.interval()
+            && !self.burst.lock_unpoisoned().available(now, self.interval())
+        {
+            return Ok(());
+        }
+
+        self.poll_sources();
+        if !self.need_redraw.take() {
+            return Ok(());
+        }
+        let mut result = Ok(());
+        let mut out = self.out.lock_unpoisoned();
+        let states = self.states.lock_unpoisoned();
         let is_terminal = self.is_terminal(&mut out);
-        if is_terminal && states.len() > 0 {
-            // Don't clean output when no bars are present
-            self.clear_existing(&mut out);
+        // In ANSI mode, events in a burst are drawn together, as each draw redraws all bars.
+        // Not in file mode, where changed bars are written only once, and removed bars would be lost.
+        if event && is_terminal && now - *last_draw < *self.force_debounce.lock_unpoisoned() {
+            self.mark_redraw();
+            return Ok(());
+        }
+        if is_terminal
+            && (!states.is_empty()
+                || !self.log_lines.lock_unpoisoned().is_empty()
+                || !self.checklist.lock_unpoisoned().is_empty()
+                || self.status_line.lock_unpoisoned().is_some())
+        {
+            // Don't clean output when no bars, log lines or status line are present
+            result = self.clear_existing(&mut out);
         }
 
-        self.draw_inner(&states, &mut out, is_terminal);
+        result = result.and(self.draw_inner(&states, &mut out, is_terminal));
+        // Output of nested `suspend`s (like by a source) goes above the bars, which are drawn again
+        if !self.deferred.lock_unpoisoned().is_empty() {
+            if is_terminal {
+                result = result.and(self.clear_existing(&mut out));
+            }
+            result = result.and(self.write_deferred(&mut out).map(|_| ()));
+            if is_terminal {
+                result = result.and(self.draw_inner(&states, &mut out, is_terminal));
+            }
+        }
+        if let (true, Err(e)) = (is_terminal, &result) {
+            if !matches!(
+                e.kind(),
+                std::io::ErrorKind::Interrupted | std::io::ErrorKind::WouldBlock
+            ) {
+                // Don't keep emitting cursor codes to a terminal gone (or to wherever it's redirected later),
+                // and write all bars again in append mode
+                *self.terminal_lost.lock_unpoisoned() = Some(clock::now());
+                for state in states.values() {
+                    state.lock_unpoisoned().mark_changed(true);
+                }
+                self.mark_redraw();
+            }
+        }
+        #[cfg(feature = "process_title")]
+        self.update_process_title(&states);
+        if let Some(lsp_progress) = self.lsp_progress.lock_unpoisoned().as_mut() {
+            result = result.and(lsp_progress.update(&states));
+        }
+
+        // A subscriber not receiving yet has been notified, and one disconnected is removed
+        self.subscribers.lock_unpoisoned().retain(|subscriber| {
+            !matches!(
+                subscriber.try_send(()),
+                Err(std::sync::mpsc::TrySendError::Disconnected(_))
+            )
+        });
 
+        if !force {
+            self.burst.lock_unpoisoned().spend(now, self.interval());
+        }
+        let mut stats = self.stats.lock_unpoisoned();
+        stats.frames += 1;
+        stats.last_frame_time = started.elapsed();
         *last_draw = now;
+        result
+    }
+
+    /// Called by the ticker, when drawing took longer than the interval.
+    pub(crate) fn add_skipped_frames(&self, skipped: u64) {
+        self.stats.lock_unpoisoned().skipped_frames += skipped;
+    }
+
+    #[cfg(feature = "process_title")]
+    fn update_process_title(&self, states: &BTreeMap<usize, Arc<Mutex<BarState>>>) {
+        let mut process_title = self.process_title.lock_unpoisoned();
+        let Some(process_title) = process_title.as_mut() else {
+            return;
+        };
+        let (mut pos, mut len) = (0u128, 0u128);
+        for state in states.values() {
+            let state = state.lock_unpoisoned();
+            pos += state.pos.min(state.len) as u128;
+            len += state.len as u128;
+        }
+        let percent = (pos * 100).checked_div(len).unwrap_or(100);
+        process_title.update(percent as u64);
+    }
+
+    /// Run the closure of `suspend` called inside a draw or `suspend` on this thread, where the output is locked.
+    ///
+    /// What it writes is deferred until the outer one is done with the output, falling back to stderr
+    /// when a temporary file could not be created.
+    fn suspend_nested<F: FnOnce(&mut Box<dyn Out>) -> R, R>(&self, f: F) -> R {
+        let Ok((deferred, mut out)) = Deferred::create() else {
+            return f(&mut (Box::new(std::io::stderr()) as Box<dyn Out>));
+        };
+        let result = f(&mut out);
+        std::mem::drop(out);
+        self.deferred.lock_unpoisoned().push(deferred);
+        // For a draw returning early, to be written by the next one
+        self.mark_redraw();
+        result
+    }
+
+    /// Write the output deferred by nested `suspend`s. Returns whether there was any.
+    fn write_deferred(&self, out: &mut Box<dyn Out>) -> std::io::Result<bool> {
+        let mut deferred = std::mem::take(&mut *self.deferred.lock_unpoisoned());
+        for deferred in deferred.iter_mut() {
+            deferred.write_to(out)?;
+        }
+        Ok(!deferred.is_empty())
     }
 
     pub(crate) fn suspend<F: FnOnce(&mut Box<dyn Out>) -> R, R>(&self, f: F) -> R {
-        let mut out = self.out.lock().unwrap();
-        let is_terminal = self.is_terminal(&mut out);
+        let Some(_guard) = RenderGuard::enter(self) else {
+            // Called inside a draw or `suspend` on this thread (like by a logger in the closure), where the output
+            // is locked: run the closure directly, and write its output later.
+            return self.suspend_nested(f);
+        };
+        let mut out = self.out.lock_unpoisoned();
+        // Nothing to clear or redraw when drawing is disabled
+        let is_terminal = !self.is_disabled() && self.is_terminal(&mut out);
         if is_terminal {
-            self.clear_existing(&mut out);
+            let _ = self.clear_existing(&mut out);
         }
         let result = f(&mut out);
+        let _ = self.write_deferred(&mut out);
         if is_terminal {
-            let states = self.states.lock().unwrap();
-            self.draw_inner(&states, &mut out, is_terminal);
+            let states = self.states.lock_unpoisoned();
+            let _ = self.draw_inner(&states, &mut out, is_terminal);
         }
         result
     }
@@ -361,6 +2034,39 @@ pub trait Out: std::io::Write + std::io::IsTerminal + Send + Sync {}
 )))]
 impl<T: std::io::Write + std::io::IsTerminal + Send + Sync> Out for T {}
 
+/// Statistics of drawing, returned by `Manager::stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DrawStats {
+    /// How many frames have been drawn.
+    pub frames: u64,
+    /// How many ticks of the ticker have been skipped, as drawing took longer than the interval (like a slow terminal).
+    pub skipped_frames: u64,
+    /// How long the last frame took to draw.
+    pub last_frame_time: std::time::Duration,
+}
+
+/// The kind of the output stream of a `Manager`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetKind {
+    /// `Manager::with_stdout`, the default.
+    Stdout,
+    /// `Manager::with_stderr`.
+    Stderr,
+    /// `Manager::with_file` or `Manager::with_path`.
+    File,
+    /// `Manager::with_output`.
+    Custom,
+}
+
+/// How `Manager::with_path` opens the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenMode {
+    /// Write after the existing content, like a log.
+    Append,
+    /// Remove the existing content first.
+    Truncate,
+}
+
 /// The manager for progress bars. It's expected for users to create a `Manager`, create progress bars from it,
 /// and drop it when all work has been done.
 ///
@@ -373,21 +2079,102 @@ impl Manager {
     /// Create a new `Manager` to stdout.
     ///
     /// The `interval` parameter specifies the minimum interval between two unforced draws.
+    ///
+    /// Operators could tune or silence progress of shipped programs with environment variables, read here:
+    ///
+    /// - `KYURI_INTERVAL`: The interval in milliseconds, instead of `interval`.
+    /// - `KYURI_FORCE_ANSI`: `1` or `0`, like `force_ansi`, taking precedence over `force_ansi` and `auto_ansi`.
+    /// - `KYURI_MAX_BARS`: The maximum number of bars drawn in ANSI mode, like `set_max_bars`.
+    /// - `KYURI_DISABLE`: `1` to draw no bars, while output of `suspend` and `log_line` is still written.
     pub fn new(interval: std::time::Duration) -> Self {
         Manager {
             inner: Arc::new(ManagerInner {
                 states: Mutex::new(BTreeMap::new()),
                 next_id: AtomicUsize::new(0),
-                interval,
+                interval: Mutex::new(interval),
                 out: Arc::new(Mutex::new(Box::new(std::io::stdout()))),
-                last_draw: Mutex::new(std::time::Instant::now() - interval),
-                last_lines: AtomicUsize::new(0),
+                target_kind: Mutex::new(TargetKind::Stdout),
+                output_path: Mutex::new(None),
+                last_draw: LastDraw::new(std::time::Instant::now() - interval),
+                terminal_lost: Mutex::new(None),
+                disabled: AtomicBool::new(false),
+                deferred: Mutex::new(Vec::new()),
+                env_ansi: Mutex::new(None),
+                max_bars: Mutex::new(None),
                 ansi: Mutex::new(None),
-                need_redraw: AtomicBool::new(false),
+                need_redraw: RedrawFlag::new(false),
+                progress_crossed: Arc::new(AtomicBool::new(false)),
                 ticker: Mutex::new(None),
                 force_when_finished: AtomicBool::new(true),
+                force_debounce: Mutex::new(std::time::Duration::ZERO),
+                burst: Mutex::new(burst::TokenBucket::new(0)),
+                frame_filter: Mutex::new(None),
+                encoder: Mutex::new(None),
+                backend: Mutex::new(None),
+                ansi_backend: Mutex::new(backend::Ansi::default()),
+                accessible: Mutex::new(None),
+                heartbeat: Mutex::new(None),
+                recorder: Mutex::new(None),
+                sequence: Mutex::new(None),
+                panel: Mutex::new(None),
+                split: Mutex::new(false),
+                updater: Mutex::new(None),
+                subscribers: Mutex::new(Vec::new()),
+                status_line: Mutex::new(None),
+                verbosity: Mutex::new(Verbosity::Normal),
+                stats: Mutex::new(DrawStats::default()),
+                render_thread: AtomicBool::new(false),
+                isolate_bidi: AtomicBool::new(false),
+                byte_precision: AtomicUsize::new(2),
+                stable_width: AtomicBool::new(false),
+                log_lines: Mutex::new(VecDeque::new()),
+                checklist: Mutex::new(Vec::new()),
+                log_capacity: AtomicUsize::new(5),
+                templates: Mutex::new(HashMap::new()),
+                theme: Mutex::new(Arc::new(Theme::default())),
+                color: Mutex::new(None),
+                detected_color: ColorSupport::detect(),
+                effects: Mutex::new(Effects::default()),
+                frame: AtomicUsize::new(0),
+                non_terminal_template: Mutex::new(None),
+                #[cfg(feature = "process_title")]
+                process_title: Mutex::new(None),
+                lsp_progress: Mutex::new(None),
+                ci_log: Mutex::new(None),
+                tqdm: Mutex::new(false),
+                milestone_step: Mutex::new(None),
+                #[cfg(all(unix, feature = "terminal_lock"))]
+                terminal_lock: Mutex::new(None),
             }),
         }
+        .with_env(|name| std::env::var(name).ok())
+    }
+
+    /// Apply overrides of `KYURI_*` environment variables, looked up by `var`. Invalid values are ignored.
+    fn with_env(self, var: impl Fn(&str) -> Option<String>) -> Self {
+        let flag = |name| match var(name)?.trim() {
+            "1" | "true" | "yes" => Some(true),
+            "0" | "false" | "no" => Some(false),
+            _ => None,
+        };
+        if let Some(millis) = var("KYURI_INTERVAL").and_then(|v| v.trim().parse().ok()) {
+            let interval = std::time::Duration::from_millis(millis);
+            *self.inner.interval.lock_unpoisoned() = interval;
+            let now = std::time::Instant::now();
+            *self.inner.last_draw.lock() = now.checked_sub(interval).unwrap_or(now);
+        }
+        if let Some(force) = flag("KYURI_FORCE_ANSI") {
+            *self.inner.env_ansi.lock_unpoisoned() = Some(force);
+        }
+        if let Some(max) = var("KYURI_MAX_BARS").and_then(|v| v.trim().parse().ok()) {
+            *self.inner.max_bars.lock_unpoisoned() = Some(max);
+        }
+        if let Some(disabled) = flag("KYURI_DISABLE") {
+            self.inner
+                .disabled
+                .store(disabled, std::sync::atomic::Ordering::Release);
+        }
+        self
     }
 
     fn mark_redraw(&self) {
@@ -396,430 +2183,4996 @@ impl Manager {
 
     /// Set the `Manager` to write to stdout.
     pub fn with_stdout(self) -> Self {
-        *self.inner.out.lock().unwrap() = Box::new(std::io::stdout());
+        *self.inner.out.lock_unpoisoned() = Box::new(std::io::stdout());
+        *self.inner.target_kind.lock_unpoisoned() = TargetKind::Stdout;
+        *self.inner.output_path.lock_unpoisoned() = None;
         self.mark_redraw();
         self
     }
 
     /// Set the `Manager` to write to stderr.
     pub fn with_stderr(self) -> Self {
-        *self.inner.out.lock().unwrap() = Box::new(std::io::stderr());
+        *self.inner.out.lock_unpoisoned() = Box::new(std::io::stderr());
+        *self.inner.target_kind.lock_unpoisoned() = TargetKind::Stderr;
+        *self.inner.output_path.lock_unpoisoned() = None;
         self.mark_redraw();
         self
     }
 
     /// Set the `Manager` to write to a file.
     pub fn with_file(self, file: std::fs::File) -> Self {
-        *self.inner.out.lock().unwrap() = Box::new(file);
+        *self.inner.out.lock_unpoisoned() = Box::new(file);
+        *self.inner.target_kind.lock_unpoisoned() = TargetKind::File;
+        *self.inner.output_path.lock_unpoisoned() = None;
         self.mark_redraw();
         self
     }
 
-    /// Let `Manager` automatically detect whether it's writing to a terminal and use ANSI or not.
-    pub fn auto_ansi(self) -> Self {
-        *self.inner.ansi.lock().unwrap() = None;
-        self.mark_redraw();
-        self
+    /// Set the `Manager` to write to the file at `path`, opened in `mode` (and created if not existing),
+    /// so it could be reopened by `reopen_output`.
+    ///
+    /// With `create_dirs`, missing parent directories are created too.
+    pub fn with_path<P: AsRef<std::path::Path>>(
+        self,
+        path: P,
+        mode: OpenMode,
+        create_dirs: bool,
+    ) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        if let (true, Some(parent)) = (create_dirs, path.parent()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut options = std::fs::OpenOptions::new();
+        match mode {
+            OpenMode::Append => options.append(true),
+            OpenMode::Truncate => options.write(true).truncate(true),
+        };
+        let file = options.create(true).open(path)?;
+        let manager = self.with_file(file);
+        *manager.inner.output_path.lock_unpoisoned() = Some(path.to_path_buf());
+        Ok(manager)
     }
 
-    /// Force `Manager` to use ANSI escape codes or not.
-    pub fn force_ansi(self, force: bool) -> Self {
-        *self.inner.ansi.lock().unwrap() = Some(force);
+    /// Open the file at the path given to `with_path` again (always for appending), and write to it from now on.
+    ///
+    /// After a log rotation (like by logrotate) renamed or removed the file, the manager would otherwise keep writing
+    /// into the old one. Call this when the rotation is done, like in the handler of `SIGHUP`.
+    /// Pending changes are written to the old file, and all bars are written again to the new one.
+    ///
+    /// Returns an error of `ErrorKind::InvalidInput` when the output is not from `with_path`,
+    /// or the error of opening the file, in which case the old file is kept.
+    pub fn reopen_output(&self) -> std::io::Result<()> {
+        let path = self.inner.output_path.lock_unpoisoned().clone();
+        let Some(path) = path else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "output is not opened from a path",
+            ));
+        };
+        let file = std::fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)?;
+        let ansi = *self.inner.ansi.lock_unpoisoned();
+        let (mut old, _, _) = self
+            .inner
+            .swap_target(Box::new(file), TargetKind::File, ansi);
+        old.flush()
+    }
+
+    /// Set the `Manager` to write to a custom output stream.
+    pub fn with_output<T: Out + 'static>(self, out: T) -> Self {
+        *self.inner.out.lock_unpoisoned() = Box::new(out);
+        *self.inner.target_kind.lock_unpoisoned() = TargetKind::Custom;
+        *self.inner.output_path.lock_unpoisoned() = None;
         self.mark_redraw();
         self
     }
 
-    /// Ticker enables a background thread to draw progress bars at a fixed interval.
-    ///
-    /// When ticker is enabled, unforced draw would be ignored.
-    pub fn set_ticker(&self, set_ticker: bool) {
-        let mut ticker = self.inner.ticker.lock().unwrap();
-        if set_ticker && ticker.is_none() {
-            *ticker = Some(Ticker::new(self.inner.clone()));
-        } else if !set_ticker && ticker.is_some() {
-            *ticker = None;
-        }
+    /// Get the kind of the output stream set by `with_stdout`, `with_stderr`, `with_file` or `with_output`.
+    pub fn target_kind(&self) -> TargetKind {
+        *self.inner.target_kind.lock_unpoisoned()
     }
 
-    /// If manager shall forcely draw when pos == len without explicitly calling finish().
+    /// Return whether bars are drawn in ANSI mode (redrawn in place) now, rather than appended as lines.
     ///
-    /// Default is true.
-    pub fn force_draw_when_finished(&self, force: bool) {
-        self.inner
-            .force_when_finished
-            .store(force, std::sync::atomic::Ordering::Release);
+    /// This is decided in the same way as drawing: by `force_ansi`, or whether the output is a terminal
+    /// (and whether the terminal lock is held, see `with_terminal_lock`).
+    pub fn is_ansi_active(&self) -> bool {
+        let mut out = self.inner.out.lock_unpoisoned();
+        self.inner.is_terminal(&mut out)
     }
 
-    /// Create a new progress bar.
-    ///
-    /// - `len`: The total length of the progress bar.
-    /// - `message`: The message of the bar. Use `{msg}` in the template to refer to this.
-    /// - `template`: The template of the bar.
-    /// - `visible`: Whether the bar is visible.
+    /// Get the width of the output in columns, used to count lines when redrawing.
     ///
-    /// This makes a forced draw when visible is true.
-    pub fn create_bar(&self, len: u64, message: &str, template: &str, visible: bool) -> Bar {
-        let id = self
-            .inner
-            .next_id
-            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-        let bar_state = Arc::new(Mutex::new(BarState {
-            len,
-            pos: 0,
-            message: message.to_string(),
-            template: Template::new(template),
-            created_at: std::time::Instant::now(),
-            visible,
-            need_redraw: true,
-        }));
-
-        self.inner
-            .states
-            .lock()
-            .unwrap()
-            .insert(id, bar_state.clone());
-
-        if visible {
-            self.mark_redraw();
-            self.draw(true);
-        }
-
-        Bar {
-            manager: Arc::downgrade(&self.inner),
-            id,
-        }
+    /// It's 80 when the width is unknown, like when the output is not a terminal or the `console_width` feature is disabled.
+    pub fn width(&self) -> usize {
+        let out = self.inner.out.lock_unpoisoned();
+        get_width(out.as_ref()) as usize
     }
 
-    /// Draw all progress bars. In most cases it's not necessary to call this manually.
-    ///
-    /// If nothing changed, it would not draw no matter what.
-    ///
-    /// If ticker is enabled, unforced draw would be ignored. Otherwise, it would only draw when the interval has passed.
+    /// Coordinate with other processes sharing the terminal by an advisory lock (`flock`) on `lockfile`,
+    /// or on `/dev/tty` when `None`.
     ///
-    /// Progress bars would be drawn by the order of `Bar` creation. In ANSI mode, it would clear the previous output.
+    /// Only the process holding the lock draws in ANSI mode. Others fall back to append mode (like writing to a file),
+    /// and try to take the lock again on each draw. The lock is released when the manager is dropped.
+    #[cfg(all(unix, feature = "terminal_lock"))]
+    pub fn with_terminal_lock<P: AsRef<std::path::Path>>(
+        self,
+        lockfile: Option<P>,
+    ) -> std::io::Result<Self> {
+        let path = lockfile
+            .as_ref()
+            .map_or(std::path::Path::new("/dev/tty"), |p| p.as_ref());
+        *self.inner.terminal_lock.lock_unpoisoned() = Some(termlock::TerminalLock::new(path)?);
+        self.mark_redraw();
+        Ok(self)
+    }
+
+    /// Let `Manager` automatically detect whether it's writing to a terminal and use ANSI or not.
     ///
-    /// Finally, when output is not a terminal, bars would be drawn only when it needs to be redrawn.
-    pub fn draw(&self, force: bool) {
-        self.inner.draw(force);
+    /// `KYURI_FORCE_ANSI` takes precedence when set, see `Manager::new`.
+    pub fn auto_ansi(self) -> Self {
+        *self.inner.ansi.lock_unpoisoned() = None;
+        self.mark_redraw();
+        self
     }
 
-    /// Hide all progress bars, run the closure, and show them again like indicatif::MultiProgress::suspend.
+    /// Force `Manager` to use ANSI escape codes or not.
     ///
-    /// This method is used for implementing integrations with other libraries that may print to the terminal.
+    /// Even when forced, the manager falls back to append mode once writing in ANSI mode fails,
+    /// like after the terminal hung up, so cursor codes don't end up in wherever the output is redirected.
+    /// It's checked every second whether the output is a terminal again, to go back to ANSI mode.
     ///
-    /// When output is not a terminal, the closure would still be run but nothing would be done to the progress bars.
-    pub fn suspend<F: FnOnce(&mut Box<dyn Out>) -> R, R>(&self, f: F) -> R {
-        self.inner.suspend(f)
+    /// `KYURI_FORCE_ANSI` takes precedence when set, see `Manager::new`.
+    pub fn force_ansi(self, force: bool) -> Self {
+        *self.inner.ansi.lock_unpoisoned() = Some(force);
+        self.mark_redraw();
+        self
     }
 
-    /// Create a writer for integration with other libraries.
+    /// Load named templates from a file, so that end users could customize the display without recompiling.
+    ///
+    /// Each line of the file looks like `name=template`. Empty lines and lines starting with `#` are ignored.
+    /// Use `template` to get a loaded template by its name.
+    ///
+    /// When the file could not be read, or a line is not a valid template (see `Template::parse`),
+    /// `on_warning` is called with the reason, and the line is skipped.
+    pub fn with_templates_from<P: AsRef<std::path::Path>, F: FnMut(&str)>(
+        self,
+        path: P,
+        mut on_warning: F,
+    ) -> Self {
+        let path = path.as_ref();
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) => {
+                on_warning(&format!("cannot read {}: {}", path.display(), e));
+                return self;
+            }
+        };
+        let mut templates = self.inner.templates.lock_unpoisoned();
+        for (i, line) in content.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((name, template)) = line.split_once('=') else {
+                on_warning(&format!(
+                    "{}:{}: expected name=template",
+                    path.display(),
+                    i + 1
+                ));
+                continue;
+            };
+            match Template::parse(template) {
+                Ok(template) => {
+                    templates.insert(name.trim().to_string(), template);
+                }
+                Err(e) => on_warning(&format!("{}:{}: {}", path.display(), i + 1, e)),
+            }
+        }
+        std::mem::drop(templates);
+        self
+    }
+
+    /// Get the template named `name` loaded by `with_templates_from`, or `default` if there is no such template.
+    pub fn template(&self, name: &str, default: impl Into<Template>) -> Template {
+        match self.inner.templates.lock_unpoisoned().get(name) {
+            Some(template) => template.clone(),
+            None => default.into(),
+        }
+    }
+
+    /// Set the theme of bars, like `Theme::ascii()`. Default is `Theme::default()`.
+    pub fn with_theme(self, theme: Theme) -> Self {
+        *self.inner.theme.lock_unpoisoned() = Arc::new(theme);
+        self.mark_redraw();
+        self
+    }
+
+    /// Set the template used for all bars when output is not a terminal, like a concise text-only template for log files.
+    ///
+    /// It's used instead of the template of bars, unless a bar has its own one set by `Bar::set_non_terminal_template`.
+    /// The finished and collapsed templates of bars still take precedence.
+    pub fn with_non_terminal_template(self, template: impl Into<Template>) -> Self {
+        *self.inner.non_terminal_template.lock_unpoisoned() = Some(Arc::new(template.into()));
+        self.mark_redraw();
+        self
+    }
+
+    /// Wrap each bar in a collapsible group of the CI service when output is not a terminal, and print it only
+    /// when it starts, at each 10% milestone, and when it's finished, instead of on each change.
+    /// See `ci::CiMode`, and `CiMode::detect` to detect the service.
+    ///
+    /// Pass `None` to disable it.
+    pub fn with_ci_mode(self, mode: Option<ci::CiMode>) -> Self {
+        let tqdm = *self.inner.tqdm.lock_unpoisoned();
+        let step = *self.inner.milestone_step.lock_unpoisoned();
+        *self.inner.ci_log.lock_unpoisoned() = (mode.is_some() || tqdm || step.is_some())
+            .then(|| ci::CiLog::new(mode, step.unwrap_or(ci::DEFAULT_MILESTONE_STEP)));
+        self.mark_redraw();
+        self
+    }
+
+    /// Render bars in a single line like tqdm when output is not a terminal, and print them only when they
+    /// start, at each 10% milestone, and when they're finished, for familiar log output in files and CI:
+    ///
+    /// ```text
+    /// Download:  50%|█████     | 50/100 [00:10<00:10, 5 it/s]
+    /// ```
+    ///
+    /// This takes precedence over templates of bars. It could be combined with `with_ci_mode`.
+    pub fn with_tqdm_style(self) -> Self {
+        *self.inner.tqdm.lock_unpoisoned() = true;
+        self.inner
+            .ci_log
+            .lock_unpoisoned()
+            .get_or_insert_with(|| ci::CiLog::new(None, ci::DEFAULT_MILESTONE_STEP));
+        self.mark_redraw();
+        self
+    }
+
+    /// Print bars only when they start, each time they cross a multiple of `step` percent, and when they're finished
+    /// or failed, when output is not a terminal. `step` is clamped to 1 to 100.
+    ///
+    /// This is instead of printing changes at most every interval, which is noisy for long tasks in CI logs.
+    /// It also sets the step of `with_ci_mode` and `with_tqdm_style`, which is 10 by default.
+    pub fn with_milestones(self, step: u8) -> Self {
+        *self.inner.milestone_step.lock_unpoisoned() = Some(step);
+        let mut ci_log = self.inner.ci_log.lock_unpoisoned();
+        let mode = ci_log.as_ref().and_then(ci::CiLog::mode);
+        *ci_log = Some(ci::CiLog::new(mode, step));
+        std::mem::drop(ci_log);
+        self.mark_redraw();
+        self
+    }
+
+    /// Make output friendly to screen readers with `Some(interval)`, or `None` to disable it: cursors are never moved
+    /// (like `force_ansi(false)`), and bars are printed as sentences without glyphs, like
+    /// `Download: 3 of 10, 30 percent`, when they start, when they're finished or failed, and in between at most
+    /// every `interval`, like 10 seconds, so that screen readers are not flooded.
+    ///
+    /// This takes precedence over templates of bars, `with_ci_mode` and `with_tqdm_style`.
+    pub fn with_accessible(self, interval: Option<std::time::Duration>) -> Self {
+        *self.inner.accessible.lock_unpoisoned() = interval.map(accessible::AccessibleLog::new);
+        self.mark_redraw();
+        self
+    }
+
+    /// Prefix each line with the sequence number of its frame, like `[42] Download 10/100`, when output is
+    /// not a terminal. Sequence numbers start from 1, and increase by one for each frame written.
+    ///
+    /// Tools processing the output could tell which lines are written at once, and find lines interleaved from
+    /// other writers sharing the file, which don't have the prefix. Log lines (`log_line`) are not numbered.
+    pub fn with_sequence_numbers(self, enabled: bool) -> Self {
+        *self.inner.sequence.lock_unpoisoned() = enabled.then_some(0);
+        self
+    }
+
+    /// Write frames with `backend`, like `backend::Json`, instead of picking ANSI or append mode by the output.
+    ///
+    /// Whether bars are redrawn in place follows `DrawBackend::redraws`, regardless of `force_ansi`,
+    /// so it should be set before bars are drawn.
+    pub fn with_backend<B: backend::DrawBackend + 'static>(self, backend: B) -> Self {
+        *self.inner.backend.lock_unpoisoned() = Some(Box::new(backend));
+        self.mark_redraw();
+        self
+    }
+
+    /// Also record what's drawn into an [asciinema](https://asciinema.org) cast (v2) at `path`, with the time of
+    /// each write, so sessions showing progress could be replayed (like with `replay::play` or `asciinema play`)
+    /// or attached to bug reports.
+    ///
+    /// The width in the header is of the output when recording starts, and the height is always 24.
+    /// Output written in `suspend` is not recorded. Errors of writing the recording are ignored.
+    pub fn with_recording<P: AsRef<std::path::Path>>(self, path: P) -> std::io::Result<Self> {
+        let file = std::fs::File::create(path)?;
+        let width = get_width(self.inner.out.lock_unpoisoned().as_ref());
+        let recorder = record::Recorder::new(Box::new(std::io::BufWriter::new(file)), width)?;
+        *self.inner.recorder.lock_unpoisoned() = Some(recorder);
+        Ok(self)
+    }
+
+    /// Set the color support of the terminal, or `None` to detect it with `ColorSupport::detect`.
+    ///
+    /// Style tags in templates are degraded to the nearest supported color. When output is not a terminal,
+    /// they are always removed.
+    pub fn with_color_support(self, support: Option<ColorSupport>) -> Self {
+        *self.inner.color.lock_unpoisoned() = support;
+        self.mark_redraw();
+        self
+    }
+
+    /// Set the effect for bars whose position has not changed for `after`, before finished, or `None` to disable it.
+    ///
+    /// Pulse effects need the ticker (`set_ticker`) to alternate when nothing else triggers a draw.
+    pub fn set_stalled_effect(&self, after: std::time::Duration, effect: Option<Effect>) {
+        self.inner.effects.lock_unpoisoned().stalled = effect.map(|effect| (after, effect));
+        self.mark_redraw();
+    }
+
+    /// Set the effect for bars marked as failed by `Bar::fail`, or `None` to disable it.
+    pub fn set_failed_effect(&self, effect: Option<Effect>) {
+        self.inner.effects.lock_unpoisoned().failed = effect;
+        self.mark_redraw();
+    }
+
+    /// Show the aggregate progress of all bars in the process title, like `myprog [42%]`, so that `ps` and `top`
+    /// show it. It's updated on each draw. The original title is restored when disabled or the manager is dropped.
+    ///
+    /// This is only supported on Linux, where the title is limited to 15 bytes. Otherwise, it does nothing.
+    #[cfg(feature = "process_title")]
+    pub fn set_process_title(&self, enabled: bool) {
+        let mut process_title = self.inner.process_title.lock_unpoisoned();
+        if enabled && process_title.is_none() {
+            *process_title = Some(title::ProcessTitle::new());
+        } else if !enabled {
+            *process_title = None;
+        }
+        std::mem::drop(process_title);
+        self.mark_redraw();
+    }
+
+    /// Also report progress of visible bars to `writer` as LSP `$/progress` notifications, or `None` to stop.
+    ///
+    /// This is for tools embedded in editors, like language servers, to show progress natively in the IDE.
+    /// Each bar is a work done progress with token `"kyuri-<id>"`: it begins with the message of the bar as the
+    /// title, reports changes of the percentage (unless len = 0) or message on each draw, and ends when the bar is
+    /// finished, failed, hidden or dropped. Notifications are framed with a `Content-Length` header.
+    ///
+    /// Before beginning, the token is created with a `window/workDoneProgress/create` request, whose id is the token;
+    /// its response is not waited for, as `writer` is write-only. Progresses still shown end when disabled or the
+    /// manager is dropped.
+    pub fn set_lsp_progress(&self, writer: Option<impl std::io::Write + Send + 'static>) {
+        let progress = writer.map(|writer| lsp::LspProgress::new(Box::new(writer)));
+        // The old one ends its progresses when dropped, outside the lock
+        let old = std::mem::replace(&mut *self.inner.lsp_progress.lock_unpoisoned(), progress);
+        std::mem::drop(old);
+        self.mark_redraw();
+    }
+
+    /// Move `bar` from its manager to this one. See `Bar::migrate_to`.
+    pub fn adopt_bar(&self, bar: &mut Bar) {
+        bar.migrate_to(self);
+    }
+
+    /// Save the state of all bars, to continue them in another process with `restore_state`.
+    /// See `persist` for details.
+    pub fn saved_state(&self) -> persist::SavedState {
+        let states: Vec<_> = self
+            .inner
+            .states
+            .lock_unpoisoned()
+            .values()
+            .cloned()
+            .collect();
+        let bars = states
+            .iter()
+            .map(|state| {
+                let state = state.lock_unpoisoned();
+                persist::SavedBar {
+                    pos: state.pos,
+                    len: state.len,
+                    message: state.message.clone(),
+                    template: Some(state.template.source().to_string()),
+                    elapsed: state.elapsed(),
+                    visible: state.visible,
+                    failed: state.failed,
+                    fields: state.fields.clone(),
+                }
+            })
+            .collect();
+        persist::SavedState { bars }
+    }
+
+    /// Create bars continuing from `saved`, and return them in the saved order.
+    ///
+    /// Bars saved without a template use the template of the theme.
+    pub fn restore_state(&self, saved: persist::SavedState) -> Vec<Bar> {
+        let bars: Vec<_> = saved
+            .bars
+            .into_iter()
+            .map(|saved| {
+                let template = match &saved.template {
+                    Some(template) => Template::new(template),
+                    None => self.default_template(),
+                };
+                let bar = self
+                    .inner
+                    .create_bar(saved.len, &saved.message, template, false, None);
+                if let Some((_, state)) = bar.get_manager_and_state() {
+                    let mut state = state.lock_unpoisoned();
+                    let now = clock::now();
+                    state.created_at = now.checked_sub(saved.elapsed).unwrap_or(now);
+                    state.pos = saved.pos;
+                    state.update_finished();
+                    state.failed = saved.failed;
+                    state.fields = saved.fields;
+                    state.visible = saved.visible;
+                    state.mark_changed(true);
+                }
+                bar
+            })
+            .collect();
+        self.mark_redraw();
+        self.inner.draw(false);
+        bars
+    }
+
+    /// Save the state of all bars in a compact binary format, to continue them in another process
+    /// with `deserialize_state`. See `persist` for details.
+    ///
+    /// Returns an error of `InvalidInput` when a string or the number of bars is longer than `u32::MAX`.
+    pub fn serialize_state(&self) -> std::io::Result<Vec<u8>> {
+        self.saved_state().to_bytes()
+    }
+
+    /// Create bars continuing from `bytes` of `serialize_state`, and return them in the saved order.
+    pub fn deserialize_state(&self, bytes: &[u8]) -> Result<Vec<Bar>, persist::DecodeError> {
+        Ok(self.restore_state(persist::SavedState::from_bytes(bytes)?))
+    }
+
+    /// Get the template of the current theme, which could be used when creating bars.
+    pub fn default_template(&self) -> Template {
+        Template::new(&self.inner.theme.lock_unpoisoned().template)
+    }
+
+    /// Ticker enables a background thread to draw progress bars at a fixed interval.
+    ///
+    /// When ticker is enabled, unforced draw would be ignored.
+    /// When drawing takes longer than the interval (like a slow terminal), the ticks missed are skipped
+    /// rather than queued, and counted in `stats`.
+    ///
+    /// With `with_render_thread`, the ticker is mandatory, and `set_ticker(false)` is ignored.
+    pub fn set_ticker(&self, set_ticker: bool) {
+        if !set_ticker
+            && self
+                .inner
+                .render_thread
+                .load(std::sync::atomic::Ordering::Acquire)
+        {
+            return;
+        }
+        self.set_ticker_inner(set_ticker);
+    }
+
+    fn set_ticker_inner(&self, set_ticker: bool) {
+        let mut ticker = self.inner.ticker.lock_unpoisoned();
+        if set_ticker && ticker.is_none() {
+            *ticker = Some(Ticker::new(self.inner.clone()));
+        } else if !set_ticker && ticker.is_some() {
+            *ticker = None;
+        }
+    }
+
+    /// Change the minimum interval between two unforced draws, which is also the interval of the ticker.
+    ///
+    /// The ticker picks up the new interval immediately, counting from its last tick, so applications could
+    /// slow down rendering when backgrounded or speed it up when interactive.
+    pub fn set_interval(&self, interval: std::time::Duration) {
+        self.inner.set_interval(interval);
+    }
+
+    /// Get the minimum interval between two unforced draws.
+    pub fn interval(&self) -> std::time::Duration {
+        self.inner.interval()
+    }
+
+    /// Render on the ticker thread only, so that bar methods like `inc` and `set_pos` never write to the output,
+    /// which is useful for latency-sensitive callers, like updating bars inside request handlers.
+    ///
+    /// This enables the ticker, and keeps it enabled. Forced draws caused by bar events (creating, finishing,
+    /// dropping and showing/hiding bars) wake up the ticker thread to draw, rather than drawing on the caller's thread.
+    /// Explicit `draw`, `suspend` and writers still write on the caller's thread.
+    pub fn with_render_thread(self) -> Self {
+        self.inner
+            .render_thread
+            .store(true, std::sync::atomic::Ordering::Release);
+        self.set_ticker_inner(true);
+        self
+    }
+
+    /// Debounce forced draws caused by bar events (creating, finishing, dropping and showing/hiding bars) in ANSI mode:
+    /// such a draw is skipped if the last draw was within `debounce`, like 20ms. Default is zero (not debounced).
+    ///
+    /// Each forced draw redraws all bars, so bursts of short tasks could dominate the output. With this, changes in a burst
+    /// are drawn together at the next draw. Enable the ticker (`set_ticker`) to make sure the next draw comes soon.
+    /// Explicit `draw(true)` calls are not debounced, and bars are still written one by one when output is not a terminal.
+    pub fn with_force_debounce(self, debounce: std::time::Duration) -> Self {
+        *self.inner.force_debounce.lock_unpoisoned() = debounce;
+        self
+    }
+
+    /// Draw a line rendered from `template` under the bars in ANSI mode, even when there are no bars,
+    /// or `None` to remove it. It's redrawn on every draw.
+    /// When removed with no bars left, its last line is kept, like the last frame of bars.
+    ///
+    /// It's rendered like a bar without len, so tags of all bars (like `{active_bars}`) are useful here,
+    /// and `{elapsed}` is the time since the status line is set, like the uptime with the ticker:
+    ///
+    /// ```
+    /// let manager = kyuri::Manager::new(std::time::Duration::from_secs(1)).with_stderr();
+    /// manager.set_status_line(Some("Up {elapsed}, {active_bars} running"));
+    /// manager.set_ticker(true);
+    /// ```
+    pub fn set_status_line(&self, template: Option<impl Into<Template>>) {
+        let state =
+            template.map(|template| self.inner.new_state(0, "", template.into(), true, None));
+        *self.inner.status_line.lock_unpoisoned() = state;
+        self.mark_redraw();
+    }
+
+    /// Draw a box (top and bottom rules with a title) around the bars in ANSI mode, or `None` to remove it.
+    /// See `panel::Panel`.
+    ///
+    /// It spans the width of the output, and is not drawn when no bars are visible.
+    pub fn set_panel(&self, panel: Option<panel::Panel>) {
+        *self.inner.panel.lock_unpoisoned() = panel;
+        self.mark_redraw();
+    }
+
+    /// Draw bars of `layout::Column::Right` next to other bars in ANSI mode, instead of below them.
+    /// See `layout` for how widths of columns are decided.
+    pub fn set_split(&self, split: bool) {
+        *self.inner.split.lock_unpoisoned() = split;
+        self.mark_redraw();
+    }
+
+    /// Post-process each frame with `filter` before writing, like adding prefixes (e.g. systemd `<6>` priorities),
+    /// stripping emoji or adding borders.
+    ///
+    /// A frame is the text of all lines drawn at once, each ending with `\n`; when output is not a terminal, it's only
+    /// bars changed. In ANSI mode, cursor movements to clear the last frame are not part of it, and lines it takes are
+    /// counted after filtering. `filter` is called with locks held, so it must not call methods of the manager or bars.
+    pub fn set_frame_filter<F: Fn(String) -> String + Send + Sync + 'static>(&self, filter: F) {
+        *self.inner.frame_filter.lock_unpoisoned() = Some(Box::new(filter));
+        self.mark_redraw();
+    }
+
+    /// Remove the filter set by `set_frame_filter`.
+    pub fn clear_frame_filter(&self) {
+        *self.inner.frame_filter.lock_unpoisoned() = None;
+        self.mark_redraw();
+    }
+
+    /// Encode each frame (after `set_frame_filter`) with `encoder` instead of UTF-8 before writing,
+    /// for terminals in other encodings. See `encoding` for encoders provided.
+    ///
+    /// In ANSI mode, lines a frame takes are still counted from the text, so `encoder` should keep the widths of lines.
+    /// Output written in `suspend` is not encoded.
+    pub fn set_encoder<F: Fn(&str) -> Vec<u8> + Send + Sync + 'static>(&self, encoder: F) {
+        *self.inner.encoder.lock_unpoisoned() = Some(Box::new(encoder));
+        self.mark_redraw();
+    }
+
+    /// Remove the encoder set by `set_encoder`, and write in UTF-8 again.
+    pub fn clear_encoder(&self) {
+        *self.inner.encoder.lock_unpoisoned() = None;
+        self.mark_redraw();
+    }
+
+    /// When output is not a terminal, write a line like `still running, 42% after 0:10:00` when nothing has been
+    /// written for `idle` while bars are in progress, so logs tell slow tasks from hung ones. `None` to disable it.
+    ///
+    /// The percentage is of all bars, and the time is of the oldest bar. It's checked on draws, so enable the ticker
+    /// (`set_ticker`) to get it when nothing else draws.
+    pub fn set_heartbeat(&self, idle: Option<std::time::Duration>) {
+        *self.inner.heartbeat.lock_unpoisoned() = idle.map(|idle| Heartbeat {
+            idle,
+            last_output: clock::now(),
+        });
+    }
+
+    /// Draw unforced draws within the interval immediately, as long as tokens are left in a bucket of `capacity`,
+    /// where a token is refilled per interval, and each unforced draw spends one. Default is 0 (disabled).
+    ///
+    /// Changes after an idle period are drawn without delay for low latency feedback, and a busy stream of changes
+    /// settles to the interval, without permanently lowering it.
+    pub fn with_burst(self, capacity: u32) -> Self {
+        *self.inner.burst.lock_unpoisoned() = burst::TokenBucket::new(capacity);
+        self
+    }
+
+    /// Draw at most `max` visible bars in ANSI mode, followed by a line like `... and 3 more`, or `None` for no limit.
+    ///
+    /// Bars are drawn in the order of creation. This doesn't apply when output is not a terminal.
+    pub fn set_max_bars(&self, max: Option<usize>) {
+        *self.inner.max_bars.lock_unpoisoned() = max;
+        self.mark_redraw();
+    }
+
+    /// Set how much is shown for bars, like `Verbosity::Detailed` to show detail blocks of bars (`Bar::set_detail`).
+    /// This makes an unforced draw.
+    pub fn set_verbosity(&self, verbosity: Verbosity) {
+        *self.inner.verbosity.lock_unpoisoned() = verbosity;
+        self.mark_redraw();
+        self.inner.draw(false);
+    }
+
+    /// Get the verbosity set by `set_verbosity`.
+    pub fn verbosity(&self) -> Verbosity {
+        *self.inner.verbosity.lock_unpoisoned()
+    }
+
+    /// Get the statistics of drawing, like how many frames the ticker has skipped.
+    pub fn stats(&self) -> DrawStats {
+        *self.inner.stats.lock_unpoisoned()
+    }
+
+    /// Get visible bars as plain values for GUI progress widgets, see the `gui` module.
+    pub fn gui_snapshot(&self) -> gui::ManagerSnapshot {
+        let states = self.inner.states.lock_unpoisoned();
+        let options = self.inner.plain_options(&states);
+        gui::snapshot(&states, &options)
+    }
+
+    /// Get a sender of `ffi::Update`, for other threads and languages to drive bars without holding `Bar`s.
+    ///
+    /// Updates are applied before the next draw, so they are applied by the ticker thread with `set_ticker`.
+    pub fn updater_channel(&self) -> std::sync::mpsc::Sender<ffi::Update> {
+        let mut updater = self.inner.updater.lock_unpoisoned();
+        let (sender, _) = updater.get_or_insert_with(std::sync::mpsc::channel);
+        sender.clone()
+    }
+
+    /// Get notified after each frame is drawn, like for a GUI to repaint with `gui_snapshot`.
+    ///
+    /// Notifications not received yet are merged into one. Frames are drawn on changes at most once per interval,
+    /// so with `set_ticker` the last changes are always notified. Dropping the receiver unsubscribes.
+    pub fn subscribe(&self) -> std::sync::mpsc::Receiver<()> {
+        let (sender, receiver) = std::sync::mpsc::sync_channel(1);
+        self.inner.subscribers.lock_unpoisoned().push(sender);
+        receiver
+    }
+
+    /// If manager shall forcely draw when pos == len without explicitly calling finish().
+    ///
+    /// Default is true.
+    pub fn force_draw_when_finished(&self, force: bool) {
+        self.inner
+            .force_when_finished
+            .store(force, std::sync::atomic::Ordering::Release);
+    }
+
+    /// If `{msg}` shall be wrapped with Unicode bidi isolates (U+2068 and U+2069).
+    ///
+    /// Enable this when messages may contain right-to-left text (like Arabic or Hebrew filenames),
+    /// so that they don't visually reorder the rest of the line.
+    ///
+    /// Default is false.
+    pub fn isolate_bidi(&self, isolate: bool) {
+        self.inner
+            .isolate_bidi
+            .store(isolate, std::sync::atomic::Ordering::Release);
+        self.mark_redraw();
+    }
+
+    /// Set the decimals of byte tags like `{bytes}` and `{bytes_per_sec}` (like `1.5 MiB` for 1),
+    /// clamped to `MAX_BYTE_PRECISION`. Tags with their own precision like `{bytes:.0}` are not affected.
+    ///
+    /// Fewer decimals save width, and jitter less at high update rates. Default is 2.
+    pub fn set_byte_precision(&self, precision: usize) {
+        self.inner.byte_precision.store(
+            precision.min(MAX_BYTE_PRECISION),
+            std::sync::atomic::Ordering::Release,
+        );
+        self.mark_redraw();
+    }
+
+    /// If numbers changing every frame shall be padded to their widest, so that lines don't change length
+    /// (which makes wrapped lines flicker).
+    ///
+    /// `{pos}` and `{bytes}` are padded to the width of the length, `{all_bytes}` to the width of
+    /// `{all_total_bytes}`, and speeds to the widest in their unit, like `1023.99 KiB/s`.
+    ///
+    /// Default is false.
+    pub fn set_stable_width(&self, stable: bool) {
+        self.inner
+            .stable_width
+            .store(stable, std::sync::atomic::Ordering::Release);
+        self.mark_redraw();
+    }
+
+    /// Create a new progress bar.
+    ///
+    /// - `len`: The total length of the progress bar.
+    /// - `message`: The message of the bar. Use `{msg}` in the template to refer to this.
+    /// - `template`: The template of the bar.
+    /// - `visible`: Whether the bar is visible.
+    ///
+    /// This makes a forced draw when visible is true.
+    pub fn create_bar(
+        &self,
+        len: u64,
+        message: &str,
+        template: impl Into<Template>,
+        visible: bool,
+    ) -> Bar {
+        self.inner
+            .create_bar(len, message, template.into(), visible, None)
+    }
+
+    /// Create a visible progress bar, and run `f` with it like `Bar::scope`. The bar is dropped afterwards.
+    pub fn with_bar<R, F: FnOnce(&Bar) -> R>(
+        &self,
+        len: u64,
+        message: &str,
+        template: impl Into<Template>,
+        f: F,
+    ) -> R {
+        self.create_bar(len, message, template, true).scope(f)
+    }
+
+    /// Create a new progress bar, which is hidden until its first `inc`, `set_pos` or `update` moving it.
+    ///
+    /// This avoids a wall of bars at 0% when lots of tasks are enqueued upfront. When it's shown, a forced draw is made
+    /// like `create_bar`, and `{elapsed}` starts from then.
+    pub fn create_lazy_bar(&self, len: u64, message: &str, template: impl Into<Template>) -> Bar {
+        self.create_bar_with(BarOptions {
+            len,
+            message: message.to_string(),
+            template: Some(template.into()),
+            lazy: true,
+            ..BarOptions::default()
+        })
+    }
+
+    /// Create a new progress bar with `options`, which are all applied before it's first drawn.
+    ///
+    /// ```
+    /// use kyuri::BarOptions;
+    ///
+    /// let manager = kyuri::Manager::new(std::time::Duration::from_secs(1));
+    /// let bar = manager.create_bar_with(BarOptions {
+    ///     len: 100,
+    ///     message: "Downloading".to_string(),
+    ///     template: Some("{msg}: {bar} {pos}/{len}".into()),
+    ///     non_terminal_template: Some("{msg}: {pos}/{len}".into()),
+    ///     ..BarOptions::default()
+    /// });
+    /// ```
+    pub fn create_bar_with(&self, options: BarOptions) -> Bar {
+        let template = options.template.unwrap_or_else(|| self.default_template());
+        let visible = options.visible && !options.lazy;
+        let mut state =
+            self.inner
+                .new_state(options.len, &options.message, template, visible, None);
+        state.lazy = options.lazy && options.visible;
+        state.non_terminal_template = options.non_terminal_template;
+        state.finished_template = options.finished_template;
+        state.detail = options.detail;
+        state.spinner = options.spinner;
+        state.gradient = options.gradient;
+        state.align_group = options.align_group;
+        state.column = options.column;
+        state.fields = options.fields;
+        state.log_changes = options.log_level;
+        state.min_delta = options.min_delta;
+        state.min_percent_delta = options.min_percent_delta;
+        state.eta_estimator = eta::EtaEstimator::new(options.eta_strategy);
+        state.collapsed = options.collapsed;
+        self.inner.insert_bar(state)
+    }
+
+    /// Create a new `Checklist`, whose steps are drawn above progress bars.
+    pub fn create_checklist(&self) -> checklist::Checklist {
+        checklist::Checklist::new(Arc::downgrade(&self.inner))
+    }
+
+    /// Create a new progress bar of a download split into segments of `segment_lens` bytes,
+    /// like one with several connections. See `segmented::SegmentedBar`.
+    pub fn create_segmented_bar(
+        &self,
+        segment_lens: &[u64],
+        message: &str,
+        template: impl Into<Template>,
+    ) -> segmented::SegmentedBar {
+        let segments = Arc::new(segmented::Segments::new(segment_lens));
+        let bar = self.inner.create_bar(
+            segments.len(),
+            message,
+            template.into(),
+            true,
+            Some(PosSource::Segments(segments.clone())),
+        );
+        segmented::SegmentedBar::new(bar, segments)
+    }
+
+    /// Create a new progress bar of the overall progress of member bars, weighted by their lens
+    /// or explicit weights. See `overall::OverallBar`.
+    ///
+    /// The bar has no message (set it with `set_message`) and no members initially, so it is 0/0.
+    pub fn create_overall_bar(&self, template: impl Into<Template>) -> overall::OverallBar {
+        let members = Arc::new(overall::Members::default());
+        let bar = self.inner.create_bar(
+            0,
+            "",
+            template.into(),
+            true,
+            Some(PosSource::Overall(members.clone())),
+        );
+        overall::OverallBar::new(bar, members)
+    }
+
+    /// Create a new progress bar, which reads its position from `pos` on each draw.
+    ///
+    /// This is useful when existing code already tracks its progress in an atomic,
+    /// as no per-update calls into the bar are needed. Enable the ticker (`set_ticker`) to keep
+    /// the bar updated when nothing else triggers a draw.
+    ///
+    /// The bar is visible, and `set_pos` or `inc` on it would be overwritten by the next draw.
+    pub fn create_bar_from_atomic(
+        &self,
+        len: u64,
+        pos: Arc<AtomicU64>,
+        message: &str,
+        template: impl Into<Template>,
+    ) -> Bar {
+        self.inner.create_bar(
+            len,
+            message,
+            template.into(),
+            true,
+            Some(PosSource::Atomic(pos)),
+        )
+    }
+
+    /// Create a new progress bar, which polls `source` for its position on each draw.
+    ///
+    /// This is useful for reflecting progress that is not instrumented, like the size of a growing
+    /// output file or the length of a queue. Enable the ticker (`set_ticker`) to poll at a fixed interval.
+    ///
+    /// `source` is called with the bar state lock held, so it must not call methods of this bar.
+    /// The bar is visible, and `set_pos` or `inc` on it would be overwritten by the next draw.
+    pub fn create_bar_with_source<F: Fn() -> u64 + Send + 'static>(
+        &self,
+        len: u64,
+        message: &str,
+        template: impl Into<Template>,
+        source: F,
+    ) -> Bar {
+        self.inner.create_bar(
+            len,
+            message,
+            template.into(),
+            true,
+            Some(PosSource::Callback(Box::new(source))),
+        )
+    }
+
+    /// Create a new progress bar, whose position advances automatically with wall time until `total` has elapsed.
+    ///
+    /// The position and length are in milliseconds, so use `{elapsed}` and `{eta}` in the template
+    /// to show the elapsed and remaining time, like `"Retrying in {eta} {bar}"`.
+    /// Enable the ticker (`set_ticker`) to keep the bar updated when nothing else triggers a draw.
+    ///
+    /// `reset_created_at` restarts the timer.
+    pub fn create_timer_bar(
+        &self,
+        total: std::time::Duration,
+        message: &str,
+        template: impl Into<Template>,
+    ) -> Bar {
+        let len = total.as_millis().min(u64::MAX as u128) as u64;
+        self.inner.create_bar(
+            len,
+            message,
+            template.into(),
+            true,
+            Some(PosSource::Elapsed),
+        )
+    }
+
+    /// Create a new progress bar, which shows the current size of the file at `path` as its position.
+    ///
+    /// This is useful for tracking a file being written by an external command (like ffmpeg or rsync).
+    /// When the file does not exist (yet), the position is 0.
+    pub fn watch_file_size<P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+        expected_len: u64,
+        message: &str,
+        template: impl Into<Template>,
+    ) -> Bar {
+        let path = path.as_ref().to_path_buf();
+        self.create_bar_with_source(expected_len, message, template, move || {
+            std::fs::metadata(&path).map_or(0, |metadata| metadata.len())
+        })
+    }
+
+    /// Draw all progress bars. In most cases it's not necessary to call this manually.
+    ///
+    /// If nothing changed, it would not draw no matter what.
+    ///
+    /// If ticker is enabled, unforced draw would be ignored. Otherwise, it would only draw when the interval has passed.
+    ///
+    /// Progress bars would be drawn by the order of `Bar` creation. In ANSI mode, it would clear the previous output.
+    ///
+    /// Finally, when output is not a terminal, bars would be drawn only when it needs to be redrawn.
+    pub fn draw(&self, force: bool) {
+        self.inner.draw(force);
+    }
+
+    /// Stop the ticker, make a final forced draw, flush the output, and consume the `Manager`.
+    ///
+    /// Dropping the `Manager` also makes a final draw, but IO errors are ignored, and the ticker thread
+    /// may still be running. This returns the first IO error instead, for deterministic teardown.
+    pub fn shutdown(self) -> std::io::Result<()> {
+        self.set_ticker_inner(false);
+        self.mark_redraw();
+        let result = self.inner.try_draw(true, false);
+        let flushed = self.inner.out.lock_unpoisoned().flush();
+        result.and(flushed)
+    }
+
+    /// Hide all progress bars, run the closure, and show them again like indicatif::MultiProgress::suspend.
+    ///
+    /// This method is used for implementing integrations with other libraries that may print to the terminal.
+    ///
+    /// When output is not a terminal, the closure would still be run but nothing would be done to the progress bars.
+    ///
+    /// Bar and Manager methods that draw could be called inside the closure, and their draws are deferred
+    /// to the next draw. Writers created by `create_writer` keep their lines until the next write outside.
+    /// Calling `suspend` of the same manager inside the closure or a draw (like by a logger) runs the inner closure
+    /// directly, with its output kept in a temporary file and written to the output after the outer closure or draw.
+    /// Only when the temporary file could not be created, the inner closure writes to stderr instead.
+    pub fn suspend<F: FnOnce(&mut Box<dyn Out>) -> R, R>(&self, f: F) -> R {
+        self.inner.suspend(f)
+    }
+
+    /// Draw to `out` while running the closure, and restore the output afterwards (even if it panics).
+    ///
+    /// This is useful for subcommands or tests that must render to a different stream mid-process.
+    /// Bars are cleared from the current output first, and all of them are drawn again on `out` and
+    /// after restored. ANSI mode is detected from `out`, and `force_ansi` is restored afterwards.
+    pub fn scoped_target<T: Out + 'static, R>(&self, out: T, f: impl FnOnce() -> R) -> R {
+        struct Restore<'a> {
+            inner: &'a ManagerInner,
+            target: Option<(Box<dyn Out>, TargetKind, Option<bool>)>,
+        }
+        impl Drop for Restore<'_> {
+            fn drop(&mut self) {
+                if let Some((out, kind, ansi)) = self.target.take() {
+                    self.inner.swap_target(out, kind, ansi);
+                }
+            }
+        }
+
+        let target = self
+            .inner
+            .swap_target(Box::new(out), TargetKind::Custom, None);
+        let _restore = Restore {
+            inner: &self.inner,
+            target: Some(target),
+        };
+        f()
+    }
+
+    /// Spawn `command`, and print its stdout and stderr line by line through `suspend`, keeping the bars intact
+    /// below the streamed output. This blocks until the child exits.
+    ///
+    /// Both stdout and stderr of the child are written to the output of the manager.
+    pub fn run_command(
+        &self,
+        command: &mut std::process::Command,
+    ) -> std::io::Result<std::process::ExitStatus> {
+        use std::io::{BufRead, Read};
+
+        let mut child = command
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()?;
+        // Both are piped above
+        let stdout: Box<dyn Read + Send> = Box::new(child.stdout.take().unwrap());
+        let stderr: Box<dyn Read + Send> = Box::new(child.stderr.take().unwrap());
+        let results: Vec<std::io::Result<()>> = std::thread::scope(|s| {
+            let handles: Vec<_> = [stdout, stderr]
+                .into_iter()
+                .map(|pipe| {
+                    s.spawn(move || {
+                        let mut reader = std::io::BufReader::new(pipe);
+                        let mut line = Vec::new();
+                        while reader.read_until(b'\n', &mut line)? > 0 {
+                            if !line.ends_with(b"\n") {
+                                line.push(b'\n');
+                            }
+                            self.suspend(|out| out.write_all(&line))?;
+                            line.clear();
+                        }
+                        Ok(())
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        let status = child.wait()?;
+        results.into_iter().collect::<std::io::Result<()>>()?;
+        Ok(status)
+    }
+
+    /// Log a line, which is kept in a fixed region above the bars in ANSI mode. This makes an unforced draw.
+    ///
+    /// Only the last lines (5 by default, see `set_log_capacity`) are kept, giving a mini two-pane display
+    /// instead of scrolling away like `suspend`. When output is not a terminal or drawing is disabled by
+    /// `KYURI_DISABLE`, the line is written directly.
+    pub fn log_line(&self, line: &str) {
+        let mut out = self.inner.out.lock_unpoisoned();
+        if self.inner.is_disabled() || !self.inner.is_terminal(&mut out) {
+            self.inner.record(&format!("{}\n", line));
+            let _ = writeln!(out, "{}", line);
+            return;
+        }
+        std::mem::drop(out);
+        let capacity = self
+            .inner
+            .log_capacity
+            .load(std::sync::atomic::Ordering::Acquire);
+        let mut log_lines = self.inner.log_lines.lock_unpoisoned();
+        log_lines.extend(line.split('\n').map(|s| s.to_string()));
+        while log_lines.len() > capacity {
+            log_lines.pop_front();
+        }
+        std::mem::drop(log_lines);
+        self.mark_redraw();
+        self.draw(false);
+    }
+
+    /// Set how many lines logged by `log_line` are kept above the bars. Default is 5.
+    pub fn set_log_capacity(&self, capacity: usize) {
+        self.inner
+            .log_capacity
+            .store(capacity, std::sync::atomic::Ordering::Release);
+        let mut log_lines = self.inner.log_lines.lock_unpoisoned();
+        while log_lines.len() > capacity {
+            log_lines.pop_front();
+        }
+        std::mem::drop(log_lines);
+        self.mark_redraw();
+    }
+
+    /// Listen on a Unix domain socket at `path`, so that external tools could query and control the manager at runtime.
+    ///
+    /// See `control` for the protocol. The socket stops listening when the returned `ControlSocket` is dropped.
+    #[cfg(all(unix, feature = "control_socket"))]
+    pub fn serve_control_socket<P: AsRef<std::path::Path>>(
+        &self,
+        path: P,
+    ) -> std::io::Result<control::ControlSocket> {
+        control::ControlSocket::new(Arc::downgrade(&self.inner), path.as_ref())
+    }
+
+    /// Serve a page and a JSON snapshot of the bars over HTTP at `addr`, for monitoring from a browser.
+    ///
+    /// See `dashboard` for the endpoints. The server stops listening when the returned `Dashboard` is dropped.
+    #[cfg(feature = "http_dashboard")]
+    pub fn serve_http<A: std::net::ToSocketAddrs>(
+        &self,
+        addr: A,
+    ) -> std::io::Result<dashboard::Dashboard> {
+        dashboard::Dashboard::new(Arc::downgrade(&self.inner), addr)
+    }
+
+    /// Create a writer for integration with other libraries.
     pub fn create_writer(&self) -> writer::KyuriWriter {
         writer::KyuriWriter::new(self.inner.clone())
     }
-}
 
-impl Drop for ManagerInner {
-    /// Force a draw when the `ManagerInner` is dropped.
-    fn drop(&mut self) {
-        self.draw(true);
+    /// Create a builder for a writer, which could write to another stream, or prefix each line with a timestamp or level.
+    pub fn writer_builder(&self) -> writer::KyuriWriterBuilder {
+        writer::KyuriWriterBuilder::new(self.inner.clone())
+    }
+}
+
+impl Drop for ManagerInner {
+    /// Force a draw when the `ManagerInner` is dropped.
+    fn drop(&mut self) {
+        self.draw(true);
+        let mut out = self.out.lock_unpoisoned();
+        if self.finish_backend(&mut out).is_ok() {
+            let _ = out.flush();
+        }
+        std::mem::drop(out);
+        // Bars are not going to finish, wake up the waiters
+        for state in self.states.lock_unpoisoned().values() {
+            state.lock_unpoisoned().notify_finished();
+        }
+    }
+}
+
+impl Bar {
+    fn get_manager_and_state(&self) -> Option<(Arc<ManagerInner>, Arc<Mutex<BarState>>)> {
+        let manager = self.manager.upgrade()?;
+        let state = manager.states.lock_unpoisoned().get(&self.id)?.clone();
+        Some((manager, state))
+    }
+
+    fn check_if_force_draw(
+        &self,
+        manager: Arc<ManagerInner>,
+        pos: u64,
+        len: u64,
+        materialized: bool,
+    ) {
+        // A lazy bar shown is like a bar created
+        if materialized
+            || pos == len
+                && manager
+                    .force_when_finished
+                    .load(std::sync::atomic::Ordering::Acquire)
+        {
+            manager.draw_event();
+        } else {
+            manager.draw(false);
+        }
+    }
+
+    /// Increment the progress bar by `n`. This makes an unforced draw.
+    ///
+    /// The position saturates at `u64::MAX` instead of overflowing.
+    pub fn inc(&self, n: u64) {
+        let _ = self.try_inc(n);
+    }
+
+    /// Like `inc`, but returns `Err(ManagerGone)` when the manager has been dropped.
+    pub fn try_inc(&self, n: u64) -> Result<(), ManagerGone> {
+        let (manager, state) = self.get_manager_and_state().ok_or(ManagerGone)?;
+        let mut state = state.lock_unpoisoned();
+        let pos = state.pos.saturating_add(n);
+        state.update_pos(pos);
+        state.thread = ThreadTag::current();
+        let materialized = state.materialize();
+        if !state.delta_reached() && !materialized {
+            return Ok(());
+        }
+        state.mark_changed(true);
+        let pos = state.pos;
+        let len = state.len;
+        // Drop state before drawing, deadlock otherwise!
+        std::mem::drop(state);
+        manager.mark_redraw();
+        self.check_if_force_draw(manager, pos, len, materialized);
+        Ok(())
+    }
+
+    /// Set the position of the progress bar. This makes an unforced draw.
+    pub fn set_pos(&self, pos: u64) {
+        let _ = self.try_set_pos(pos);
+    }
+
+    /// Like `set_pos`, but returns `Err(ManagerGone)` when the manager has been dropped.
+    pub fn try_set_pos(&self, pos: u64) -> Result<(), ManagerGone> {
+        let (manager, state) = self.get_manager_and_state().ok_or(ManagerGone)?;
+        let mut state = state.lock_unpoisoned();
+        state.update_pos(pos);
+        state.thread = ThreadTag::current();
+        let materialized = state.materialize();
+        if !state.delta_reached() && !materialized {
+            return Ok(());
+        }
+        state.mark_changed(true);
+        let pos = state.pos;
+        let len = state.len;
+        // Drop state before drawing, deadlock otherwise!
+        std::mem::drop(state);
+        manager.mark_redraw();
+        self.check_if_force_draw(manager, pos, len, materialized);
+        Ok(())
+    }
+
+    /// Apply several changes to the progress bar at once, like `bar.update(|u| { u.pos(0).len(100).message("Copying"); })`.
+    /// This makes an unforced draw, or a forced one when finished like `set_pos`.
+    ///
+    /// The changes are applied under one lock, so drawing would never observe a half-updated bar (like a new len with an old pos).
+    pub fn update<F: FnOnce(&mut BarUpdate)>(&self, f: F) {
+        if let Some((manager, state)) = self.get_manager_and_state() {
+            let mut state = state.lock_unpoisoned();
+            let (old_pos, old_len) = (state.pos, state.len);
+            f(&mut BarUpdate { state: &mut state });
+            let mut materialized = false;
+            if state.pos != old_pos {
+                state.thread = ThreadTag::current();
+                materialized = state.materialize();
+            }
+            let position = state.pos != old_pos || state.len != old_len;
+            state.mark_changed(position);
+            let pos = state.pos;
+            let len = state.len;
+            // Drop state before drawing, deadlock otherwise!
+            std::mem::drop(state);
+            manager.mark_redraw();
+            self.check_if_force_draw(manager, pos, len, materialized);
+        }
+    }
+
+    /// Set the total length of the progress bar. This makes an unforced draw.
+    pub fn set_len(&self, len: u64) {
+        let _ = self.try_set_len(len);
+    }
+
+    /// Like `set_len`, but returns `Err(ManagerGone)` when the manager has been dropped.
+    pub fn try_set_len(&self, len: u64) -> Result<(), ManagerGone> {
+        let (manager, state) = self.get_manager_and_state().ok_or(ManagerGone)?;
+        let mut state = state.lock_unpoisoned();
+        state.len = len;
+        state.update_finished();
+        state.mark_changed(true);
+        let pos = state.pos;
+        let len = state.len;
+        // Drop state before drawing, deadlock otherwise!
+        std::mem::drop(state);
+        manager.mark_redraw();
+        self.check_if_force_draw(manager, pos, len, false);
+        Ok(())
+    }
+
+    /// Reset the created_at time to now. This makes an unforced draw.
+    ///
+    /// Remember to call this when you want to reuse a bar object.
+    pub fn reset_created_at(&self) {
+        if let Some((manager, state)) = self.get_manager_and_state() {
+            let mut state = state.lock_unpoisoned();
+            state.created_at = clock::now();
+            state.finished_at = None;
+            state.update_finished();
+            state.mark_changed(false);
+            let pos = state.pos;
+            let len = state.len;
+            // Drop state before drawing, deadlock otherwise!
+            std::mem::drop(state);
+            manager.mark_redraw();
+            self.check_if_force_draw(manager, pos, len, false);
+        }
+    }
+
+    /// Get the position of the progress bar.
+    ///
+    /// When manager is dropped, this would return 0
+    pub fn get_pos(&self) -> u64 {
+        self.try_get_pos().unwrap_or(0)
+    }
+
+    /// Like `get_pos`, but returns `Err(ManagerGone)` when the manager has been dropped.
+    pub fn try_get_pos(&self) -> Result<u64, ManagerGone> {
+        let (_, state) = self.get_manager_and_state().ok_or(ManagerGone)?;
+        let pos = state.lock_unpoisoned().pos;
+        Ok(pos)
+    }
+
+    /// Get the position, length, message and other states of the progress bar at once,
+    /// or `None` when the manager is dropped.
+    ///
+    /// Unlike separate calls like `get_pos` and `get_len`, the values are read under one lock, so they are consistent.
+    pub fn snapshot(&self) -> Option<BarSnapshot> {
+        let (_, state) = self.get_manager_and_state()?;
+        let snapshot = state.lock_unpoisoned().snapshot();
+        Some(snapshot)
+    }
+
+    /// Get the elapsed time, the same as `{elapsed}`: it stops when the bar is finished.
+    ///
+    /// When manager is dropped, this would return zero
+    pub fn elapsed(&self) -> std::time::Duration {
+        self.get_manager_and_state()
+            .map_or(std::time::Duration::ZERO, |(_, state)| {
+                state.lock_unpoisoned().elapsed()
+            })
+    }
+
+    /// Get the estimated remaining time, the same as `{eta}`, or `None` when the position is 0.
+    ///
+    /// When manager is dropped, this would return `None`
+    pub fn eta(&self) -> Option<std::time::Duration> {
+        let (_, state) = self.get_manager_and_state()?;
+        let eta = state.lock_unpoisoned().eta();
+        eta
+    }
+
+    /// Only redraw the progress bar when its position moved at least `delta` since last drawn, or it's finished.
+    /// Default is 0.
+    ///
+    /// This applies to `inc`, `set_pos` and external sources, which return early without drawing when the delta is not reached,
+    /// cutting the cost and file output of counters updated millions of times. The position itself is always updated.
+    pub fn set_min_delta(&self, delta: u64) {
+        if let Some((_, state)) = self.get_manager_and_state() {
+            state.lock_unpoisoned().min_delta = delta;
+        }
+    }
+
+    /// Like `set_min_delta`, but by the percentage of the length, like 1.0 for every 1%. Default is 0.0.
+    ///
+    /// Both have to be reached when both are set.
+    pub fn set_min_percent_delta(&self, percent: f64) {
+        if let Some((_, state)) = self.get_manager_and_state() {
+            state.lock_unpoisoned().min_percent_delta = percent;
+        }
+    }
+
+    /// Set which changes make the progress bar written again when output is not a terminal. Default is `LogChanges::All`.
+    ///
+    /// This is useful to keep frequent message changes from multiplying file output, while recording the progress.
+    /// Changes of visibility are always written. It has no effect in ANSI mode, where all bars are redrawn.
+    pub fn set_log_level(&self, log_changes: LogChanges) {
+        if let Some((_, state)) = self.get_manager_and_state() {
+            state.lock_unpoisoned().log_changes = log_changes;
+        }
+    }
+
+    /// Set how the remaining time (`{eta}` and `eta`) is estimated, like `EtaStrategy::RecentWindow` for workloads
+    /// whose speed changes over time. Default is `EtaStrategy::Linear`. This makes an unforced draw.
+    ///
+    /// Samples of the previous strategy are discarded.
+    pub fn set_eta_strategy(&self, strategy: eta::EtaStrategy) {
+        if let Some((manager, state)) = self.get_manager_and_state() {
+            let mut state = state.lock_unpoisoned();
+            state.eta_estimator = eta::EtaEstimator::new(strategy);
+            state.mark_changed(false);
+            // Drop state before drawing, deadlock otherwise!
+            std::mem::drop(state);
+            manager.mark_redraw();
+            manager.draw(false);
+        }
+    }
+
+    /// Get the average speed (position per second) since created, the same as `{bytes_per_sec}` without the unit.
+    ///
+    /// When manager is dropped, this would return 0
+    pub fn per_sec(&self) -> f64 {
+        self.get_manager_and_state()
+            .map_or(0.0, |(_, state)| state.lock_unpoisoned().per_sec())
+    }
+
+    /// Get the total length of the progress bar.
+    ///
+    /// When manager is dropped, this would return 0
+    pub fn get_len(&self) -> u64 {
+        self.get_manager_and_state()
+            .map_or(0, |(_, state)| state.lock_unpoisoned().len)
+    }
+
+    /// Set the progress bar to the end, and force a draw.
+    pub fn finish(&self) {
+        let _ = self.try_finish();
+    }
+
+    /// Like `finish`, but returns `Err(ManagerGone)` when the manager has been dropped.
+    pub fn try_finish(&self) -> Result<(), ManagerGone> {
+        let (manager, state) = self.get_manager_and_state().ok_or(ManagerGone)?;
+        let state = state.lock_unpoisoned();
+        let pos = state.pos;
+        let len = state.len;
+        // Drop state before setting pos, deadlock otherwise!
+        std::mem::drop(state);
+        if pos != len {
+            self.set_pos(len);
+        }
+        manager.draw_event();
+        Ok(())
+    }
+
+    /// Set the progress bar to the end, force a draw, and remove the progress bar from the manager.
+    pub fn finish_and_drop(self) {
+        self.finish();
+        // Automatically drop
+    }
+
+    /// Set the visibility of the progress bar. This makes an forced draw when visible actually changes.
+    pub fn set_visible(&self, visible: bool) {
+        if let Some((manager, state)) = self.get_manager_and_state() {
+            let mut state = state.lock_unpoisoned();
+            // Shown or hidden explicitly, not by progress anymore
+            state.lazy = false;
+            if state.visible != visible {
+                state.visible = visible;
+                state.need_redraw = true;
+                // Drop state before drawing, deadlock otherwise!
+                std::mem::drop(state);
+                manager.mark_redraw();
+                manager.draw_event();
+            }
+        }
+    }
+
+    /// Get the visibility of the progress bar.
+    ///
+    /// When manager is dropped, this would return false
+    pub fn is_visible(&self) -> bool {
+        self.get_manager_and_state()
+            .is_some_and(|(_, state)| state.lock_unpoisoned().visible)
+    }
+
+    /// Set the message of the progress bar. This makes an unforced draw.
+    pub fn set_message(&self, message: &str) {
+        let _ = self.try_set_message(message);
+    }
+
+    /// Like `set_message`, but returns `Err(ManagerGone)` when the manager has been dropped.
+    pub fn try_set_message(&self, message: &str) -> Result<(), ManagerGone> {
+        let (manager, state) = self.get_manager_and_state().ok_or(ManagerGone)?;
+        let mut state = state.lock_unpoisoned();
+        state.message = message.to_string();
+        state.mark_changed(false);
+        // Drop state before drawing, deadlock otherwise!
+        std::mem::drop(state);
+        manager.mark_redraw();
+        manager.draw(false);
+        Ok(())
+    }
+
+    /// Set the field `name` of the progress bar, shown by `{field:NAME}` in the template. This makes an unforced draw.
+    ///
+    /// Fields show several dynamic strings, like a host or a file name, without putting them all in `{msg}`.
+    /// Fields not set are empty.
+    pub fn set_field(&self, name: &str, value: &str) {
+        if let Some((manager, state)) = self.get_manager_and_state() {
+            let mut state = state.lock_unpoisoned();
+            state.fields.insert(name.to_string(), value.to_string());
+            state.mark_changed(false);
+            // Drop state before drawing, deadlock otherwise!
+            std::mem::drop(state);
+            manager.mark_redraw();
+            manager.draw(false);
+        }
+    }
+
+    /// Set the template of the progress bar. This makes an unforced draw.
+    pub fn set_template(&self, template: impl Into<Template>) {
+        if let Some((manager, state)) = self.get_manager_and_state() {
+            let mut state = state.lock_unpoisoned();
+            state.template = template.into();
+            state.mark_changed(false);
+            // Drop state before drawing, deadlock otherwise!
+            std::mem::drop(state);
+            manager.mark_redraw();
+            manager.draw(false);
+        }
+    }
+
+    /// Mark the progress bar as failed, or not. This makes an unforced draw.
+    ///
+    /// A failed bar shows the failed icon in `{state_emoji}`, and the effect set by `Manager::set_failed_effect`.
+    pub fn fail(&self, failed: bool) {
+        if let Some((manager, state)) = self.get_manager_and_state() {
+            let mut state = state.lock_unpoisoned();
+            state.failed = failed;
+            state.notify_finished();
+            state.mark_changed(false);
+            // Drop state before drawing, deadlock otherwise!
+            std::mem::drop(state);
+            manager.mark_redraw();
+            manager.draw(false);
+        }
+    }
+
+    /// Show the progress bar as waiting to retry, like `Download: attempt 2/5 failed, retrying in 4s`,
+    /// until `resume_progress`. This makes an unforced draw.
+    ///
+    /// `attempt` is the attempt which failed, out of `max`, and `next_in` is the delay before the next one,
+    /// like from an exponential backoff. In a terminal, the countdown is redrawn by the ticker as it changes.
+    pub fn retrying(&self, attempt: u32, max: u32, next_in: std::time::Duration) {
+        if let Some((manager, state)) = self.get_manager_and_state() {
+            let mut state = state.lock_unpoisoned();
+            let mut retry = Retry {
+                attempt,
+                max,
+                at: clock::now() + next_in,
+                shown: 0,
+            };
+            retry.shown = retry.countdown();
+            state.retry = Some(retry);
+            state.mark_changed(false);
+            // Drop state before drawing, deadlock otherwise!
+            std::mem::drop(state);
+            manager.mark_redraw();
+            manager.draw(false);
+        }
+    }
+
+    /// Show the progress bar with its template again after `retrying`. This makes an unforced draw.
+    pub fn resume_progress(&self) {
+        if let Some((manager, state)) = self.get_manager_and_state() {
+            let mut state = state.lock_unpoisoned();
+            if state.retry.take().is_some() {
+                state.mark_changed(false);
+            }
+            // Drop state before drawing, deadlock otherwise!
+            std::mem::drop(state);
+            manager.mark_redraw();
+            manager.draw(false);
+        }
+    }
+
+    /// Get whether the progress bar is marked as failed.
+    ///
+    /// When manager is dropped, this would return false
+    pub fn is_failed(&self) -> bool {
+        self.get_manager_and_state()
+            .is_some_and(|(_, state)| state.lock_unpoisoned().failed)
+    }
+
+    /// Set the color gradient of the fill of `{bar}`, or `None` to disable it. This makes an unforced draw.
+    ///
+    /// The fill is colored by the completion percentage, like `Gradient::red_yellow_green()`.
+    /// Colors are degraded like style tags, so they are removed when output is not a terminal.
+    pub fn set_gradient(&self, gradient: Option<style::Gradient>) {
+        if let Some((manager, state)) = self.get_manager_and_state() {
+            let mut state = state.lock_unpoisoned();
+            state.gradient = gradient;
+            state.mark_changed(false);
+            // Drop state before drawing, deadlock otherwise!
+            std::mem::drop(state);
+            manager.mark_redraw();
+            manager.draw(false);
+        }
+    }
+
+    /// Set the frames of `{spinner}` and how long each one is shown, instead of ones of the theme. This makes an unforced draw.
+    ///
+    /// Frames could be multiple characters, like `&["[=  ]", "[ = ]", "[  =]"]`, but should have the same width.
+    /// Pass empty `frames` to use ones of the theme again, with `interval`. The spinner stops when the bar is finished.
+    /// Enable the ticker (`Manager::set_ticker`) to keep it spinning when nothing else triggers a draw.
+    pub fn set_spinner_frames(&self, frames: &[&str], interval: std::time::Duration) {
+        if let Some((manager, state)) = self.get_manager_and_state() {
+            let mut state = state.lock_unpoisoned();
+            let frames = frames.iter().map(|frame| frame.to_string()).collect();
+            state.spinner = Some((frames, interval));
+            state.mark_changed(false);
+            // Drop state before drawing, deadlock otherwise!
+            std::mem::drop(state);
+            manager.mark_redraw();
+            manager.draw(false);
+        }
+    }
+
+    /// Put the progress bar into an alignment group, or `None` to remove it from its group. This makes an unforced draw.
+    ///
+    /// Visible bars in the same group are aligned to each other on every draw: `{msg}` is padded to the longest message,
+    /// and `{pos}`, `{len}`, `{bytes}` and `{total_bytes}` are right-aligned to the widest one,
+    /// so that the following parts (like `{bar}`) start at the same column.
+    pub fn set_align_group(&self, group: Option<usize>) {
+        if let Some((manager, state)) = self.get_manager_and_state() {
+            let mut state = state.lock_unpoisoned();
+            state.align_group = group;
+            state.mark_changed(false);
+            // Drop state before drawing, deadlock otherwise!
+            std::mem::drop(state);
+            manager.mark_redraw();
+            manager.draw(false);
+        }
+    }
+
+    /// Put the progress bar into a column of the split layout (see `Manager::set_split`). This makes an unforced draw.
+    pub fn set_column(&self, column: layout::Column) {
+        if let Some((manager, state)) = self.get_manager_and_state() {
+            let mut state = state.lock_unpoisoned();
+            state.column = column;
+            state.mark_changed(false);
+            // Drop state before drawing, deadlock otherwise!
+            std::mem::drop(state);
+            manager.mark_redraw();
+            manager.draw(false);
+        }
+    }
+
+    /// Collapse the progress bar into a one-line summary, or expand it back. This makes an unforced draw.
+    ///
+    /// Unlike `set_visible(false)`, a collapsed bar is still drawn, with the collapsed template
+    /// (default is `{msg} ({pos}/{len})`, see `set_collapsed_template`) instead of its own one.
+    pub fn set_collapsed(&self, collapsed: bool) {
+        if let Some((manager, state)) = self.get_manager_and_state() {
+            let mut state = state.lock_unpoisoned();
+            state.collapsed = collapsed;
+            state.mark_changed(false);
+            // Drop state before drawing, deadlock otherwise!
+            std::mem::drop(state);
+            manager.mark_redraw();
+            manager.draw(false);
+        }
+    }
+
+    /// Set the template used when the progress bar is finished (pos == len, and not failed), or `None` to keep the template.
+    /// This makes an unforced draw.
+    ///
+    /// This is useful to make the final line look like a completion record, like `{msg}: done in {elapsed}`.
+    /// `{elapsed}` stops at the time when the bar is finished, so it shows the total time.
+    pub fn set_finished_template(&self, template: Option<impl Into<Template>>) {
+        if let Some((manager, state)) = self.get_manager_and_state() {
+            let mut state = state.lock_unpoisoned();
+            state.finished_template = template.map(Into::into);
+            state.mark_changed(false);
+            // Drop state before drawing, deadlock otherwise!
+            std::mem::drop(state);
+            manager.mark_redraw();
+            manager.draw(false);
+        }
+    }
+
+    /// Set the detail block of the progress bar, or `None` to remove it. This makes an unforced draw.
+    ///
+    /// The detail block is rendered as extra lines under the bar only with `Verbosity::Detailed`
+    /// (see `Manager::set_verbosity`), like per-chunk or per-connection info, without changing the template.
+    /// It's hidden when the bar is collapsed.
+    pub fn set_detail(&self, template: Option<impl Into<Template>>) {
+        if let Some((manager, state)) = self.get_manager_and_state() {
+            let mut state = state.lock_unpoisoned();
+            state.detail = template.map(Into::into);
+            state.mark_changed(false);
+            // Drop state before drawing, deadlock otherwise!
+            std::mem::drop(state);
+            manager.mark_redraw();
+            manager.draw(false);
+        }
+    }
+
+    /// Set the template used when output is not a terminal, or `None` to use the one of the manager
+    /// (`Manager::with_non_terminal_template`) or the template of the bar. This makes an unforced draw.
+    pub fn set_non_terminal_template(&self, template: Option<impl Into<Template>>) {
+        if let Some((manager, state)) = self.get_manager_and_state() {
+            let mut state = state.lock_unpoisoned();
+            state.non_terminal_template = template.map(Into::into);
+            state.mark_changed(false);
+            // Drop state before drawing, deadlock otherwise!
+            std::mem::drop(state);
+            manager.mark_redraw();
+            manager.draw(false);
+        }
+    }
+
+    /// Set the template used when the progress bar is collapsed. This makes an unforced draw.
+    pub fn set_collapsed_template(&self, template: impl Into<Template>) {
+        if let Some((manager, state)) = self.get_manager_and_state() {
+            let mut state = state.lock_unpoisoned();
+            state.collapsed_template = template.into();
+            state.mark_changed(false);
+            // Drop state before drawing, deadlock otherwise!
+            std::mem::drop(state);
+            manager.mark_redraw();
+            manager.draw(false);
+        }
+    }
+
+    /// Move the progress bar to `manager`, keeping its state, like the position, elapsed time and template.
+    /// This makes forced draws of both managers.
+    ///
+    /// This is useful for moving bars from a bootstrap manager (like one writing to stderr) to the final one
+    /// (like a file-backed one) once the config is loaded. It does nothing when the manager of the bar has been dropped.
+    pub fn migrate_to(&mut self, manager: &Manager) {
+        let Some((old, state)) = self.get_manager_and_state() else {
+            return;
+        };
+        if Arc::ptr_eq(&old, &manager.inner) {
+            return;
+        }
+        old.states.lock_unpoisoned().remove(&self.id);
+        old.mark_redraw();
+        old.draw_event();
+
+        let inner = &manager.inner;
+        let id = inner
+            .next_id
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let mut bar_state = state.lock_unpoisoned();
+        bar_state.progress_crossed = inner.progress_crossed.clone();
+        if !bar_state.crossed.is_empty() {
+            bar_state
+                .progress_crossed
+                .store(true, std::sync::atomic::Ordering::Release);
+        }
+        bar_state.mark_changed(true);
+        // Drop state before drawing, deadlock otherwise!
+        std::mem::drop(bar_state);
+        inner.states.lock_unpoisoned().insert(id, state);
+        self.id = id;
+        self.manager = Arc::downgrade(inner);
+        inner.mark_redraw();
+        inner.draw_event();
+    }
+
+    /// Get the id of the progress bar, unique among bars of its manager, like for `ffi::Update`.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Return whether the progress bar (the manager) is still alive.
+    ///
+    /// When the manager is dropped, the progress bar would not be able to be interacted with.
+    pub fn alive(&self) -> bool {
+        self.get_manager_and_state().is_some()
+    }
+
+    /// Call `callback` once with the threshold when the progress (pos / len) reaches each percentage in `thresholds`,
+    /// like `bar.on_progress_at(&[25, 50, 75, 100], |percent| log::info!("{percent}% done"))`.
+    ///
+    /// Thresholds already reached are called back on the next update. Callbacks are called after the bar is updated
+    /// and drawn, without holding locks, so they could update bars or log lines.
+    pub fn on_progress_at<F: Fn(u8) + Send + Sync + 'static>(
+        &self,
+        thresholds: &[u8],
+        callback: F,
+    ) {
+        if let Some((manager, state)) = self.get_manager_and_state() {
+            let mut state = state.lock_unpoisoned();
+            state.progress_callbacks.push(ProgressCallback {
+                thresholds: thresholds.to_vec(),
+                callback: Arc::new(callback),
+            });
+            state.check_progress_callbacks();
+            // Drop state before drawing, deadlock otherwise!
+            std::mem::drop(state);
+            manager.draw(false);
+        }
+    }
+
+    /// Block until the progress bar is finished (pos == len), when it's updated by other threads.
+    ///
+    /// Returns `true` when finished, or `false` when the bar is marked as failed or the manager is dropped.
+    pub fn wait_finished(&self) -> bool {
+        let Some((manager, state)) = self.get_manager_and_state() else {
+            return false;
+        };
+        // Don't keep the manager alive while waiting
+        std::mem::drop(manager);
+        let mut state = state.lock_unpoisoned();
+        let cvar = state.finished_cvar.clone();
+        loop {
+            if let Some(finished) = state.finished_or_failed() {
+                return finished;
+            }
+            if self.manager.strong_count() == 0 {
+                return false;
+            }
+            state = cvar
+                .wait(state)
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+        }
+    }
+
+    /// Get a future which resolves when the progress bar is finished, the async version of `wait_finished`.
+    ///
+    /// It does not depend on any async runtime.
+    pub fn finished_future(&self) -> FinishedFuture<'_> {
+        FinishedFuture { bar: self }
+    }
+
+    /// Convert the progress bar into a `DetachedBar`, which keeps the manager alive, or `None` if the manager is gone.
+    ///
+    /// A `Bar` only holds a weak reference to its manager, so operations become no-ops after the `Manager` is dropped.
+    /// This is useful when the bar is passed deep into library code which may outlive the `Manager` borrow.
+    pub fn into_detached(self) -> Option<DetachedBar> {
+        let manager = self.manager.upgrade()?;
+        Some(DetachedBar {
+            bar: self,
+            _manager: manager,
+        })
+    }
+
+    /// Start a countdown of `duration` on this bar. This makes a forced draw.
+    ///
+    /// The length and position of the bar are set to `duration` and the elapsed time (in milliseconds),
+    /// and `{bar}` empties as time goes on. Use `{eta}` in the template to show the remaining time.
+    ///
+    /// Use the returned `Countdown` to wait for the countdown, or to cancel it.
+    pub fn countdown(&self, duration: std::time::Duration) -> countdown::Countdown {
+        let mut generation = 0;
+        if let Some((manager, state)) = self.get_manager_and_state() {
+            let mut state = state.lock_unpoisoned();
+            state.countdown += 1;
+            generation = state.countdown;
+            state.len = duration.as_millis().min(u64::MAX as u128) as u64;
+            state.pos = 0;
+            state.created_at = clock::now();
+            state.update_finished();
+            state.source = Some(PosSource::Elapsed);
+            state.reverse = true;
+            state.mark_changed(true);
+            // Drop state before drawing, deadlock otherwise!
+            std::mem::drop(state);
+            manager.mark_redraw();
+            manager.draw(true);
+        }
+        countdown::Countdown::new(self.id, generation, self.manager.clone(), duration)
+    }
+
+    /// Get a part of this bar from `start` to `end` (fractions from 0 to 1), with its own position and length
+    /// mapped onto the part, like for a phase of a job reporting from 0 to 100%.
+    ///
+    /// Updates of the `SubBar` set the position of this bar, so the length of this bar should be set before.
+    pub fn subrange(&self, start: f64, end: f64) -> subrange::SubBar<'_> {
+        subrange::SubBar::new(self, start, end)
+    }
+
+    /// Run `f` with this bar, and finish the bar when `f` returns, or mark it as failed when `f` panics.
+    ///
+    /// On a panic, the panic message is appended to the message of the bar (like `Download (panicked: oops)`),
+    /// and the bar is drawn before the panic continues, so it's clear which task died.
+    /// A bar marked as failed by `f` (like on an error) is not finished.
+    pub fn scope<R, F: FnOnce(&Bar) -> R>(&self, f: F) -> R {
+        match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(self))) {
+            Ok(result) => {
+                if !self.is_failed() {
+                    self.finish();
+                }
+                result
+            }
+            Err(payload) => {
+                let reason = match (
+                    payload.downcast_ref::<&str>(),
+                    payload.downcast_ref::<String>(),
+                ) {
+                    (Some(reason), _) => reason,
+                    (_, Some(reason)) => reason.as_str(),
+                    _ => "unknown panic",
+                };
+                self.mark_panicked(reason);
+                std::panic::resume_unwind(payload)
+            }
+        }
+    }
+
+    /// Mark the bar as failed with `reason` appended to the message, and force a draw.
+    fn mark_panicked(&self, reason: &str) {
+        if let Some((manager, state)) = self.get_manager_and_state() {
+            let mut state = state.lock_unpoisoned();
+            state.failed = true;
+            state.message = format!("{} (panicked: {})", state.message, reason);
+            state.notify_finished();
+            state.mark_changed(false);
+            // Drop state before drawing, deadlock otherwise!
+            std::mem::drop(state);
+            manager.mark_redraw();
+            manager.draw(true);
+        }
+    }
+}
+
+/// Which changes of a bar make it written again when output is not a terminal, set by `Bar::set_log_level`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogChanges {
+    /// All changes. This is the default.
+    #[default]
+    All,
+    /// Only changes of the position or the length, so that changes of message, template, etc. are not written.
+    PositionOnly,
+    /// No changes.
+    Nothing,
+}
+
+/// How much is shown for bars, set by `Manager::set_verbosity`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Verbosity {
+    /// Only the templates of bars. This is the default.
+    #[default]
+    Normal,
+    /// Also the detail blocks of bars set by `Bar::set_detail`, like for a `-v` flag.
+    Detailed,
+}
+
+/// The state of a progress bar, like what `{state_emoji}` shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BarStatus {
+    /// pos == 0 (and len != 0).
+    New,
+    /// 0 < pos < len.
+    InProgress,
+    /// pos == len.
+    Finished,
+    /// pos > len.
+    Overflowed,
+    /// Marked by `Bar::fail`.
+    Failed,
+}
+
+/// A consistent view of a progress bar at one moment, returned by `Bar::snapshot`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BarSnapshot {
+    /// The position.
+    pub pos: u64,
+    /// The total length.
+    pub len: u64,
+    /// The message.
+    pub message: String,
+    /// The elapsed time, like `{elapsed}`.
+    pub elapsed: std::time::Duration,
+    /// Whether the bar is visible.
+    pub visible: bool,
+    /// The state of the bar.
+    pub status: BarStatus,
+}
+
+/// Settings of a new progress bar, for `Manager::create_bar_with`.
+///
+/// Fields not given are like a bar from `create_bar` with `..BarOptions::default()`.
+#[derive(Debug)]
+pub struct BarOptions {
+    /// The total length. Default is 0.
+    pub len: u64,
+    /// The message. Default is empty.
+    pub message: String,
+    /// The template, or `None` for `Manager::default_template`.
+    pub template: Option<Template>,
+    /// Like `Bar::set_non_terminal_template`.
+    pub non_terminal_template: Option<Template>,
+    /// Like `Bar::set_finished_template`.
+    pub finished_template: Option<Template>,
+    /// Like `Bar::set_detail`.
+    pub detail: Option<Template>,
+    /// Like `Bar::set_spinner_frames`, the frames and how long each one is shown, or `None` for ones of the theme.
+    pub spinner: Option<(Vec<String>, std::time::Duration)>,
+    /// Whether the bar is visible. Default is true.
+    pub visible: bool,
+    /// Hidden until the first progress, like `Manager::create_lazy_bar`. Default is false.
+    pub lazy: bool,
+    /// Like `Bar::set_gradient`.
+    pub gradient: Option<style::Gradient>,
+    /// Like `Bar::set_align_group`.
+    pub align_group: Option<usize>,
+    /// Like `Bar::set_column`.
+    pub column: layout::Column,
+    /// Like `Bar::set_field` for each of them.
+    pub fields: BTreeMap<String, String>,
+    /// Like `Bar::set_log_level`. Default is `LogChanges::All`.
+    pub log_level: LogChanges,
+    /// Like `Bar::set_min_delta`. Default is 0.
+    pub min_delta: u64,
+    /// Like `Bar::set_min_percent_delta`. Default is 0.0.
+    pub min_percent_delta: f64,
+    /// Like `Bar::set_eta_strategy`. Default is `EtaStrategy::Linear`.
+    pub eta_strategy: eta::EtaStrategy,
+    /// Like `Bar::set_collapsed`. Default is false.
+    pub collapsed: bool,
+}
+
+impl Default for BarOptions {
+    fn default() -> Self {
+        BarOptions {
+            len: 0,
+            message: String::new(),
+            template: None,
+            non_terminal_template: None,
+            finished_template: None,
+            detail: None,
+            spinner: None,
+            visible: true,
+            lazy: false,
+            gradient: None,
+            align_group: None,
+            column: layout::Column::Left,
+            fields: BTreeMap::new(),
+            log_level: LogChanges::All,
+            min_delta: 0,
+            min_percent_delta: 0.0,
+            eta_strategy: eta::EtaStrategy::Linear,
+            collapsed: false,
+        }
+    }
+}
+
+/// Changes to a progress bar applied together by `Bar::update`.
+pub struct BarUpdate<'a> {
+    state: &'a mut BarState,
+}
+
+impl BarUpdate<'_> {
+    /// Set the position.
+    pub fn pos(&mut self, pos: u64) -> &mut Self {
+        self.state.update_pos(pos);
+        self
+    }
+
+    /// Increment the position by `n`, saturating at `u64::MAX`.
+    pub fn inc(&mut self, n: u64) -> &mut Self {
+        let pos = self.state.pos.saturating_add(n);
+        self.state.update_pos(pos);
+        self
+    }
+
+    /// Set the total length.
+    pub fn len(&mut self, len: u64) -> &mut Self {
+        self.state.len = len;
+        self.state.update_finished();
+        self
+    }
+
+    /// Set the message.
+    pub fn message(&mut self, message: &str) -> &mut Self {
+        self.state.message = message.to_string();
+        self
+    }
+
+    /// Set the template.
+    pub fn template(&mut self, template: impl Into<Template>) -> &mut Self {
+        self.state.template = template.into();
+        self
+    }
+}
+
+/// A future of a progress bar being finished, returned by `Bar::finished_future`.
+///
+/// It resolves to `true` when finished, or `false` when failed or the manager is dropped, like `Bar::wait_finished`.
+pub struct FinishedFuture<'a> {
+    bar: &'a Bar,
+}
+
+impl std::future::Future for FinishedFuture<'_> {
+    type Output = bool;
+
+    fn poll(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<bool> {
+        let Some((manager, state)) = self.bar.get_manager_and_state() else {
+            return std::task::Poll::Ready(false);
+        };
+        // Don't keep the manager alive in the future
+        std::mem::drop(manager);
+        let mut state = state.lock_unpoisoned();
+        if let Some(finished) = state.finished_or_failed() {
+            return std::task::Poll::Ready(finished);
+        }
+        if self.bar.manager.strong_count() == 0 {
+            return std::task::Poll::Ready(false);
+        }
+        if !state
+            .finished_wakers
+            .iter()
+            .any(|w| w.will_wake(cx.waker()))
+        {
+            state.finished_wakers.push(cx.waker().clone());
+        }
+        std::task::Poll::Pending
+    }
+}
+
+/// A progress bar that keeps its manager alive, returned by `Bar::into_detached`.
+///
+/// It dereferences to `Bar`, so all methods of `Bar` could be used.
+/// The manager makes its final draw when both the `Manager` and all `DetachedBar`s are dropped.
+pub struct DetachedBar {
+    // Declared first to be dropped first, while the manager is still alive
+    bar: Bar,
+    _manager: Arc<ManagerInner>,
+}
+
+impl std::ops::Deref for DetachedBar {
+    type Target = Bar;
+
+    fn deref(&self) -> &Bar {
+        &self.bar
+    }
+}
+
+impl Drop for Bar {
+    /// Drop the progress bar. This removes the progress bar from the manager and forces a draw.
+    fn drop(&mut self) {
+        if let Some((manager, _)) = self.get_manager_and_state() {
+            manager.states.lock_unpoisoned().remove(&self.id);
+            manager.mark_redraw();
+            manager.draw_event();
+        }
+    }
+}
+
+#[cfg(all(test, not(loom)))]
+mod tests {
+    use std::future::Future;
+    use std::io::{Read, Seek};
+
+    use super::*;
+    use crate::vt::VirtualTerminal;
+
+    fn test_state(len: u64, pos: u64, message: &str, template: &str) -> BarState {
+        BarState {
+            len,
+            pos,
+            message: message.to_string(),
+            template: Template::new(template),
+            created_at: clock::now(),
+            visible: true,
+            need_redraw: true,
+            source: None,
+            reverse: false,
+            countdown: 0,
+            gradient: None,
+            align_group: None,
+            column: layout::Column::Left,
+            lazy: false,
+            collapsed: false,
+            collapsed_template: Template::new(DEFAULT_COLLAPSED_TEMPLATE),
+            finished_template: None,
+            non_terminal_template: None,
+            finished_at: None,
+            thread: ThreadTag::current(),
+            finished_cvar: Arc::new(std::sync::Condvar::new()),
+            finished_wakers: Vec::new(),
+            eta_estimator: eta::EtaEstimator::default(),
+            log_changes: LogChanges::All,
+            fields: BTreeMap::new(),
+            bell_rung: false,
+            detail: None,
+            min_delta: 0,
+            min_percent_delta: 0.0,
+            drawn_pos: 0,
+            progress_callbacks: Vec::new(),
+            crossed: Vec::new(),
+            progress_crossed: Arc::new(AtomicBool::new(false)),
+            failed: false,
+            last_progress_at: clock::now(),
+            effect_shown: None,
+            spinner: None,
+            spinner_shown: 0,
+            retry: None,
+        }
+    }
+
+    fn test_options() -> RenderOptions {
+        RenderOptions {
+            isolate_bidi: false,
+            theme: Arc::new(Theme::default()),
+            color: ColorSupport::None,
+            effects: Effects::default(),
+            frame: 0,
+            alignments: HashMap::new(),
+            is_terminal: true,
+            non_terminal_template: None,
+            verbosity: Verbosity::Normal,
+            aggregate: Aggregate::default(),
+            tqdm: false,
+            byte_precision: 2,
+            stable_width: false,
+        }
+    }
+
+    #[test]
+    fn basic_test() {
+        let manager = Manager::new(std::time::Duration::from_secs(1));
+        let bar_1 = manager.create_bar(
+            100,
+            "Downloading",
+            "{msg}\n[{elapsed}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
+            true,
+        );
+        let bar_2 = manager.create_bar(
+            100,
+            "Uploading",
+            "{msg}\n[{elapsed}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
+            true,
+        );
+
+        bar_1.set_pos(50);
+        bar_2.set_pos(25);
+
+        std::mem::drop(bar_1);
+        std::mem::drop(bar_2);
+    }
+
+    #[test]
+    fn dont_crash_when_zero() {
+        let manager = Manager::new(std::time::Duration::from_secs(1));
+        let bar = manager.create_bar(
+            0,
+            "Downloading",
+            "{msg}\n[{elapsed}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
+            true,
+        );
+
+        bar.set_pos(0);
+        manager.draw(true);
+    }
+
+    #[test]
+    fn inc() {
+        let manager = Manager::new(std::time::Duration::from_secs(1));
+        let bar = manager.create_bar(
+            100,
+            "Downloading",
+            "{msg}\n[{elapsed}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
+            true,
+        );
+
+        bar.inc(10);
+        bar.inc(10);
+        bar.inc(10);
+        bar.inc(10);
+        bar.inc(10);
+
+        assert_eq!(bar.get_pos(), 50);
+
+        std::mem::drop(bar);
+    }
+
+    #[test]
+    fn visible() {
+        let manager = Manager::new(std::time::Duration::from_secs(1));
+        let bar = manager.create_bar(
+            100,
+            "Downloading",
+            "{msg}\n[{elapsed}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
+            true,
+        );
+
+        assert!(bar.is_visible());
+
+        bar.set_visible(false);
+        assert!(!bar.is_visible());
+
+        std::mem::drop(bar);
+    }
+
+    #[test]
+    fn ticker() {
+        let manager = Manager::new(std::time::Duration::from_secs(1));
+        manager.set_ticker(true);
+        let bar = manager.create_bar(
+            100,
+            "Downloading",
+            "{msg}\n[{elapsed}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
+            true,
+        );
+
+        std::thread::sleep(std::time::Duration::from_secs(2));
+        std::mem::drop(bar);
+    }
+
+    #[test]
+    fn alive() {
+        let manager = Manager::new(std::time::Duration::from_secs(1));
+        let bar = manager.create_bar(
+            100,
+            "Downloading",
+            "{msg}\n[{elapsed}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
+            true,
+        );
+
+        assert!(bar.alive());
+
+        std::mem::drop(manager);
+        assert!(!bar.alive());
+    }
+
+    #[test]
+    fn from_atomic() {
+        let manager = Manager::new(std::time::Duration::from_secs(1));
+        let pos = Arc::new(AtomicU64::new(3));
+        let bar = manager.create_bar_from_atomic(100, pos.clone(), "Downloading", "{pos}/{len}");
+        assert_eq!(bar.get_pos(), 3);
+
+        pos.store(42, std::sync::atomic::Ordering::Release);
+        manager.draw(true);
+        assert_eq!(bar.get_pos(), 42);
+    }
+
+    #[test]
+    fn with_source() {
+        let manager = Manager::new(std::time::Duration::from_secs(1));
+        let queue = Arc::new(Mutex::new(vec![1, 2, 3]));
+        let queue_clone = queue.clone();
+        let bar = manager.create_bar_with_source(10, "Queue", "{pos}/{len}", move || {
+            queue_clone.lock().unwrap().len() as u64
+        });
+        assert_eq!(bar.get_pos(), 3);
+
+        queue.lock().unwrap().push(4);
+        manager.draw(true);
+        assert_eq!(bar.get_pos(), 4);
+    }
+
+    #[test]
+    fn watch_file_size() {
+        use std::io::Write;
+
+        let path = std::env::temp_dir().join(format!("kyuri-watch-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let manager = Manager::new(std::time::Duration::from_secs(1));
+        let bar = manager.watch_file_size(&path, 100, "Writing", "{bytes}/{total_bytes}");
+        assert_eq!(bar.get_pos(), 0);
+
+        let mut file = std::fs::File::create(&path).unwrap();
+        file.write_all(&[0; 42]).unwrap();
+        manager.draw(true);
+        assert_eq!(bar.get_pos(), 42);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn timer_bar() {
+        let manager = Manager::new(std::time::Duration::from_secs(1));
+        let bar = manager.create_timer_bar(
+            std::time::Duration::from_millis(200),
+            "Retrying",
+            "{msg} in {eta}",
+        );
+        assert_eq!(bar.get_len(), 200);
+        assert!(bar.get_pos() < 200);
+
+        std::thread::sleep(std::time::Duration::from_millis(300));
+        manager.draw(true);
+        assert_eq!(bar.get_pos(), 200);
+    }
+
+    #[test]
+    fn countdown() {
+        let manager = Manager::new(std::time::Duration::from_millis(10));
+        let bar = manager.create_bar(100, "Retrying", "{msg} in {eta} {bar}", true);
+        let countdown = bar.countdown(std::time::Duration::from_millis(50));
+        assert_eq!(bar.get_len(), 50);
+        assert!(countdown.wait());
+        assert_eq!(bar.get_pos(), 50);
+
+        let countdown = bar.countdown(std::time::Duration::from_secs(60));
+        let countdown_clone = countdown.clone();
+        std::thread::spawn(move || countdown_clone.cancel());
+        assert!(!countdown.wait());
+        assert!(countdown.is_cancelled());
+        assert!(bar.get_pos() < 60_000);
+
+        let counting = || {
+            let (_, state) = bar.get_manager_and_state().unwrap();
+            let state = state.lock().unwrap();
+            state.source.is_some()
+        };
+        // Cancelling stops the bar without a waiting thread
+        bar.countdown(std::time::Duration::from_secs(60)).cancel();
+        assert!(!counting());
+        // A stale handle doesn't stop a newer countdown
+        let stale = bar.countdown(std::time::Duration::from_secs(60));
+        let countdown = bar.countdown(std::time::Duration::from_secs(60));
+        stale.cancel();
+        assert!(counting());
+        countdown.cancel();
+        assert!(!counting());
+
+        // The deadline follows the mocked clock
+        let clock = clock::MockClock::new();
+        let countdown = bar.countdown(std::time::Duration::from_secs(60));
+        clock.advance(std::time::Duration::from_secs(60));
+        assert!(countdown.wait());
+        assert_eq!(bar.get_pos(), 60_000);
+        assert!(!counting());
+    }
+
+    #[test]
+    fn parse_template() {
+        assert!(Template::parse("{msg}: {bar30} {{literal}}").is_ok());
+        assert_eq!(
+            Template::parse("{msg} {foo}").unwrap_err(),
+            TemplateError::UnknownTag("foo".to_string())
+        );
+        assert_eq!(
+            Template::parse("{bar3x}").unwrap_err(),
+            TemplateError::UnknownTag("bar3x".to_string())
+        );
+        assert_eq!(
+            Template::parse("{msg").unwrap_err(),
+            TemplateError::UnclosedTag("msg".to_string())
+        );
+    }
+
+    #[test]
+    fn templates_from_file() {
+        let path = std::env::temp_dir().join(format!("kyuri-templates-{}", std::process::id()));
+        std::fs::write(
+            &path,
+            "# comment\ndownload={msg}: {bytes}\nbad={foo}\nnoequal\n",
+        )
+        .unwrap();
+        let mut warnings = Vec::new();
+        let manager = Manager::new(std::time::Duration::from_secs(1))
+            .with_templates_from(&path, |w| warnings.push(w.to_string()));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(warnings.len(), 2);
+
+        let mut state = test_state(10, 5, "a", "");
+        state.template = manager.template("download", "{msg}");
+        assert_eq!(state.render(&test_options()), "a: 5 B");
+        state.template = manager.template("bad", "{msg}");
+        assert_eq!(state.render(&test_options()), "a");
+    }
+
+    #[test]
+    fn template_from_env() {
+        let var = format!("KYURI_TEST_TEMPLATE_{}", std::process::id());
+        let mut warnings = Vec::new();
+        let mut state = test_state(10, 5, "a", "");
+        state.template = Template::from_env(&var, "{msg}", |w| warnings.push(w.to_string()));
+        assert_eq!(state.render(&test_options()), "a");
+        std::env::set_var(&var, "{msg}: {pos}");
+        state.template = Template::from_env(&var, "{msg}", |w| warnings.push(w.to_string()));
+        assert_eq!(state.render(&test_options()), "a: 5");
+        // A typo falls back to the default with a warning
+        std::env::set_var(&var, "{msg}: {poss}");
+        state.template = Template::from_env(&var, "{msg}", |w| warnings.push(w.to_string()));
+        std::env::remove_var(&var);
+        assert_eq!(state.render(&test_options()), "a");
+        assert_eq!(warnings, [format!("{}: unknown tag {{poss}}", var)]);
+    }
+
+    #[test]
+    fn theme() {
+        let state = test_state(10, 5, "a", "{state_emoji} {bar10}");
+        let mut options = test_options();
+        let in_progress = &Theme::default().icon_in_progress;
+        assert_eq!(
+            state.render(&options),
+            format!("{} [=====     ]", in_progress)
+        );
+        options.theme = Arc::new(Theme::ascii());
+        assert_eq!(state.render(&options), "[>>] [#####-----]");
+        options.theme = Arc::new(Theme::minimal());
+        assert_eq!(state.render(&options), "… ━━━━━─────");
+
+        let manager = Manager::new(std::time::Duration::from_secs(1)).with_theme(Theme::ci());
+        let mut state = test_state(10, 10, "a", "");
+        state.template = manager.default_template();
+        assert_eq!(state.render(&options), "✓ a: 10/10 (0:00:00)");
+    }
+
+    #[test]
+    fn color_degradation() {
+        let state = test_state(10, 5, "a", "{#ff8700}{msg}{reset} {bold}{pos}");
+        let mut options = test_options();
+        assert_eq!(state.render(&options), "a 5");
+        options.color = ColorSupport::TrueColor;
+        let rendered = state.render(&options);
+        assert_eq!(rendered, "\x1b[38;2;255;135;0ma\x1b[0m \x1b[1m5\x1b[0m");
+        assert_eq!(string_width(&rendered), 3);
+        options.color = ColorSupport::Ansi256;
+        assert!(state.render(&options).starts_with("\x1b[38;5;208ma"));
+        options.color = ColorSupport::Ansi16;
+        assert!(state.render(&options).starts_with("\x1b[33ma"));
+    }
+
+    #[test]
+    fn gradient() {
+        let mut state = test_state(10, 5, "a", "{bar4}");
+        state.gradient = Some(Gradient::red_yellow_green());
+        let mut options = test_options();
+        assert_eq!(state.render(&options), "[==  ]");
+        options.color = ColorSupport::TrueColor;
+        assert_eq!(state.render(&options), "[\x1b[38;2;255;255;0m==\x1b[39m  ]");
+        state.pos = 10;
+        assert_eq!(state.render(&options), "[\x1b[38;2;0;255;0m====\x1b[39m]");
+    }
+
+    #[test]
+    fn effects() {
+        let mut state = test_state(10, 5, "a", "{msg}\n{pos}");
+        let mut options = test_options();
+        options.color = ColorSupport::Ansi16;
+        options.effects.failed = Some(Effect::PulseInverse);
+        options.effects.stalled = Some((
+            std::time::Duration::from_secs(3600),
+            Effect::Color(style::Color::Ansi16(1)),
+        ));
+        assert_eq!(state.render(&options), "a\n5");
+
+        state.failed = true;
+        assert_eq!(state.render(&options), "\x1b[7ma\x1b[0m\n\x1b[7m5\x1b[0m");
+        options.frame = 1;
+        assert_eq!(state.render(&options), "a\n5");
+
+        state.failed = false;
+        options.effects.stalled = Some((
+            std::time::Duration::ZERO,
+            Effect::Color(style::Color::Ansi16(1)),
+        ));
+        assert_eq!(state.render(&options), "\x1b[31ma\x1b[0m\n\x1b[31m5\x1b[0m");
+    }
+
+    #[test]
+    fn isolate_bidi() {
+        let state = test_state(10, 5, "שלום.txt", "{msg}: {pos}/{len}");
+        let mut options = test_options();
+        assert_eq!(state.render(&options), "שלום.txt: 5/10");
+        options.isolate_bidi = true;
+        let rendered = state.render(&options);
+        assert_eq!(rendered, "\u{2068}שלום.txt\u{2069}: 5/10");
+        assert_eq!(string_width(&rendered), string_width("שלום.txt: 5/10"));
+    }
+
+    #[cfg(target_os = "linux")]
+    fn memfd_manager(name: &str) -> (Manager, std::fs::File) {
+        let memfd_name = std::ffi::CString::new(name).unwrap();
+        let memfd_fd =
+            nix::sys::memfd::memfd_create(&memfd_name, nix::sys::memfd::MemFdCreateFlag::empty())
+                .unwrap();
+        let memfd_writer: std::fs::File = memfd_fd.into();
+        let memfd_writer_clone = memfd_writer.try_clone().unwrap();
+        let manager = Manager::new(std::time::Duration::from_secs(1)).with_file(memfd_writer);
+        (manager, memfd_writer_clone)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn read_memfd(mut memfd: std::fs::File) -> String {
+        memfd.seek(std::io::SeekFrom::Start(0)).unwrap();
+        let mut output = String::new();
+        memfd.read_to_string(&mut output).unwrap();
+        output
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn run_command() {
+        let (manager, memfd) = memfd_manager("run_command");
+        let bar = manager.create_bar(10, "Running", "{msg}", true);
+        let status = manager
+            .run_command(std::process::Command::new("sh").args(["-c", "echo hello; printf world"]))
+            .unwrap();
+        assert!(status.success());
+        std::mem::drop(bar);
+        std::mem::drop(manager);
+        assert_eq!(read_memfd(memfd), "Running\nhello\nworld\n");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn checklist() {
+        let (manager, memfd) = memfd_manager("checklist");
+        let checklist = manager.create_checklist();
+        let resolve = checklist.add_step("Resolve deps");
+        let build = checklist.add_step("Build");
+        resolve.start();
+        resolve.done();
+        build.start();
+        build.fail();
+        std::mem::drop(manager);
+        // Steps added after the manager is dropped do nothing
+        let late = checklist.add_step("Late");
+        late.start();
+        late.done();
+        #[cfg(not(feature = "ascii_only"))]
+        let expected = r#"🔲 Resolve deps
+🔲 Build
+⏳ Resolve deps (0:00:00)
+✅ Resolve deps (0:00:00)
+⏳ Build (0:00:00)
+❌ Build (0:00:00)
+"#;
+        #[cfg(feature = "ascii_only")]
+        let expected = r#"[  ] Resolve deps
+[  ] Build
+[>>] Resolve deps (0:00:00)
+[OK] Resolve deps (0:00:00)
+[>>] Build (0:00:00)
+[XX] Build (0:00:00)
+"#;
+        assert_eq!(read_memfd(memfd), expected);
+
+        // In ANSI mode, steps are drawn above bars created before, with durations by the mocked clock
+        let (manager, memfd) = memfd_manager("checklist");
+        let manager = manager.force_ansi(true);
+        let clock = clock::MockClock::new();
+        let _bar = manager.create_bar(10, "Working", "{msg}", true);
+        let checklist = manager.create_checklist();
+        let build = checklist.add_step("Build");
+        let test = checklist.add_step("Test");
+        build.start();
+        clock.advance(std::time::Duration::from_secs(2));
+        build.done();
+        test.start();
+        let output = read_memfd(memfd.try_clone().unwrap());
+        let frame = output.rsplit(CLEAR_ANSI).next().unwrap();
+        #[cfg(not(feature = "ascii_only"))]
+        assert_eq!(frame, "✅ Build (0:00:02)\n⏳ Test (0:00:00)\nWorking\n");
+        #[cfg(feature = "ascii_only")]
+        assert_eq!(
+            frame,
+            "[OK] Build (0:00:02)\n[>>] Test (0:00:00)\nWorking\n"
+        );
+        // Dropped steps are removed
+        std::mem::drop((checklist, build, test));
+        let output = read_memfd(memfd);
+        assert_eq!(output.rsplit(CLEAR_ANSI).next().unwrap(), "Working\n");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn log_line() {
+        let (manager, memfd) = memfd_manager("log_line");
+        let manager = manager.force_ansi(true);
+        manager.set_log_capacity(2);
+        let bar = manager.create_bar(10, "Working", "{msg}", true);
+        manager.log_line("a");
+        manager.log_line("b");
+        manager.log_line("c");
+        manager.draw(true);
+        std::mem::drop(manager);
+        std::mem::drop(bar);
+        let output = read_memfd(memfd);
+        let last_frame = output.rsplit(CLEAR_ANSI).next().unwrap();
+        assert_eq!(last_frame, "b\nc\nWorking\n");
+    }
+
+    #[test]
+    fn collapsed() {
+        let mut state = test_state(10, 5, "Working", "{msg}: {bar4}\n{elapsed}");
+        let options = test_options();
+        state.collapsed = true;
+        assert_eq!(state.render(&options), "Working (5/10)");
+        state.collapsed_template = Template::new("{msg}");
+        assert_eq!(state.render(&options), "Working");
+        state.collapsed = false;
+        assert_eq!(state.render(&options), "Working: [==  ]\n0:00:00");
+    }
+
+    #[test]
+    fn finished_template() {
+        let mut state = test_state(10, 5, "Copy", "{msg}: {bar4} {eta}");
+        state.finished_template = Some(Template::new("{msg}: done in {elapsed}"));
+        let options = test_options();
+        assert_eq!(state.render(&options), "Copy: [==  ] 0:00:00");
+        state.update_pos(10);
+        let finished_at = state.finished_at.unwrap();
+        assert_eq!(state.render(&options), "Copy: done in 0:00:00");
+        state.created_at = finished_at - std::time::Duration::from_secs(61);
+        assert_eq!(state.render(&options), "Copy: done in 0:01:01");
+        state.failed = true;
+        assert_eq!(state.render(&options), "Copy: [====] 0:00:00");
+        state.failed = false;
+        state.update_pos(9);
+        assert!(state.finished_at.is_none());
+    }
+
+    #[test]
+    fn non_terminal_template() {
+        let mut state = test_state(10, 5, "Copy", "{msg}: {bar4}");
+        let mut options = test_options();
+        options.non_terminal_template = Some(Arc::new(Template::new("{msg}: {pos}/{len}")));
+        assert_eq!(state.render(&options), "Copy: [==  ]");
+        options.is_terminal = false;
+        assert_eq!(state.render(&options), "Copy: 5/10");
+        state.non_terminal_template = Some(Template::new("{msg} {pos}"));
+        assert_eq!(state.render(&options), "Copy 5");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn ci_mode() {
+        let (manager, memfd) = memfd_manager("ci_mode");
+        let manager = manager.with_ci_mode(Some(ci::CiMode::GitHub));
+        let bar = manager.create_bar(100, "Download", "{msg} {pos}/{len}", true);
+        for i in 1..=25 {
+            bar.set_pos(i);
+            manager.draw(true);
+        }
+        let failed = manager.create_bar(10, "Build", "{msg} {pos}/{len}", true);
+        failed.set_pos(1);
+        failed.fail(true);
+        bar.set_pos(100);
+        manager.draw(true);
+        // Changes after ending are not printed
+        bar.set_message("Downloaded");
+        manager.draw(true);
+        let spinner = manager.create_bar(0, "Wait", "{msg}", true);
+        drop(spinner);
+        assert_eq!(
+            read_memfd(memfd),
+            "::group::Download\nDownload 0/100\nDownload 10/100\nDownload 20/100\n\
+             ::group::Build\nBuild 0/10\nDownload 100/100\n::endgroup::\nBuild 1/10\n::endgroup::\n\
+             ::group::Wait\nWait\n::endgroup::\n"
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn tqdm_style() {
+        let clock = clock::MockClock::new();
+        let (manager, memfd) = memfd_manager("tqdm_style");
+        let manager = manager.with_tqdm_style();
+        let bar = manager.create_bar(100, "Download", "{msg} {pos}", true);
+        let items = manager.create_bar(0, "", "{msg} {pos}", true);
+        clock.advance(std::time::Duration::from_secs(10));
+        // Not a milestone
+        bar.set_pos(5);
+        manager.draw(true);
+        bar.set_pos(50);
+        items.set_pos(50);
+        manager.draw(true);
+        clock.advance(std::time::Duration::from_secs(3600));
+        bar.set_pos(100);
+        manager.draw(true);
+        let filled = if cfg!(feature = "ascii_only") {
+            "#"
+        } else {
+            "█"
+        };
+        assert_eq!(
+            read_memfd(memfd),
+            format!(
+                "Download:   0%|          | 0/100 [00:00<?, ? it/s]\n\
+                 0it [00:00, ? it/s]\n\
+                 Download:  50%|{}     | 50/100 [00:10<00:10, 5 it/s]\n\
+                 Download: 100%|{}| 100/100 [1:00:10<00:00, 36.10 s/it]\n",
+                filled.repeat(5),
+                filled.repeat(10)
+            )
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn migrate() {
+        let clock = clock::MockClock::new();
+        let bootstrap = Manager::new(std::time::Duration::from_secs(1));
+        let mut bar = bootstrap.create_bar(10, "Loading", "{msg} {pos}", true);
+        bar.set_pos(3);
+        clock.advance(std::time::Duration::from_secs(7));
+        let (manager, memfd) = memfd_manager("migrate");
+        let other = manager.create_bar(10, "Other", "{msg} {pos}", true);
+        manager.adopt_bar(&mut bar);
+        assert_eq!(bootstrap.inner.states.lock().unwrap().len(), 0);
+        assert_eq!(bar.get_pos(), 3);
+        assert_eq!(bar.elapsed(), std::time::Duration::from_secs(7));
+        bar.set_pos(4);
+        manager.draw(true);
+        drop(other);
+        assert_eq!(read_memfd(memfd), "Other 0\nLoading 3\nLoading 4\n");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn scoped_target() {
+        let (manager, memfd) = memfd_manager("scoped_target");
+        let manager = manager.force_ansi(true);
+        let bar = manager.create_bar(10, "Working", "{msg} {pos}", true);
+        let scoped = nix::sys::memfd::memfd_create(
+            &std::ffi::CString::new("scoped").unwrap(),
+            nix::sys::memfd::MemFdCreateFlag::empty(),
+        )
+        .unwrap();
+        let scoped = std::fs::File::from(scoped);
+        let value = manager.scoped_target(scoped.try_clone().unwrap(), || {
+            bar.set_pos(5);
+            42
+        });
+        assert_eq!(value, 42);
+        // Not a terminal, so in append mode
+        assert_eq!(read_memfd(scoped), "Working 0\nWorking 5\n");
+        assert_eq!(read_memfd(memfd), "Working 0\n\x1b[F\r\x1b[KWorking 5\n");
+        assert_eq!(manager.target_kind(), TargetKind::File);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn terminal_lost() {
+        let clock = clock::MockClock::new();
+        let full = std::fs::File::options()
+            .write(true)
+            .open("/dev/full")
+            .unwrap();
+        let manager = Manager::new(std::time::Duration::from_secs(1))
+            .with_file(full)
+            .force_ansi(true);
+        let _bar = manager.create_bar(10, "Working", "{msg}", true);
+        // Even though ANSI mode is forced
+        let lost = || {
+            let mut out = manager.inner.out.lock().unwrap();
+            !manager.inner.is_terminal(&mut out)
+        };
+        assert!(lost());
+        manager.draw(true);
+        assert_eq!(manager.inner.ansi_backend.lock().unwrap().lines(), 0);
+        // Checked again later, but still not a terminal
+        clock.advance(TERMINAL_RECHECK_INTERVAL);
+        assert!(lost());
+        assert_eq!(
+            *manager.inner.terminal_lost.lock().unwrap(),
+            Some(clock::now())
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn env_overrides() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+        let (manager, memfd) = memfd_manager("env_overrides");
+        let manager = manager.with_env(env(&[
+            ("KYURI_INTERVAL", "250"),
+            ("KYURI_FORCE_ANSI", "1"),
+            ("KYURI_MAX_BARS", "2"),
+            ("KYURI_DISABLE", "invalid"),
+        ]));
+        // Builders called afterwards don't override the environment
+        let manager = manager.force_ansi(false);
+        assert_eq!(
+            manager.inner.interval(),
+            std::time::Duration::from_millis(250)
+        );
+        let bars: Vec<_> = (0..4)
+            .map(|i| manager.create_bar(10, &format!("bar{}", i), "{msg}", true))
+            .collect();
+        manager.draw(true);
+        let output = read_memfd(memfd);
+        assert!(output.ends_with("bar0\nbar1\n... and 2 more\n"));
+        drop(bars);
+
+        let (manager, memfd) = memfd_manager("env_overrides");
+        let manager = manager
+            .with_env(env(&[("KYURI_DISABLE", "1")]))
+            .force_ansi(true);
+        let _bar = manager.create_bar(10, "bar", "{msg}", true);
+        manager.draw(true);
+        // Output of the user is still written, without bars
+        manager.suspend(|out| writeln!(out, "hello")).unwrap();
+        manager.log_line("log");
+        assert_eq!(read_memfd(memfd), "hello\nlog\n");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn shutdown() {
+        let (manager, memfd) = memfd_manager("shutdown");
+        manager.set_ticker(true);
+        let bar = manager.create_bar(10, "Working", "{msg} {pos}", true);
+        bar.set_pos(3);
+        manager.shutdown().unwrap();
+        assert!(!bar.alive());
+        assert_eq!(read_memfd(memfd), "Working 0\nWorking 3\n");
+
+        let full = std::fs::File::options()
+            .write(true)
+            .open("/dev/full")
+            .unwrap();
+        let manager = Manager::new(std::time::Duration::from_secs(1)).with_file(full);
+        let bar = manager.create_bar(10, "Working", "{msg}", true);
+        bar.set_pos(1);
+        assert!(manager.shutdown().is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn detached() {
+        let (manager, memfd) = memfd_manager("detached");
+        let bar = manager.create_bar(10, "Working", "{msg} {pos}", true);
+        let bar = bar.into_detached().unwrap();
+        std::mem::drop(manager);
+        assert!(bar.alive());
+        bar.finish();
+        std::mem::drop(bar);
+        assert_eq!(read_memfd(memfd), "Working 0\nWorking 10\n");
+
+        let manager = Manager::new(std::time::Duration::from_secs(1));
+        let bar = manager.create_bar(10, "Working", "{msg}", false);
+        std::mem::drop(manager);
+        assert!(bar.into_detached().is_none());
+    }
+
+    #[test]
+    fn manager_gone() {
+        let manager = Manager::new(std::time::Duration::from_secs(1));
+        let bar = manager.create_bar(10, "Working", "{msg}", false);
+        assert_eq!(bar.try_inc(1), Ok(()));
+        assert_eq!(bar.try_get_pos(), Ok(1));
+        std::mem::drop(manager);
+        assert_eq!(bar.try_set_pos(2), Err(ManagerGone));
+        assert_eq!(bar.try_set_message("Done"), Err(ManagerGone));
+        assert_eq!(bar.try_finish(), Err(ManagerGone));
+        assert_eq!(bar.try_get_pos(), Err(ManagerGone));
+    }
+
+    #[test]
+    fn thread_tags() {
+        let mut state = test_state(10, 0, "Working", "{thread} {thread_id}");
+        let options = test_options();
+        state.thread = std::thread::Builder::new()
+            .name("worker".to_string())
+            .spawn(ThreadTag::current)
+            .unwrap()
+            .join()
+            .unwrap();
+        let id = state.thread.number;
+        assert_eq!(state.render(&options), format!("worker {id}"));
+        state.thread = std::thread::spawn(ThreadTag::current).join().unwrap();
+        let other = state.thread.number;
+        assert_ne!(other, id);
+        assert_eq!(state.render(&options), format!("{other} {other}"));
+        // Stable within a thread
+        assert_eq!(ThreadTag::current().number, ThreadTag::current().number);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn update() {
+        let (manager, memfd) = memfd_manager("update");
+        let bar = manager.create_bar(10, "Working", "{msg} {pos}/{len}", true);
+        bar.update(|u| {
+            u.len(100).pos(50).message("Copying").inc(1);
+        });
+        assert_eq!(bar.get_pos(), 51);
+        assert_eq!(bar.get_len(), 100);
+        bar.update(|u| {
+            u.template("{msg}: {pos}").pos(100);
+        });
+        std::mem::drop(bar);
+        std::mem::drop(manager);
+        assert_eq!(read_memfd(memfd), "Working 0/10\nCopying: 100\n");
+    }
+
+    #[test]
+    fn rate_getters() {
+        let mut state = test_state(100, 0, "Working", "{eta}");
+        let options = test_options();
+        state.created_at = std::time::Instant::now() - std::time::Duration::from_secs(10);
+        assert_eq!(state.eta(), None);
+        assert_eq!(state.render(&options), "Unknown");
+        state.pos = 20;
+        assert!((state.per_sec() - 2.0).abs() < 0.1);
+        // 80 left at about 2 per second
+        assert!((39..=40).contains(&state.eta().unwrap().as_secs()));
+        assert!(["0:00:39", "0:00:40"].contains(&state.render(&options).as_str()));
+        state.pos = 120;
+        assert_eq!(state.eta(), Some(std::time::Duration::ZERO));
+
+        let manager = Manager::new(std::time::Duration::from_secs(1));
+        let bar = manager.create_bar(10, "Working", "{msg}", false);
+        assert_eq!(bar.eta(), None);
+        std::mem::drop(manager);
+        assert_eq!(bar.elapsed(), std::time::Duration::ZERO);
+        assert_eq!(bar.per_sec(), 0.0);
+    }
+
+    #[test]
+    fn wait_finished() {
+        let manager = Manager::new(std::time::Duration::from_secs(1));
+        let bar = manager.create_bar(10, "Working", "{msg}", false);
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                for _ in 0..10 {
+                    std::thread::sleep(std::time::Duration::from_millis(1));
+                    bar.inc(1);
+                }
+            });
+            assert!(bar.wait_finished());
+        });
+
+        bar.set_pos(0);
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                std::thread::sleep(std::time::Duration::from_millis(10));
+                bar.fail(true);
+            });
+            assert!(!bar.wait_finished());
+        });
+
+        bar.fail(false);
+        struct NoopWake;
+        impl std::task::Wake for NoopWake {
+            fn wake(self: Arc<Self>) {}
+        }
+        let waker = std::task::Waker::from(Arc::new(NoopWake));
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut future = std::pin::pin!(bar.finished_future());
+        assert!(future.as_mut().poll(&mut cx).is_pending());
+        bar.finish();
+        assert_eq!(future.as_mut().poll(&mut cx), std::task::Poll::Ready(true));
+
+        bar.set_pos(0);
+        std::thread::scope(|s| {
+            s.spawn(move || {
+                std::thread::sleep(std::time::Duration::from_millis(10));
+                std::mem::drop(manager);
+            });
+            assert!(!bar.wait_finished());
+        });
+    }
+
+    #[test]
+    fn on_progress_at() {
+        let manager = Manager::new(std::time::Duration::from_secs(1));
+        let bar = manager.create_bar(200, "Working", "{msg}", false);
+        let reached = Arc::new(Mutex::new(Vec::new()));
+        let reached_clone = reached.clone();
+        bar.set_pos(120);
+        bar.on_progress_at(&[25, 50, 75, 100], move |percent| {
+            reached_clone.lock().unwrap().push(percent);
+        });
+        assert_eq!(*reached.lock().unwrap(), vec![25, 50]);
+        bar.set_pos(100);
+        bar.inc(60);
+        bar.set_pos(0);
+        bar.inc(160);
+        assert_eq!(*reached.lock().unwrap(), vec![25, 50, 75]);
+        bar.finish();
+        assert_eq!(*reached.lock().unwrap(), vec![25, 50, 75, 100]);
+    }
+
+    #[test]
+    fn eta_strategy() {
+        use eta::{EtaEstimator, EtaStrategy};
+        let start = std::time::Instant::now() - std::time::Duration::from_secs(100);
+        let at = |secs| start + std::time::Duration::from_secs(secs);
+        let elapsed = std::time::Duration::from_secs(100);
+
+        // Slow at first, then fast: 10 in the first 90s, then 90 in 10s
+        let mut estimators = [
+            EtaEstimator::new(EtaStrategy::Linear),
+            EtaEstimator::new(EtaStrategy::RecentWindow(std::time::Duration::from_secs(
+                10,
+            ))),
+            EtaEstimator::new(EtaStrategy::Kalman),
+        ];
+        for estimator in estimators.iter_mut() {
+            for secs in 1..=90 {
+                estimator.record(at(secs), secs / 9);
+            }
+            for secs in 91..=100 {
+                estimator.record(at(secs), 10 + (secs - 90) * 9);
+            }
+        }
+        let [linear, recent, kalman] = estimators.map(|e| e.estimate(100, 200, elapsed).unwrap());
+        assert_eq!(linear.as_secs(), 100);
+        assert!((10..=12).contains(&recent.as_secs()));
+        assert!(kalman < linear / 2);
+
+        let custom = EtaEstimator::new(EtaStrategy::Custom(Box::new(|pos, len, _| {
+            Some(std::time::Duration::from_secs(len - pos))
+        })));
+        assert_eq!(
+            custom.estimate(1, 3, elapsed),
+            Some(std::time::Duration::from_secs(2))
+        );
+        assert_eq!(EtaEstimator::default().estimate(0, 10, elapsed), None);
+    }
+
+    #[test]
+    fn huge_values() {
+        assert_eq!(bytes_to_human(1023, 2), "1023 B");
+        assert_eq!(bytes_to_human(1536, 2), "1.50 KiB");
+        assert_eq!(bytes_to_human((1 << 20) - 1, 2), "1.00 MiB");
+        assert_eq!(bytes_to_human(1 << 50, 2), "1.00 PiB");
+        assert_eq!(bytes_to_human(u64::MAX, 2), "16.00 EiB");
+
+        let mut state = test_state(
+            u64::MAX,
+            1 << 20,
+            "Long",
+            "{bytes}/{total_bytes} {bytes_per_sec} {eta}",
+        );
+        let options = test_options();
+        state.created_at = std::time::Instant::now() - std::time::Duration::from_secs(1);
+        let rendered = state.render(&options);
+        assert!(
+            rendered.starts_with("1.00 MiB/16.00 EiB 1.00 MiB/s "),
+            "{rendered}"
+        );
+        // About 2^44 seconds left, which is about 4.9 billion hours
+        let hours = rendered
+            .rsplit(' ')
+            .next()
+            .unwrap()
+            .split(':')
+            .next()
+            .unwrap();
+        assert_eq!(hours.len(), 10, "{rendered}");
+        state.pos = u64::MAX;
+        state.update_finished();
+        state.created_at = std::time::Instant::now() - std::time::Duration::from_millis(1);
+        assert!(state.render(&options).ends_with(" 0:00:00"));
+    }
+
+    #[test]
+    fn fixed_units() {
+        let options = test_options();
+        let template =
+            "{mib_pos}/{gib_total} {bytes:unit=B} {total_bytes:unit=KiB} {bytes:unit=mib}";
+        let state = test_state(3 << 30, 512 << 10, "", template);
+        assert_eq!(
+            state.render(&options),
+            "0.50 MiB/3.00 GiB 524288 B 3145728.00 KiB {bytes:unit=mib}"
+        );
+        let template = "{bytes:.0} {bytes:.1} {mib_pos:.0} {all_total_bytes:unit=B}";
+        let mut options = test_options();
+        options.aggregate.total_bytes = 3 << 30;
+        assert_eq!(
+            test_state(3 << 30, (1023 << 10) + 768, "", template).render(&options),
+            "1 MiB 1023.8 KiB 1 MiB 3221225472 B"
+        );
+        options.byte_precision = 0;
+        assert_eq!(
+            test_state(0, 1536, "", "{bytes} {bytes:.3}").render(&options),
+            "2 KiB 1.500 KiB"
+        );
+        assert_eq!(
+            Template::parse("{bytes:unit=mib}").unwrap_err(),
+            TemplateError::UnknownTag("bytes:unit=mib".to_string())
+        );
+    }
+
+    #[test]
+    fn stable_width() {
+        let mut options = test_options();
+        let template = "{pos}/{len} {bytes} {mib_pos:.0} {bytes_per_sec}|";
+        let mut state = test_state(3 << 20, 5, "", template);
+        state.created_at = std::time::Instant::now() - std::time::Duration::from_secs(3600);
+        assert_eq!(state.render(&options), "5/3145728 5 B 0 MiB 0 B/s|");
+        options.stable_width = true;
+        let rendered = state.render(&options);
+        assert_eq!(rendered, "      5/3145728         5 B 0 MiB         0 B/s|");
+        state.pos = 3 << 20;
+        assert_eq!(state.render(&options).len(), rendered.len());
+    }
+
+    #[test]
+    fn overflow() {
+        let options = test_options();
+        let theme = Theme::default();
+        let state = test_state(10, 15, "a", "{bar10} {state_emoji} {eta}");
+        assert_eq!(
+            state.render(&options),
+            format!("[==========!!!!! {} 0:00:00", theme.icon_overflowed)
+        );
+        let state = test_state(10, 1000, "a", "{bar10}");
+        assert_eq!(state.render(&options), "[==========!!!!!!!!!!");
+        let state = test_state(0, 1, "a", "{bar4}");
+        assert_eq!(state.render(&options), "[====!!!!");
+        let state = test_state(0, 0, "a", "{bar4} {state_emoji}");
+        assert_eq!(
+            state.render(&options),
+            format!("[    ] {}", theme.icon_finished)
+        );
+
+        let manager = Manager::new(std::time::Duration::from_secs(1));
+        let bar = manager.create_bar(10, "a", "{msg}", false);
+        bar.set_pos(u64::MAX - 1);
+        bar.inc(2);
+        assert_eq!(bar.get_pos(), u64::MAX);
+        bar.update(|u| {
+            u.inc(1);
+        });
+        assert_eq!(bar.get_pos(), u64::MAX);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn log_level() {
+        let (manager, memfd) = memfd_manager("log_level");
+        let bar = manager.create_bar(10, "a", "{msg} {pos}", true);
+        bar.set_log_level(LogChanges::PositionOnly);
+        bar.set_message("b");
+        manager.draw(true);
+        bar.set_pos(1);
+        manager.draw(true);
+        bar.set_message("c");
+        manager.draw(true);
+        bar.set_log_level(LogChanges::Nothing);
+        bar.set_pos(2);
+        manager.draw(true);
+        bar.set_log_level(LogChanges::All);
+        bar.set_message("d");
+        manager.draw(true);
+        std::mem::drop(bar);
+        std::mem::drop(manager);
+        assert_eq!(read_memfd(memfd), "a 0\nb 1\nd 2\n");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn min_delta() {
+        let (manager, memfd) = memfd_manager("min_delta");
+        let bar = manager.create_bar(1000, "a", "{pos}", true);
+        bar.set_min_delta(100);
+        bar.set_min_percent_delta(20.0);
+        for _ in 0..1000 {
+            bar.inc(1);
+            manager.draw(true);
+        }
+        std::mem::drop(bar);
+        std::mem::drop(manager);
+        assert_eq!(read_memfd(memfd), "0\n200\n400\n600\n800\n1000\n");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn force_debounce() {
+        let (manager, memfd) = memfd_manager("force_debounce");
+        let manager = manager
+            .force_ansi(true)
+            .with_force_debounce(std::time::Duration::from_secs(3600));
+        let bar = manager.create_bar(10, "a", "{msg}", true);
+        for _ in 0..10 {
+            manager.create_bar(10, "b", "{msg}", true).finish_and_drop();
+        }
+        let bar_c = manager.create_bar(10, "c", "{msg}", true);
+        // Within the debounce since the manager is created
+        assert_eq!(read_memfd(memfd.try_clone().unwrap()), "");
+        manager.draw(true);
+        std::mem::drop((bar, bar_c));
+        std::mem::drop(manager);
+        assert_eq!(read_memfd(memfd), "a\nc\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skipped_frames() {
+        let manager = Manager::new(std::time::Duration::from_millis(20)).with_file(
+            std::fs::OpenOptions::new()
+                .write(true)
+                .open("/dev/null")
+                .unwrap(),
+        );
+        // A source taking 200ms to poll makes every frame slower than the interval
+        let pos = std::sync::atomic::AtomicU64::new(0);
+        let bar = manager.create_bar_with_source(100, "Working", "{msg} {pos}", move || {
+            std::thread::sleep(std::time::Duration::from_millis(200));
+            pos.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+        });
+        manager.set_ticker(true);
+        std::thread::sleep(std::time::Duration::from_millis(1000));
+        manager.set_ticker(false);
+        let stats = manager.stats();
+        assert!(stats.frames > 0);
+        assert!(stats.skipped_frames > 0);
+        assert!(stats.last_frame_time >= std::time::Duration::from_millis(200));
+        std::mem::drop(bar);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn render_thread() {
+        let (_, memfd) = memfd_manager("render_thread");
+        let manager = Manager::new(std::time::Duration::from_secs(3600))
+            .with_file(memfd.try_clone().unwrap())
+            .with_render_thread();
+        manager.set_ticker(false);
+        assert!(manager.inner.is_ticker_enabled());
+        let bar = manager.create_bar(10, "a", "{msg} {pos}/{len}", true);
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert_eq!(read_memfd(memfd.try_clone().unwrap()), "a 0/10\n");
+        // Not drawn until the next tick
+        bar.set_pos(5);
+        assert_eq!(read_memfd(memfd.try_clone().unwrap()), "a 0/10\n");
+        // Events are drawn by the ticker thread
+        bar.finish();
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert_eq!(read_memfd(memfd), "a 0/10\na 10/10\n");
     }
-}
 
-impl Bar {
-    fn get_manager_and_state(&self) -> Option<(Arc<ManagerInner>, Arc<Mutex<BarState>>)> {
-        let manager = self.manager.upgrade()?;
-        let state = manager.states.lock().unwrap().get(&self.id)?.clone();
-        Some((manager, state))
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn set_interval() {
+        let (manager, memfd) = memfd_manager("set_interval");
+        manager.set_interval(std::time::Duration::from_secs(3600));
+        manager.set_ticker(true);
+        let bar = manager.create_bar(10, "a", "{msg} {pos}/{len}", true);
+        bar.set_pos(5);
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert_eq!(read_memfd(memfd.try_clone().unwrap()), "a 0/10\n");
+        // The ticker doesn't wait for the old interval
+        manager.set_interval(std::time::Duration::from_millis(10));
+        assert_eq!(manager.interval(), std::time::Duration::from_millis(10));
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        assert_eq!(read_memfd(memfd), "a 0/10\na 5/10\n");
     }
 
-    fn check_if_force_draw(&self, manager: Arc<ManagerInner>, pos: u64, len: u64) {
-        if pos == len && manager
-            .force_when_finished
-            .load(std::sync::atomic::Ordering::Acquire)
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn burst() {
+        let (manager, memfd) = memfd_manager("burst");
+        let manager = manager.with_burst(2);
+        let bar = manager.create_bar(10, "a", "{msg} {pos}", true);
+        for i in 1..=5 {
+            bar.set_pos(i);
+        }
+        // Two tokens for the first changes, then settled to the interval
+        assert_eq!(read_memfd(memfd.try_clone().unwrap()), "a 0\na 1\na 2\n");
+
+        // Draws after the interval spend tokens too, so a steady stream is drawn once per interval
+        let clock = clock::MockClock::new();
+        clock.advance(std::time::Duration::from_secs(10));
+        let (manager, memfd) = memfd_manager("burst");
+        let manager = manager.with_burst(2);
+        let bar = manager.create_bar(1000, "a", "{msg} {pos}", true);
+        for i in 1..=52 {
+            bar.set_pos(i);
+            clock.advance(std::time::Duration::from_millis(100));
+        }
+        // "a 0", two in the burst, then one per second
+        assert_eq!(read_memfd(memfd).lines().count(), 3 + 5);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn frame_filter() {
+        let (manager, memfd) = memfd_manager("frame_filter");
+        let manager = manager.force_ansi(true);
+        let theme = Theme::default();
+        let finished = theme.icon_finished.clone();
+        manager.set_frame_filter(move |frame| {
+            frame
+                .lines()
+                .map(|line| format!("<6>{}\n", line.replace(&finished, "")))
+                .collect()
+        });
+        let bar = manager.create_bar(10, "a", "{state_emoji}{msg} {pos}", true);
+        bar.set_pos(10);
+        manager.clear_frame_filter();
+        manager.draw(true);
+        assert_eq!(
+            read_memfd(memfd),
+            format!(
+                "<6>{}a 0\n\x1b[F\r\x1b[K<6>a 10\n\x1b[F\r\x1b[K{}a 10\n",
+                theme.icon_new, theme.icon_finished
+            )
+        );
+    }
+
+    #[test]
+    fn panel() {
+        // Box characters are replaced with the `ascii_only` feature, see `ascii_only`
+        #[cfg(not(feature = "ascii_only"))]
         {
-            manager.draw(true);
-        } else {
-            manager.draw(false);
+            let panel = panel::Panel::new("Downloads");
+            assert_eq!(panel.top(20), "┌─ Downloads ──────┐\n");
+            assert_eq!(panel.bottom(6), "└────┘\n");
+            // Truncated to fit
+            assert_eq!(panel.top(10), "┌─ Down ─┐\n");
         }
+        assert_eq!(panel::Panel::ascii("").top(6), "+----+\n");
     }
 
-    /// Increment the progress bar by `n`. This makes an unforced draw.
-    pub fn inc(&self, n: u64) {
-        if let Some((manager, state)) = self.get_manager_and_state() {
-            let mut state = state.lock().unwrap();
-            state.pos += n;
-            state.need_redraw = true;
-            let pos = state.pos;
-            let len = state.len;
-            // Drop state before drawing, deadlock otherwise!
-            std::mem::drop(state);
-            manager.mark_redraw();
-            self.check_if_force_draw(manager, pos, len);
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn panel_drawn() {
+        let (manager, memfd) = memfd_manager("panel_drawn");
+        let manager = manager.force_ansi(true);
+        manager.set_panel(Some(panel::Panel::ascii("Jobs")));
+        let bar = manager.create_bar(10, "a", "{msg} {pos}", true);
+        drop(bar);
+        // 80 columns, as the width of a memfd is unknown
+        let top = format!("+- Jobs {}+\n", "-".repeat(71));
+        let bottom = format!("+{}+\n", "-".repeat(78));
+        assert_eq!(read_memfd(memfd), format!("{}a 0\n{}", top, bottom));
+    }
+
+    #[test]
+    fn split_layout() {
+        let lines = |lines: &[&str]| lines.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+        let left = lines(&["aaaa", "aa"]);
+        let right = lines(&["bbbbbb"]);
+        // Both fit
+        assert_eq!(layout::split(&left, &right, 20), "aaaa | bbbbbb\naa  \n");
+        // The narrower left column keeps its width
+        assert_eq!(layout::split(&left, &right, 11), "aaaa | bbbb\naa  \n");
+        // Both are wider than half
+        let right = lines(&["", "\x1b[31mbbbbbb\x1b[0m"]);
+        assert_eq!(
+            layout::split(&lines(&["aaaaaa"]), &right, 9),
+            "aaa | \n    | \x1b[31mbbb\x1b[0m\n"
+        );
+        // No room to split
+        assert_eq!(
+            layout::split(&left, &right, 4),
+            "aaaa\naa\n\n\x1b[31mbbbbbb\x1b[0m\n"
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn split_drawn() {
+        let (manager, memfd) = memfd_manager("split_drawn");
+        let manager = manager.force_ansi(true);
+        manager.set_split(true);
+        let a = manager.create_bar(10, "a", "{msg} {pos}", true);
+        let b = manager.create_bar(10, "b", "{msg} {pos}", true);
+        let c = manager.create_bar(10, "c", "{msg} {pos}", true);
+        b.set_column(layout::Column::Right);
+        manager.draw(true);
+        assert!(read_memfd(memfd).ends_with("a 0 | b 0\nc 0\n"));
+        drop((a, b, c));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn status_line() {
+        let clock = clock::MockClock::new();
+        let (manager, memfd) = memfd_manager("status_line");
+        let manager = manager.force_ansi(true);
+        manager.set_status_line(Some("up {elapsed}, {active_bars} running"));
+        manager.draw(true);
+        clock.advance(std::time::Duration::from_secs(2));
+        let bar = manager.create_bar(10, "a", "{msg} {pos}", true);
+        drop(bar);
+        clock.advance(std::time::Duration::from_secs(1));
+        manager.draw(true);
+        // Kept like the last frame of bars
+        manager.set_status_line(None::<&str>);
+        manager.draw(true);
+        assert_eq!(
+            read_memfd(memfd),
+            "up 0:00:00, 0 running\n\
+             \x1b[F\r\x1b[Ka 0\nup 0:00:02, 1 running\n\
+             \x1b[F\r\x1b[K\x1b[F\r\x1b[Kup 0:00:02, 0 running\n\
+             \x1b[F\r\x1b[Kup 0:00:03, 0 running\n"
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn lazy_bar() {
+        let (manager, memfd) = memfd_manager("lazy_bar");
+        let manager = manager.force_ansi(true);
+        let a = manager.create_bar(10, "a", "{msg} {pos}", true);
+        let lazy = (0..3)
+            .map(|i| manager.create_lazy_bar(10, &format!("lazy{}", i), "{msg} {pos}"))
+            .collect::<Vec<_>>();
+        assert!(!lazy[1].is_visible());
+        // Shown with a forced draw, within the interval
+        lazy[1].inc(1);
+        assert!(lazy[1].is_visible());
+        lazy[2].set_len(20);
+        assert!(!lazy[2].is_visible());
+        assert_eq!(read_memfd(memfd), "a 0\n\x1b[F\r\x1b[Ka 0\nlazy1 1\n");
+        drop((a, lazy));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn create_bar_with() {
+        let (manager, memfd) = memfd_manager("create_bar_with");
+        let mut fields = BTreeMap::new();
+        fields.insert("host".to_string(), "example.com".to_string());
+        let bar = manager.create_bar_with(BarOptions {
+            len: 10,
+            message: "a".to_string(),
+            template: Some("{msg} {bar}".into()),
+            non_terminal_template: Some("{msg} {field:host} {pos}/{len}".into()),
+            fields,
+            ..BarOptions::default()
+        });
+        bar.set_pos(10);
+        let hidden = manager.create_bar_with(BarOptions {
+            visible: false,
+            lazy: true,
+            ..BarOptions::default()
+        });
+        hidden.set_pos(1);
+        assert!(!hidden.is_visible());
+        assert_eq!(
+            read_memfd(memfd.try_clone().unwrap()),
+            "a example.com 0/10\na example.com 10/10\n"
+        );
+
+        let (manager, memfd) = memfd_manager("create_bar_with");
+        let manager = manager.force_ansi(true);
+        manager.set_verbosity(Verbosity::Detailed);
+        let bar = manager.create_bar_with(BarOptions {
+            len: 10,
+            message: "b".to_string(),
+            template: Some("{spinner} {msg} {pos}".into()),
+            detail: Some("  detail of {msg}".into()),
+            spinner: Some((vec!["<>".to_string()], std::time::Duration::from_secs(1))),
+            min_delta: 5,
+            eta_strategy: eta::EtaStrategy::Custom(Box::new(|_, _, _| {
+                Some(std::time::Duration::from_secs(42))
+            })),
+            ..BarOptions::default()
+        });
+        assert_eq!(bar.eta(), Some(std::time::Duration::from_secs(42)));
+        // Not redrawn until the delta is reached
+        bar.set_pos(1);
+        assert!(!read_memfd(memfd.try_clone().unwrap()).contains("b 1"));
+        let collapsed = manager.create_bar_with(BarOptions {
+            len: 10,
+            message: "c".to_string(),
+            collapsed: true,
+            ..BarOptions::default()
+        });
+        manager.draw(true);
+        let output = read_memfd(memfd);
+        let last_frame = output.rsplit(CLEAR_ANSI).next().unwrap();
+        assert_eq!(last_frame, "<> b 1\n  detail of b\nc (0/10)\n");
+        drop(collapsed);
+    }
+
+    #[test]
+    fn template_macro() {
+        let template = crate::template!("{msg}: {bar10} {{pos}} {#ff8800}{field:host}");
+        assert_eq!(
+            template.parts.len(),
+            Template::new("{msg}: {bar10} {{pos}} {#ff8800}{field:host}")
+                .parts
+                .len()
+        );
+        // The const check agrees with strict parsing
+        for tag in [
+            "msg",
+            "elapsed_precise",
+            "bar",
+            "bar1024",
+            "bar1025",
+            "bar+5",
+            "barx",
+            "bar99999999999999999999999",
+            "field:",
+            "field:x",
+            "red",
+            "bright_cyan",
+            "bright_",
+            "color:255",
+            "color:256",
+            "#00ff0a",
+            "#00ff0",
+            "#00fg0a",
+            "reset",
+            "inverse",
+            "bytes:unit=MiB",
+            "bytes_per_sec:unit=B",
+            "total_bytes:unit=EiB",
+            "bytes:unit=mib",
+            "bytes:unit=",
+            "pos:unit=MiB",
+            "mib_pos",
+            "gib_total",
+            "mib_len",
+            "mib_",
+            "bytes:.0",
+            "mib_pos:.1",
+            "bytes_per_sec:unit=MiB:.1",
+            "all_bytes:.9:unit=B",
+            "bytes:.10",
+            "bytes:.1:.1",
+            "bytes:.x",
+            "mib_pos:unit=GiB",
+            "bytes:",
+            "",
+            "foo",
+        ] {
+            let template = format!("a {{{}}} b", tag);
+            let expected = match Template::parse(&template) {
+                Ok(_) => Ok(()),
+                Err(TemplateError::BarTooWide(_)) => Err(template::CheckError::BarTooWide),
+                Err(_) => Err(template::CheckError::UnknownTag),
+            };
+            assert_eq!(template::check(&template), expected, "{}", template);
         }
+        assert_eq!(
+            template::check("a {{ {msg"),
+            Err(template::CheckError::UnclosedTag)
+        );
     }
 
-    /// Set the position of the progress bar. This makes an unforced draw.
-    pub fn set_pos(&self, pos: u64) {
-        if let Some((manager, state)) = self.get_manager_and_state() {
-            let mut state = state.lock().unwrap();
-            state.pos = pos;
-            state.need_redraw = true;
-            let pos = state.pos;
-            let len = state.len;
-            // Drop state before drawing, deadlock otherwise!
-            std::mem::drop(state);
-            manager.mark_redraw();
-            self.check_if_force_draw(manager, pos, len);
+    #[cfg(feature = "ascii_only")]
+    #[test]
+    fn ascii_only() {
+        let options = test_options();
+        let state = test_state(10, 10, "a", "{state_emoji} {spinner} {msg}");
+        assert_eq!(state.render(&options), "[OK] | a");
+        assert_eq!(panel::Panel::new("").bottom(4), "+--+\n");
+        assert_eq!(panel::Panel::new("Downloads").top(10), "+- Down -+\n");
+        // Nothing but ASCII in any state with the default theme
+        for (len, pos, failed) in [
+            (10, 0, false),
+            (10, 5, false),
+            (10, 15, false),
+            (10, 5, true),
+        ] {
+            let mut state = test_state(len, pos, "a", "{state_emoji} {spinner} {bar10} {msg}");
+            state.failed = failed;
+            let rendered = state.render(&options);
+            assert!(rendered.is_ascii(), "{rendered}");
         }
+        let mut state = test_state(100, 50, "Download", "");
+        state.created_at = std::time::Instant::now() - std::time::Duration::from_secs(10);
+        assert!(tqdm::render(&state).contains("|#####     |"));
     }
 
-    /// Set the total length of the progress bar. This makes an unforced draw.
-    pub fn set_len(&self, len: u64) {
-        if let Some((manager, state)) = self.get_manager_and_state() {
-            let mut state = state.lock().unwrap();
-            state.len = len;
-            state.need_redraw = true;
-            let pos = state.pos;
-            let len = state.len;
-            // Drop state before drawing, deadlock otherwise!
-            std::mem::drop(state);
-            manager.mark_redraw();
-            self.check_if_force_draw(manager, pos, len);
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn encoder() {
+        let (manager, memfd) = memfd_manager("encoder");
+        manager.set_encoder(encoding::latin1);
+        let bar = manager.create_bar(10, "café €", "{msg} {pos}", true);
+        manager.set_encoder(encoding::ascii);
+        bar.set_message("naïve ✓");
+        manager.draw(true);
+        manager.clear_encoder();
+        bar.set_message("ok ✓");
+        manager.draw(true);
+        let mut memfd = memfd;
+        memfd.seek(std::io::SeekFrom::Start(0)).unwrap();
+        let mut output = Vec::new();
+        memfd.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"caf\xe9 ? 0\nna?ve ? 0\nok \xe2\x9c\x93 0\n");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn accessible() {
+        let clock = clock::MockClock::new();
+        let (manager, memfd) = memfd_manager("accessible");
+        let manager = manager
+            .force_ansi(true)
+            .with_accessible(Some(std::time::Duration::from_secs(10)));
+        // Unforced draws of `inc` are within the interval, even with the clock advanced
+        manager.set_interval(std::time::Duration::from_secs(60));
+        let bar = manager.create_bar(10, "Download", "{msg} {bar}", true);
+        let counter = manager.create_bar(0, "", "{pos}", true);
+        for _ in 0..3 {
+            clock.advance(std::time::Duration::from_secs(4));
+            bar.inc(1);
+            counter.inc(1);
+            manager.draw(true);
+        }
+        bar.set_pos(10);
+        counter.fail(true);
+        manager.draw(true);
+        assert_eq!(
+            read_memfd(memfd),
+            "Download: 0 of 10, 0 percent\n0\n\
+             Download: 3 of 10, 30 percent\n3\n\
+             Download: done\nfailed\n"
+        );
+
+        // A shorter interval prints each step
+        let (manager, memfd) = memfd_manager("accessible");
+        let manager = manager.with_accessible(Some(std::time::Duration::from_secs(4)));
+        manager.set_interval(std::time::Duration::from_secs(60));
+        let bar = manager.create_bar(10, "Download", "{msg} {bar}", true);
+        for _ in 0..2 {
+            clock.advance(std::time::Duration::from_secs(4));
+            bar.inc(1);
+            manager.draw(true);
         }
+        assert_eq!(
+            read_memfd(memfd),
+            "Download: 0 of 10, 0 percent\n\
+             Download: 1 of 10, 10 percent\n\
+             Download: 2 of 10, 20 percent\n"
+        );
     }
 
-    /// Reset the created_at time to now. This makes an unforced draw.
-    /// 
-    /// Remember to call this when you want to reuse a bar object.
-    pub fn reset_created_at(&self) {
-        if let Some((manager, state)) = self.get_manager_and_state() {
-            let mut state = state.lock().unwrap();
-            state.created_at = std::time::Instant::now();
-            state.need_redraw = true;
-            let pos = state.pos;
-            let len = state.len;
-            // Drop state before drawing, deadlock otherwise!
-            std::mem::drop(state);
-            manager.mark_redraw();
-            self.check_if_force_draw(manager, pos, len);
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn milestones() {
+        let (manager, memfd) = memfd_manager("milestones");
+        let manager = manager.with_milestones(25);
+        let bar = manager.create_bar(8, "a", "{msg} {pos}", true);
+        for _ in 0..8 {
+            bar.inc(1);
+            manager.draw(true);
         }
+        assert_eq!(read_memfd(memfd), "a 0\na 2\na 4\na 6\na 8\n");
     }
 
-    /// Get the position of the progress bar.
-    ///
-    /// When manager is dropped, this would return 0
-    pub fn get_pos(&self) -> u64 {
-        self.get_manager_and_state()
-            .map_or(0, |(_, state)| state.lock().unwrap().pos)
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn heartbeat() {
+        let clock = clock::MockClock::new();
+        let (manager, memfd) = memfd_manager("heartbeat");
+        manager.set_heartbeat(Some(std::time::Duration::from_secs(60)));
+        let bar = manager.create_bar(10, "a", "{msg} {pos}", true);
+        let _len_0 = manager.create_bar(0, "b", "{msg} {pos}", false);
+        bar.set_pos(4);
+        for _ in 0..3 {
+            clock.advance(std::time::Duration::from_secs(40));
+            manager.draw(true);
+        }
+        bar.set_pos(10);
+        clock.advance(std::time::Duration::from_secs(60));
+        manager.draw(true);
+        assert_eq!(
+            read_memfd(memfd),
+            "a 0\na 4\nstill running, 40% after 0:02:00\na 10\n"
+        );
     }
 
-    /// Get the total length of the progress bar.
-    ///
-    /// When manager is dropped, this would return 0
-    pub fn get_len(&self) -> u64 {
-        self.get_manager_and_state()
-            .map_or(0, |(_, state)| state.lock().unwrap().len)
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn sequence_numbers() {
+        let (manager, memfd) = memfd_manager("sequence_numbers");
+        let manager = manager.with_sequence_numbers(true);
+        let a = manager.create_bar(10, "a", "{msg} {pos}", true);
+        let b = manager.create_bar(10, "b", "{msg} {pos}", true);
+        manager.draw(true);
+        a.inc(1);
+        b.inc(1);
+        manager.draw(true);
+        assert_eq!(read_memfd(memfd), "[1] a 0\n[2] b 0\n[3] a 1\n[3] b 1\n");
     }
 
-    /// Set the progress bar to the end, and force a draw.
-    pub fn finish(&self) {
-        if let Some((manager, state)) = self.get_manager_and_state() {
-            let state = state.lock().unwrap();
-            let pos = state.pos;
-            let len = state.len;
-            if pos != len {
-                self.set_pos(len);
-            }
-            std::mem::drop(state);
+    #[cfg(feature = "crossterm")]
+    #[test]
+    fn crossterm_backend() {
+        let (manager, memfd) = memfd_manager("crossterm_backend");
+        let manager = manager.with_backend(backend::Crossterm::new().alternate_screen(true));
+        let bar = manager.create_bar(10, "a", "{msg} {pos}", true);
+        bar.set_pos(1);
+        manager.draw(true);
+        drop((bar, manager));
+        assert_eq!(
+            read_memfd(memfd),
+            "\x1b[?1049h\x1b[1;1Ha 0\n\x1b[1F\x1b[Ja 1\n\x1b[?1049l"
+        );
+    }
+
+    #[cfg(feature = "ratatui")]
+    #[test]
+    fn bars_widget() {
+        use ratatui::{buffer::Buffer, layout::Rect, widgets::Widget};
+
+        let (manager, _memfd) = memfd_manager("bars_widget");
+        let manager = manager.with_color_support(Some(ColorSupport::TrueColor));
+        let a = manager.create_bar(10, "a", "{msg} {pos}/{len}", true);
+        let _hidden = manager.create_bar(10, "hidden", "{msg}", false);
+        let _b = manager.create_bar(10, "a long message", "{msg}", true);
+        let _c = manager.create_bar(10, "c", "{msg}", true);
+        a.set_pos(3);
+        let mut buffer = Buffer::empty(Rect::new(0, 0, 8, 3));
+        // Bars past the area are not rendered
+        let area = Rect::new(1, 1, 7, 2);
+        tui::BarsWidget::new(&manager).render(area, &mut buffer);
+        assert_eq!(
+            buffer,
+            Buffer::with_lines(vec!["        ", " a 3/10 ", " a long "])
+        );
+    }
+
+    #[test]
+    fn gui_snapshot() {
+        let clock = clock::MockClock::new();
+        let (manager, _memfd) = memfd_manager("gui_snapshot");
+        let changes = manager.subscribe();
+        let dropped = manager.subscribe();
+        drop(dropped);
+        let a = manager.create_bar(8, "a", "{msg} {pos}", true);
+        let _busy = manager.create_bar(0, "busy", "{msg}", true);
+        let hidden = manager.create_bar(100, "hidden", "{msg}", false);
+        hidden.set_pos(100);
+        clock.advance(std::time::Duration::from_secs(65));
+        a.set_pos(2);
+        manager.draw(true);
+        manager.draw(true);
+        // Notifications are merged
+        assert!(changes.try_recv().is_ok());
+        assert!(changes.try_recv().is_err());
+        assert_eq!(manager.inner.subscribers.lock().unwrap().len(), 1);
+
+        let snapshot = manager.gui_snapshot();
+        assert_eq!(snapshot.fraction, Some(0.25));
+        assert_eq!(snapshot.bars.len(), 2);
+        let bar = &snapshot.bars[0];
+        assert_eq!(bar.fraction, Some(0.25));
+        assert_eq!(bar.percent_text, "25%");
+        assert_eq!(bar.progress_text, "2/8");
+        assert_eq!(bar.elapsed_text, "0:01:05");
+        assert_eq!(bar.eta_text.as_deref(), Some("0:03:15"));
+        assert_eq!(bar.text, "a 2");
+        assert_eq!(bar.status, BarStatus::InProgress);
+        let busy = &snapshot.bars[1];
+        assert_eq!((busy.fraction, busy.percent_text.as_str()), (None, ""));
+        assert_ne!(bar.id, busy.id);
+    }
+
+    #[test]
+    fn updater_channel() {
+        use ffi::{Update, UpdateKind};
+
+        let (manager, memfd) = memfd_manager("updater_channel");
+        let a = manager.create_bar(10, "a", "{msg} {pos}/{len}", true);
+        let b = manager.create_lazy_bar(10, "b", "{msg} {pos}/{len}");
+        let updater = manager.updater_channel();
+        let send = |id, kind, value| updater.send(Update { id, kind, value }).unwrap();
+        send(a.id(), UpdateKind::Inc, 2);
+        send(a.id(), UpdateKind::Inc, 3);
+        send(a.id(), UpdateKind::SetLen, 20);
+        send(b.id(), UpdateKind::Finish, 0);
+        send(b.id(), UpdateKind::Fail, 1);
+        // Dropped bars are ignored
+        send(b.id() + 1, UpdateKind::SetPos, 1);
+        // Not applied until the next draw
+        assert_eq!(a.get_pos(), 0);
+        manager.draw(true);
+        assert_eq!((a.get_pos(), a.get_len()), (5, 20));
+        assert_eq!(b.get_pos(), 10);
+        assert!(b.is_visible() && b.is_failed());
+        manager
+            .updater_channel()
+            .send(Update {
+                id: a.id(),
+                kind: UpdateKind::SetVisible,
+                value: 0,
+            })
+            .unwrap();
+        manager.draw(true);
+        assert!(!a.is_visible());
+        assert_eq!(read_memfd(memfd), "a 0/10\na 5/20\nb 10/10\n");
+    }
+
+    #[cfg(feature = "ffi")]
+    #[test]
+    fn ffi_updater() {
+        let manager = Manager::new(std::time::Duration::from_secs(1));
+        let bar = manager.create_bar(10, "a", "{msg}", true);
+        let updater = ffi::updater_into_raw(manager.updater_channel());
+        unsafe {
+            let clone = ffi::kyuri_updater_clone(updater);
+            ffi::kyuri_updater_free(updater);
+            assert!(ffi::kyuri_updater_send(clone, bar.id(), 1, 7));
+            assert!(!ffi::kyuri_updater_send(clone, bar.id(), 42, 7));
+            assert!(!ffi::kyuri_updater_send(std::ptr::null(), bar.id(), 1, 7));
             manager.draw(true);
+            assert_eq!(bar.get_pos(), 7);
+            drop(manager);
+            assert!(!ffi::kyuri_updater_send(clone, bar.id(), 1, 8));
+            ffi::kyuri_updater_free(clone);
         }
     }
 
-    /// Set the progress bar to the end, force a draw, and remove the progress bar from the manager.
-    pub fn finish_and_drop(self) {
-        self.finish();
-        // Automatically drop
+    #[cfg(feature = "ffi")]
+    #[test]
+    fn ffi_snapshot() {
+        let manager = Manager::new(std::time::Duration::from_secs(1));
+        let bar = manager.create_bar(10, "a\0b", "{msg}: {pos}/{len}", true);
+        bar.set_pos(3);
+        let _hidden = manager.create_bar(0, "hidden", "{msg}", false);
+        let handle = ffi::manager_into_raw(&manager);
+        unsafe {
+            let snapshot = ffi::kyuri_manager_snapshot(handle);
+            let bars = std::slice::from_raw_parts((*snapshot).bars, (*snapshot).len);
+            assert_eq!(bars.len(), 2);
+            let c_str = |s| std::ffi::CStr::from_ptr(s).to_str().unwrap();
+            assert_eq!((bars[0].id, bars[0].pos, bars[0].len), (bar.id(), 3, 10));
+            assert_eq!((bars[0].status, bars[0].visible), (1, true));
+            assert_eq!(c_str(bars[0].message), "ab");
+            assert_eq!(c_str(bars[0].text), "ab: 3/10");
+            assert_eq!((bars[1].status, bars[1].visible), (2, false));
+            assert_eq!(c_str(bars[1].message), "hidden");
+            ffi::kyuri_snapshot_free(snapshot);
+
+            drop((bar, _hidden, manager));
+            let snapshot = ffi::kyuri_manager_snapshot(handle);
+            assert_eq!((*snapshot).len, 0);
+            ffi::kyuri_snapshot_free(snapshot);
+            assert!(ffi::kyuri_manager_snapshot(std::ptr::null()).is_null());
+            ffi::kyuri_manager_free(handle);
+        }
     }
 
-    /// Set the visibility of the progress bar. This makes an forced draw when visible actually changes.
-    pub fn set_visible(&self, visible: bool) {
-        if let Some((manager, state)) = self.get_manager_and_state() {
-            let mut state = state.lock().unwrap();
-            if state.visible != visible {
-                state.visible = visible;
-                state.need_redraw = true;
-                // Drop state before drawing, deadlock otherwise!
-                std::mem::drop(state);
-                manager.mark_redraw();
-                manager.draw(true);
+    #[test]
+    fn handles_are_send() {
+        // Handles could be moved to other threads, and wrapped for other languages
+        fn assert_send<T: Send + Sync + 'static>() {}
+        assert_send::<Manager>();
+        assert_send::<Bar>();
+        assert_send::<writer::KyuriWriter>();
+    }
+
+    #[cfg(feature = "python")]
+    #[test]
+    fn python_facade() {
+        use pyo3::{prelude::*, types::PyDict};
+
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let module = pyo3::types::PyModule::new_bound(py, "kyuri").unwrap();
+            python::register(&module).unwrap();
+            let locals = PyDict::new_bound(py);
+            locals.set_item("kyuri", module).unwrap();
+            py.run_bound(
+                r#"
+manager = kyuri.Manager(interval=1.0)
+bar = manager.create_bar(10, "Working", "{msg}: {pos}/{len}", visible=False)
+bar.inc()
+bar.inc(2)
+bar.set_len(20)
+pos, len = bar.pos, bar.len
+try:
+    manager.create_bar(10, "", "{foo}")
+except ValueError as e:
+    error = str(e)
+try:
+    kyuri.Manager(interval=-1.0)
+except ValueError:
+    negative = True
+del manager
+try:
+    bar.inc()
+except RuntimeError as e:
+    gone = str(e)
+"#,
+                None,
+                Some(&locals),
+            )
+            .unwrap();
+            let get = |name: &str| locals.get_item(name).unwrap().unwrap();
+            assert_eq!(get("pos").extract::<u64>().unwrap(), 3);
+            assert_eq!(get("len").extract::<u64>().unwrap(), 20);
+            assert!(get("error")
+                .extract::<String>()
+                .unwrap()
+                .starts_with("invalid template: "));
+            assert!(get("negative").extract::<bool>().unwrap());
+            assert_eq!(
+                get("gone").extract::<String>().unwrap(),
+                "manager is dropped"
+            );
+        });
+    }
+
+    #[test]
+    fn subrange() {
+        let (manager, memfd) = memfd_manager("subrange");
+        let bar = manager.create_bar(1000, "a", "{msg} {pos}", true);
+        let first = bar.subrange(0.0, 0.5);
+        first.set_len(4);
+        first.inc(1);
+        assert_eq!((first.get_pos(), first.get_len()), (1, 4));
+        assert_eq!(bar.get_pos(), 125);
+        // Overflowing the sub-bar does not overflow its range
+        first.inc(10);
+        assert_eq!(bar.get_pos(), 500);
+        let second = bar.subrange(0.5, 1.0);
+        let nested = second.subrange(0.5, 2.0);
+        nested.set_len(10);
+        nested.set_pos(2);
+        assert_eq!(bar.get_pos(), 800);
+        nested.set_message("b");
+        // An empty range is finished at its end
+        let empty = bar.subrange(f64::NAN, 0.9);
+        empty.finish();
+        assert_eq!(bar.get_pos(), 900);
+        second.finish();
+        assert_eq!(bar.get_pos(), 1000);
+        manager.draw(true);
+        assert!(read_memfd(memfd).ends_with("b 1000\n"));
+    }
+
+    #[test]
+    fn progress_trait() {
+        use progress::{NoProgress, Progress};
+
+        fn phases(progress: &dyn Progress) {
+            progress.set_message("phases");
+            for i in 0..2 {
+                let phase = progress.subrange(i as f64 / 2.0, (i + 1) as f64 / 2.0);
+                phase.set_len(10);
+                phase.inc(5);
             }
         }
+
+        let (manager, _memfd) = memfd_manager("progress_trait");
+        let bar = manager.create_bar(100, "", "{msg} {pos}", true);
+        phases(&bar);
+        // The second phase is half done
+        assert_eq!(bar.get_pos(), 75);
+        phases(&bar.subrange(0.0, 0.5));
+        assert_eq!(bar.get_pos(), 38);
+        phases(&NoProgress);
+        let progress: Box<dyn Progress> = Box::new(bar);
+        progress.inc(2);
     }
 
-    /// Get the visibility of the progress bar.
-    ///
-    /// When manager is dropped, this would return false
-    pub fn is_visible(&self) -> bool {
-        self.get_manager_and_state()
-            .map_or(false, |(_, state)| state.lock().unwrap().visible)
+    #[test]
+    fn scope() {
+        let (manager, memfd) = memfd_manager("scope");
+        let len = manager.with_bar(10, "a", "{msg} {pos} {state_emoji}", |bar| {
+            bar.inc(3);
+            bar.get_len()
+        });
+        assert_eq!(len, 10);
+        let bar = manager.create_bar(10, "b", "{msg} {pos} {state_emoji}", true);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            bar.scope(|bar| {
+                bar.inc(4);
+                panic!("oops");
+            })
+        }));
+        assert!(result.is_err());
+        assert!(bar.is_failed());
+        assert_eq!(bar.get_pos(), 4);
+        let bar = manager.create_bar(10, "d", "{msg}", true);
+        let disk = String::from("disk");
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            bar.scope(|_| panic!("{} is gone", disk))
+        }));
+        assert_eq!(
+            result.unwrap_err().downcast_ref::<String>().unwrap(),
+            "disk is gone"
+        );
+        // Failed on an error
+        let bar = manager.create_bar(10, "c", "{msg} {pos}", true);
+        let result: Result<(), &str> = bar.scope(|bar| {
+            bar.fail(true);
+            Err("oops")
+        });
+        assert!(result.is_err());
+        assert_eq!(bar.get_pos(), 0);
+        let theme = Theme::default();
+        let output = read_memfd(memfd);
+        assert!(output.starts_with(&format!(
+            "a 0 {}\na 10 {}\n",
+            theme.icon_new, theme.icon_finished
+        )));
+        assert!(output.contains(&format!("b (panicked: oops) 4 {}\n", theme.icon_failed)));
+        assert!(output.contains("d (panicked: disk is gone)\n"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn retrying() {
+        let clock = clock::MockClock::new();
+        let (manager, memfd) = memfd_manager("retrying");
+        let bar = manager.create_bar(10, "Download", "{msg} {pos}/{len}", true);
+        bar.retrying(2, 5, std::time::Duration::from_millis(2500));
+        manager.draw(true);
+        clock.advance(std::time::Duration::from_secs(1));
+        // The countdown changed, so the ticker would redraw in a terminal
+        manager.inner.poll_sources();
+        assert!(manager.inner.need_redraw.take());
+        clock.advance(std::time::Duration::from_secs(2));
+        bar.retrying(3, 5, std::time::Duration::ZERO);
+        manager.draw(true);
+        bar.resume_progress();
+        bar.inc(3);
+        manager.draw(true);
+        assert_eq!(
+            read_memfd(memfd),
+            "Download 0/10\n\
+             Download: attempt 2/5 failed, retrying in 3s\n\
+             Download: attempt 3/5 failed, retrying now\n\
+             Download 3/10\n"
+        );
+    }
+
+    #[test]
+    fn json_backend() {
+        let clock = clock::MockClock::new();
+        let (manager, memfd) = memfd_manager("json_backend");
+        let manager = manager.with_backend(backend::Json).force_ansi(true);
+        let bar = manager.create_bar(10, "a \"b\"", "{msg} {pos}", true);
+        clock.advance(std::time::Duration::from_millis(1500));
+        bar.set_pos(3);
+        let _hidden = manager.create_bar(10, "hidden", "{msg} {pos}", false);
+        manager.draw(true);
+        assert_eq!(
+            read_memfd(memfd),
+            concat!(
+                r#"{"bars": [{"message": "a \"b\"", "pos": 0, "len": 10, "elapsed": 0, "status": "new"}]}"#,
+                "\n",
+                r#"{"bars": [{"message": "a \"b\"", "pos": 3, "len": 10, "elapsed": 1.5, "status": "in_progress"}]}"#,
+                "\n",
+            )
+        );
+    }
+
+    #[test]
+    fn reopen_output() {
+        let dir = std::env::temp_dir().join(format!("kyuri-reopen-{}", std::process::id()));
+        let path = dir.join("logs").join("progress.log");
+        let manager = Manager::new(std::time::Duration::from_secs(1))
+            .with_path(&path, OpenMode::Append, true)
+            .unwrap();
+        let bar = manager.create_bar(10, "a", "{msg} {pos}", true);
+        // Rotated
+        std::fs::rename(&path, dir.join("logs").join("progress.log.1")).unwrap();
+        bar.set_pos(5);
+        manager.reopen_output().unwrap();
+        bar.set_pos(10);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("logs").join("progress.log.1")).unwrap(),
+            "a 0\na 5\n"
+        );
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "a 5\na 10\n");
+        drop((bar, manager));
+        // Truncated, and appended when reopened
+        let manager = Manager::new(std::time::Duration::from_secs(1))
+            .with_path(&path, OpenMode::Truncate, false)
+            .unwrap();
+        let bar = manager.create_bar(10, "b", "{msg} {pos}", true);
+        manager.reopen_output().unwrap();
+        drop(bar);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "b 0\nb 0\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+        let manager = Manager::new(std::time::Duration::from_secs(1)).with_stderr();
+        assert_eq!(
+            manager.reopen_output().unwrap_err().kind(),
+            std::io::ErrorKind::InvalidInput
+        );
     }
 
-    /// Set the message of the progress bar. This makes an unforced draw.
-    pub fn set_message(&self, message: &str) {
-        if let Some((manager, state)) = self.get_manager_and_state() {
-            let mut state = state.lock().unwrap();
-            state.message = message.to_string();
-            state.need_redraw = true;
-            // Drop state before drawing, deadlock otherwise!
-            std::mem::drop(state);
-            manager.mark_redraw();
-            manager.draw(false);
-        }
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn recording() {
+        let clock = clock::MockClock::new();
+        let path =
+            std::env::temp_dir().join(format!("kyuri-recording-{}.cast", std::process::id()));
+        let (manager, _memfd) = memfd_manager("recording");
+        let manager = manager.force_ansi(true).with_recording(&path).unwrap();
+        let bar = manager.create_bar(10, "a", "{msg} \"{pos}\"", true);
+        clock.advance(std::time::Duration::from_millis(1500));
+        bar.set_pos(10);
+        let cast = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<_> = cast.lines().collect();
+        assert!(lines[0].starts_with(r#"{"version": 2, "width": 80, "height": 24, "timestamp": "#));
+        assert_eq!(
+            &lines[1..],
+            [
+                r#"[0.000000, "o", "a \"0\"\r\n"]"#,
+                r#"[1.500000, "o", "\u001b[F\r\u001b[K"]"#,
+                r#"[1.500000, "o", "a \"10\"\r\n"]"#,
+            ]
+        );
     }
 
-    /// Set the template of the progress bar. This makes an unforced draw.
-    pub fn set_template(&self, template: &str) {
-        if let Some((manager, state)) = self.get_manager_and_state() {
-            let mut state = state.lock().unwrap();
-            state.template = Template::new(template);
-            state.need_redraw = true;
-            // Drop state before drawing, deadlock otherwise!
-            std::mem::drop(state);
-            manager.mark_redraw();
-            manager.draw(false);
+    #[test]
+    fn replay() {
+        let cast = r#"{"version": 2, "width": 80, "height": 24}
+[0.0, "o", "a \"0\"\r\n"]
+[0.1, "i", "q"]
+[0.2, "o", "\u001b[F\r\u001b[K\ud83d\ude00 \u00e9/\/"]
+"#;
+        let mut output = Vec::new();
+        replay::play(cast.as_bytes(), &mut output, f64::INFINITY).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "a \"0\"\r\n\x1b[F\r\x1b[K😀 é//"
+        );
+        for cast in [
+            "",
+            "{}\n[0.0, \"o\"]\n",
+            "{}\n[0.0, \"o\", \"\\ud83d\"]\n",
+            "{}\n[0.0, \"o\", \"a\"] x\n",
+        ] {
+            let error = replay::play(cast.as_bytes(), std::io::sink(), 1.0).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData, "{}", cast);
         }
+        let error = replay::play("{}".as_bytes(), std::io::sink(), 0.0).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
     }
 
-    /// Return whether the progress bar (the manager) is still alive.
-    ///
-    /// When the manager is dropped, the progress bar would not be able to be interacted with.
-    pub fn alive(&self) -> bool {
-        self.get_manager_and_state().is_some()
+    #[test]
+    fn virtual_terminal() {
+        let mut vt = VirtualTerminal::new(4);
+        vt.write("abcdef\nghijk\n\x1b[31mx\x1b[0m\x07y\n");
+        assert_eq!(vt.lines(), ["abcd", "ef", "ghij", "k", "xy"]);
+        assert_eq!(vt.row(), 5);
+        vt.write("\x1b[2Azz\x1b[1K\x1b[3G!\x1b[E\x1b[J");
+        assert_eq!(vt.lines(), ["abcd", "ef", "ghij", "  !"]);
     }
-}
 
-impl Drop for Bar {
-    /// Drop the progress bar. This removes the progress bar from the manager and forces a draw.
-    fn drop(&mut self) {
-        if let Some((manager, _)) = self.get_manager_and_state() {
-            manager.states.lock().unwrap().remove(&self.id);
-            manager.mark_redraw();
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn layouts_redrawn() {
+        let (manager, memfd) = memfd_manager("layouts_redrawn");
+        let manager = manager.force_ansi(true);
+        manager.set_panel(Some(panel::Panel::ascii("Jobs")));
+        manager.set_split(true);
+        manager.set_status_line(Some("{active_bars} running"));
+        let a = manager.create_bar(10, "download", "{msg} {pos}", true);
+        let b = manager.create_bar(10, "upload", "{msg} {pos}", true);
+        b.set_column(layout::Column::Right);
+        let long = "x".repeat(100);
+        for message in [long.as_str(), "c", "a much longer message", "c"] {
+            let c = manager.create_bar(10, message, "{msg} {pos}", true);
+            a.inc(1);
+            b.inc(2);
             manager.draw(true);
+            drop(c);
         }
+        manager.log_line("log");
+        manager.draw(true);
+        let mut vt = VirtualTerminal::new(80);
+        vt.write("$ prompt\n");
+        vt.write(&read_memfd(memfd));
+        let rule = |title: &str| format!("+{:-<78}+", title);
+        assert_eq!(
+            vt.lines(),
+            [
+                "$ prompt".to_string(),
+                "log".to_string(),
+                rule("- Jobs "),
+                "download 4 | upload 8".to_string(),
+                rule(""),
+                "2 running".to_string(),
+            ]
+        );
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::io::{Read, Seek};
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn reentrancy() {
+        use std::io::Write;
+        let (manager, memfd) = memfd_manager("reentrancy");
+        let manager = manager.force_ansi(true);
+        let bar = manager.create_bar(10, "a", "{msg} {pos}", true);
+        let mut writer = manager.create_writer();
+        manager.suspend(|out| {
+            writeln!(out, "suspended").unwrap();
+            // Draws are deferred instead of deadlocking
+            bar.set_pos(3);
+            manager
+                .create_bar(10, "b", "{msg} {pos}", true)
+                .finish_and_drop();
+            // Lines are kept until the next write outside
+            writeln!(writer, "log").unwrap();
+            // Nested suspends (like by a logger) run directly, and their output is written after the closure
+            let nested = manager.suspend(|out| {
+                writeln!(out, "nested").unwrap();
+                1
+            });
+            assert_eq!(nested, 1);
+            writeln!(out, "suspended again").unwrap();
+        });
+        let output = read_memfd(memfd.try_clone().unwrap());
+        assert!(output.contains("suspended\nsuspended again\nnested\n"));
+        assert!(!output.contains("log"));
+        writer.flush().unwrap();
+        assert!(read_memfd(memfd.try_clone().unwrap()).contains("log\n"));
 
-    use super::*;
+        // Nested suspends inside a draw are written above the bars drawn again
+        let bar_logging = {
+            let inner = Arc::downgrade(&manager.inner);
+            let logged = AtomicBool::new(false);
+            manager.create_bar_with_source(10, "e", "{msg} {pos}", move || {
+                if !logged.swap(true, std::sync::atomic::Ordering::Relaxed) {
+                    let inner = inner.upgrade().unwrap();
+                    inner.suspend(|out| writeln!(out, "from source").unwrap());
+                }
+                1
+            })
+        };
+        manager.draw(true);
+        let output = read_memfd(memfd.try_clone().unwrap());
+        let (_, after) = output.rsplit_once("from source\n").unwrap();
+        assert!(after.contains("a 3\n") && after.ends_with("e 1\n"));
+        std::mem::drop(bar_logging);
+
+        // A source updating another bar
+        let bar_src = {
+            let bar = manager.create_bar(10, "c", "{msg} {pos}", true);
+            manager.create_bar_with_source(10, "d", "{msg} {pos}", move || {
+                bar.inc(1);
+                1
+            })
+        };
+        manager.draw(true);
+        std::mem::drop((bar, bar_src, writer));
+        std::mem::drop(manager);
+        // The source is polled at each draw
+        assert!(read_memfd(memfd).contains("a 3\nc 2\nd 1\n"));
+    }
 
     #[test]
-    fn basic_test() {
+    fn poisoned() {
         let manager = Manager::new(std::time::Duration::from_secs(1));
-        let bar_1 = manager.create_bar(
-            100,
-            "Downloading",
-            "{msg}\n[{elapsed}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
-            true,
+        let fail = Arc::new(AtomicBool::new(false));
+        let bar = {
+            let fail = fail.clone();
+            manager.create_bar_with_source(10, "a", "{msg} {pos}", move || {
+                if fail.load(std::sync::atomic::Ordering::Relaxed) {
+                    panic!("source failed");
+                }
+                5
+            })
+        };
+        // The source panics with the bar state locked
+        fail.store(true, std::sync::atomic::Ordering::Relaxed);
+        let result = std::thread::scope(|s| s.spawn(|| manager.draw(true)).join());
+        fail.store(false, std::sync::atomic::Ordering::Relaxed);
+        assert!(result.is_err());
+        manager.draw(true);
+        assert_eq!(bar.get_pos(), 5);
+        bar.set_message("b");
+        assert_eq!(bar.snapshot().unwrap().message, "b");
+    }
+
+    #[test]
+    fn template_limits() {
+        assert_eq!(
+            Template::parse("{bar99999999}").unwrap_err(),
+            TemplateError::BarTooWide("bar99999999".to_string())
         );
-        let bar_2 = manager.create_bar(
-            100,
-            "Uploading",
-            "{msg}\n[{elapsed}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
-            true,
+        assert_eq!(
+            Template::parse("{bar99999999999999999999999}").unwrap_err(),
+            TemplateError::BarTooWide("bar99999999999999999999999".to_string())
+        );
+        let state = test_state(10, 5, "", "{bar99999999999999999999999}");
+        assert_eq!(
+            state.render(&test_options()).chars().count(),
+            MAX_BAR_WIDTH + 2
         );
 
-        bar_1.set_pos(50);
-        bar_2.set_pos(25);
+        let long = "{{".repeat(MAX_TEMPLATE_LEN);
+        assert_eq!(
+            Template::parse(&long).unwrap_err(),
+            TemplateError::TooLong(MAX_TEMPLATE_LEN * 2)
+        );
+        // Truncated at a char boundary
+        let long = "€".repeat(MAX_TEMPLATE_LEN);
+        let state = test_state(10, 5, "", &long);
+        assert_eq!(
+            state.render(&test_options()).len(),
+            MAX_TEMPLATE_LEN / 3 * 3
+        );
+    }
 
-        std::mem::drop(bar_1);
-        std::mem::drop(bar_2);
+    proptest::proptest! {
+        #[test]
+        fn count_lines_matches_screen(text in "[a-z 漢é\n]{0,300}", width in 2usize..120) {
+            let text = format!("{}\n", text);
+            let mut screen = VirtualTerminal::new(width);
+            screen.write(&text);
+            proptest::prop_assert_eq!(count_lines(&text, width), screen.row());
+        }
+
+        #[cfg(target_os = "linux")]
+        #[test]
+        fn no_ghost_lines(
+            first in "[a-z 漢é\n]{0,300}",
+            second in "[a-z 漢é\n]{0,300}",
+            template in proptest::sample::select(vec!["{msg}", "{msg} {bar}\n{pos}/{len}", "[{msg}]\n\n{bar100}"]),
+        ) {
+            let draw = |messages: &[&str]| {
+                let (manager, memfd) = memfd_manager("no_ghost_lines");
+                let manager = manager.force_ansi(true);
+                let bar = manager.create_bar(10, messages[0], template, true);
+                for message in &messages[1..] {
+                    bar.set_message(message);
+                    manager.draw(true);
+                }
+                std::mem::drop(bar);
+                // 80 columns, as the output is not a terminal
+                let mut screen = VirtualTerminal::new(80);
+                screen.write("$ prompt\n");
+                screen.write(&read_memfd(memfd));
+                screen
+            };
+            // Drawing the second message over the first one looks the same as drawing it directly
+            let redrawn = draw(&[&first, &second]);
+            let direct = draw(&[&second]);
+            proptest::prop_assert_eq!(redrawn.lines(), direct.lines());
+            proptest::prop_assert_eq!(redrawn.row(), direct.row());
+        }
     }
 
     #[test]
-    fn dont_crash_when_zero() {
+    fn render_frames() {
+        use crate::test::{render_frames, Step};
+        let frames = render_frames(
+            |manager| {
+                manager.log_line("started");
+                manager.create_bar(1 << 20, "a", "{msg} {bytes_per_sec} {eta}", true)
+            },
+            [
+                Step::new(|bar: &mut Bar| bar.set_pos(1 << 18))
+                    .after(std::time::Duration::from_secs(1)),
+                Step::wait(std::time::Duration::from_secs(1)),
+            ],
+        );
+        assert_eq!(
+            frames,
+            [
+                "started\na 0 B/s Unknown\n",
+                "started\na 256.00 KiB/s 0:00:03\n",
+                "started\na 128.00 KiB/s 0:00:06\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn fields() {
         let manager = Manager::new(std::time::Duration::from_secs(1));
         let bar = manager.create_bar(
-            0,
+            10,
             "Downloading",
-            "{msg}\n[{elapsed}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
-            true,
+            "{msg} from {field:host} ({field:file})",
+            false,
+        );
+        bar.set_field("host", "mirror1");
+        let render = |bar: &Bar| {
+            let (_, state) = bar.get_manager_and_state().unwrap();
+            let state = state.lock().unwrap();
+            state.render(&test_options())
+        };
+        assert_eq!(render(&bar), "Downloading from mirror1 ()");
+        bar.set_field("host", "mirror2");
+        bar.set_field("file", "a.iso");
+        assert_eq!(render(&bar), "Downloading from mirror2 (a.iso)");
+        assert_eq!(
+            Template::parse("{field:}").unwrap_err(),
+            TemplateError::UnknownTag("field:".to_string())
         );
+    }
 
-        bar.set_pos(0);
+    #[test]
+    fn detail() {
+        let mut state = test_state(10, 3, "a", "{msg} {pos}/{len}");
+        state.detail = Some(Template::new("  {field:conn} connections\n  {field:chunk}"));
+        state.fields.insert("conn".to_string(), "4".to_string());
+        state
+            .fields
+            .insert("chunk".to_string(), "chunk 3".to_string());
+        let mut options = test_options();
+        assert_eq!(state.render(&options), "a 3/10");
+        options.verbosity = Verbosity::Detailed;
+        assert_eq!(state.render(&options), "a 3/10\n  4 connections\n  chunk 3");
+        state.collapsed = true;
+        assert_eq!(state.render(&options), "a (3/10)");
+
+        let manager = Manager::new(std::time::Duration::from_secs(1));
+        manager.set_verbosity(Verbosity::Detailed);
+        assert_eq!(manager.verbosity(), Verbosity::Detailed);
+    }
+
+    #[test]
+    fn segmented_bar() {
+        let manager = Manager::new(std::time::Duration::from_secs(1));
+        let bar =
+            manager.create_segmented_bar(&[100, 100, 100, 100], "a", "{msg} {bar8} {pos}/{len}");
+        assert_eq!(bar.segment_count(), 4);
+        std::thread::scope(|s| {
+            for i in 0..4 {
+                let segment = bar.segment(i);
+                s.spawn(move || {
+                    for _ in 0..(i as u64 + 1) * 20 {
+                        segment.inc(1);
+                    }
+                });
+            }
+        });
+        assert_eq!(bar.segment(3).get_pos(), 80);
         manager.draw(true);
+        let render = || {
+            let (_, state) = bar.get_manager_and_state().unwrap();
+            let state = state.lock().unwrap();
+            state.render(&test_options())
+        };
+        // 20%, 40%, 60% and 80% of segments of 2 cells, filled when half of a cell is downloaded
+        assert_eq!(render(), "a [  = = ==] 200/400");
+        bar.segment(0).set_pos(100);
+        manager.draw(true);
+        assert_eq!(render(), "a [=== = ==] 280/400");
     }
 
     #[test]
-    fn inc() {
+    fn overall_bar() {
         let manager = Manager::new(std::time::Duration::from_secs(1));
-        let bar = manager.create_bar(
-            100,
-            "Downloading",
-            "{msg}\n[{elapsed}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
-            true,
+        let small = manager.create_bar(10, "small", "", true);
+        let large = manager.create_bar(990, "large", "", true);
+        let files = manager.create_bar(4, "files", "", true);
+        let overall = manager.create_overall_bar("{msg} {pos}/{len}");
+        overall.set_message("Total");
+        overall.add(&small);
+        overall.add(&large);
+        let render = || {
+            manager.draw(true);
+            let (_, state) = overall.get_manager_and_state().unwrap();
+            let state = state.lock().unwrap();
+            state.render(&test_options())
+        };
+        assert_eq!(render(), "Total 0/1000");
+        // A finished small task barely moves the whole job
+        small.set_pos(10);
+        assert_eq!(render(), "Total 10/1000");
+        large.set_pos(495);
+        assert_eq!(render(), "Total 505/1000");
+        // Half of the files weighs as much as the rest
+        overall.add_weighted(&files, 1000);
+        files.set_pos(2);
+        assert_eq!(render(), "Total 1005/2000");
+        // Dropped members keep their last progress
+        drop(large);
+        assert_eq!(render(), "Total 1005/2000");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn bell() {
+        let (manager, memfd) = memfd_manager("bell");
+        let manager = manager.force_ansi(true);
+        let bar = manager.create_bar(2, "a", "{msg}{bell}", true);
+        let bells = || {
+            read_memfd(memfd.try_clone().unwrap())
+                .matches('\x07')
+                .count()
+        };
+        bar.set_pos(2);
+        assert_eq!(bells(), 1);
+        bar.set_message("b");
+        manager.draw(true);
+        assert_eq!(bells(), 1);
+        // Rings again when finished again, after drawn unfinished
+        bar.set_pos(1);
+        manager.draw(true);
+        bar.set_pos(2);
+        assert_eq!(bells(), 2);
+
+        let state = test_state(2, 2, "a", "{msg}{bell}");
+        let mut options = test_options();
+        options.is_terminal = false;
+        assert_eq!(state.render(&options), "a");
+    }
+
+    #[test]
+    fn spinner() {
+        use crate::test::{render_frames, Step};
+        use std::time::Duration;
+        let frames = render_frames(
+            |manager| manager.create_bar(2, "a", "{spinner} {msg}", true),
+            [
+                Step::wait(Duration::from_millis(100)),
+                Step::new(|bar: &mut Bar| {
+                    bar.set_spinner_frames(&["<o>", "<->"], Duration::from_millis(300))
+                }),
+                Step::wait(Duration::from_millis(300)),
+                Step::wait(Duration::from_millis(300)),
+                Step::new(|bar: &mut Bar| bar.set_pos(2)),
+                // Stopped when finished
+                Step::wait(Duration::from_millis(300)),
+            ],
         );
+        let theme = Theme::default();
+        let (first, second) = (&theme.spinner_frames[0], &theme.spinner_frames[1]);
+        assert_eq!(
+            frames,
+            [
+                format!("{} a\n", first),
+                format!("{} a\n", second),
+                "<o> a\n".to_string(),
+                "<-> a\n".to_string(),
+                "<o> a\n".to_string(),
+                "<o> a\n".to_string(),
+                "<o> a\n".to_string(),
+            ]
+        );
+    }
 
-        bar.inc(10);
-        bar.inc(10);
-        bar.inc(10);
-        bar.inc(10);
-        bar.inc(10);
+    #[test]
+    fn persist() {
+        let clock = clock::MockClock::new();
+        let manager = Manager::new(std::time::Duration::from_secs(1));
+        let bar = manager.create_bar(100, "Updating", "{msg}", true);
+        bar.set_field("phase", "download");
+        let hidden = manager.create_bar(10, "Hidden", "{msg}", false);
+        hidden.set_pos(10);
+        clock.advance(std::time::Duration::from_secs(30));
+        bar.set_pos(42);
+        let bytes = manager.serialize_state().unwrap();
+        assert_eq!(
+            persist::SavedState::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(persist::DecodeError::Truncated)
+        );
+        assert_eq!(
+            persist::SavedState::from_bytes(b"KYRI\xff"),
+            Err(persist::DecodeError::UnknownFormat)
+        );
 
-        assert_eq!(bar.get_pos(), 50);
+        clock.advance(std::time::Duration::from_secs(5));
+        let restored = Manager::new(std::time::Duration::from_secs(1));
+        let bars = restored.deserialize_state(&bytes).unwrap();
+        assert_eq!(
+            restored.saved_state(),
+            persist::SavedState::from_bytes(&bytes).unwrap()
+        );
+        assert_eq!(bars[0].get_pos(), 42);
+        assert_eq!(bars[0].elapsed(), std::time::Duration::from_secs(30));
+        // The template is kept
+        assert_eq!(
+            restored.saved_state().bars[0].template.as_deref(),
+            Some("{msg}")
+        );
+        // Bars saved by version 1 have no template
+        let mut v1 = bytes.clone();
+        v1[4] = 1;
+        let offset = 4 + 1 + 4 + 8 * 3;
+        v1[offset] &= !4;
+        let template_at = offset + 1 + 4 + "Updating".len();
+        v1.drain(template_at..template_at + 4 + "{msg}".len());
+        let saved = persist::SavedState::from_bytes(&v1).unwrap();
+        assert_eq!(saved.bars[0].template, None);
+        assert_eq!(saved.bars[0].fields["phase"], "download");
+        // The finished bar keeps its elapsed time
+        clock.advance(std::time::Duration::from_secs(5));
+        assert_eq!(bars[1].elapsed(), std::time::Duration::ZERO);
+        assert!(!bars[1].snapshot().unwrap().visible);
+    }
 
-        std::mem::drop(bar);
+    #[test]
+    fn aggregate() {
+        use crate::test::{render_frames, Step};
+        let frames = render_frames(
+            |manager| {
+                [
+                    manager.create_bar(1 << 20, "a", "{msg} {bytes}", true),
+                    manager.create_bar(1 << 20, "b", "{msg} {bytes}", true),
+                    manager.create_bar(
+                        0,
+                        "TOTAL",
+                        "{msg} {all_bytes}/{all_total_bytes} {all_bytes_per_sec} ({active_bars} active)",
+                        true,
+                    ),
+                ]
+            },
+            [Step::new(|bars: &mut [Bar; 3]| {
+                bars[0].set_pos(1 << 19);
+                bars[1].set_pos(1 << 20);
+            })
+            .after(std::time::Duration::from_secs(1))],
+        );
+        assert_eq!(
+            frames[1],
+            "a 512.00 KiB\nb 1.00 MiB\nTOTAL 1.50 MiB/2.00 MiB 512.00 KiB/s (1 active)\n"
+        );
     }
 
     #[test]
-    fn visible() {
+    fn snapshot() {
         let manager = Manager::new(std::time::Duration::from_secs(1));
-        let bar = manager.create_bar(
-            100,
-            "Downloading",
-            "{msg}\n[{elapsed}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
-            true,
+        let bar = manager.create_bar(10, "Working", "{msg}", false);
+        bar.set_pos(3);
+        let snapshot = bar.snapshot().unwrap();
+        assert_eq!(snapshot.pos, 3);
+        assert_eq!(snapshot.len, 10);
+        assert_eq!(snapshot.message, "Working");
+        assert!(!snapshot.visible);
+        assert_eq!(snapshot.status, BarStatus::InProgress);
+        bar.fail(true);
+        assert_eq!(bar.snapshot().unwrap().status, BarStatus::Failed);
+        std::mem::drop(manager);
+        assert!(bar.snapshot().is_none());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn mode_query() {
+        let (manager, _memfd) = memfd_manager("mode_query");
+        assert_eq!(manager.target_kind(), TargetKind::File);
+        assert!(!manager.is_ansi_active());
+        assert_eq!(manager.width(), 80);
+        let manager = manager.force_ansi(true).with_stderr();
+        assert_eq!(manager.target_kind(), TargetKind::Stderr);
+        assert!(manager.is_ansi_active());
+        assert_eq!(string_width("\x1b[31mabc\x1b[0m"), 3);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn align_group() {
+        let (manager, memfd) = memfd_manager("align_group");
+        let manager = manager.force_ansi(true);
+        let bar_1 = manager.create_bar(100, "Download", "{msg} {pos}/{len}|", true);
+        let bar_2 = manager.create_bar(5, "Unpack", "{msg} {pos}/{len}|", true);
+        let bar_3 = manager.create_bar(5, "Other", "{msg} {pos}/{len}|", true);
+        bar_1.set_pos(42);
+        bar_1.set_align_group(Some(0));
+        bar_2.set_align_group(Some(0));
+        manager.draw(true);
+        std::mem::drop(manager);
+        let output = read_memfd(memfd);
+        let last_frame = output.rsplit(CLEAR_ANSI).next().unwrap();
+        assert_eq!(
+            last_frame,
+            "Download 42/100|\nUnpack    0/  5|\nOther 0/5|\n"
         );
+        std::mem::drop((bar_1, bar_2, bar_3));
+    }
 
-        assert!(bar.is_visible());
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn log_line_to_file() {
+        let (manager, memfd) = memfd_manager("log_line_to_file");
+        let bar = manager.create_bar(10, "Working", "{msg}", true);
+        manager.log_line("a");
+        std::mem::drop(bar);
+        std::mem::drop(manager);
+        assert_eq!(read_memfd(memfd), "Working\na\n");
+    }
 
-        bar.set_visible(false);
-        assert!(!bar.is_visible());
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn writer_prefix() {
+        use std::io::Write;
+
+        let (manager, memfd) = memfd_manager("writer_prefix");
+        let mut writer = manager.writer_builder().level("INFO").build();
+        writer.write_all(b"hel").unwrap();
+        writer.write_all(b"lo\nworld\n").unwrap();
+        std::mem::drop(manager);
+        std::mem::drop(writer);
+        assert_eq!(read_memfd(memfd), "[INFO] hello\n[INFO] world\n");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn writer_line_buffering() {
+        use std::io::Write;
 
+        let (manager, memfd) = memfd_manager("writer_line_buffering");
+        let manager = manager.force_ansi(true);
+        let bar = manager.create_bar(10, "Working", "{msg}", true);
+        let mut writer = manager.create_writer();
+        write!(writer, "a").unwrap();
+        write!(writer, "b").unwrap();
+        let before = read_memfd(memfd.try_clone().unwrap());
+        write!(writer, "c\nd").unwrap();
+        writer.flush().unwrap();
+        std::mem::drop(manager);
         std::mem::drop(bar);
+        let output = read_memfd(memfd);
+        // Nothing is written before a newline
+        assert_eq!(before, "Working\n");
+        assert_eq!(
+            output,
+            "Working\n\x1b[F\r\x1b[Kabc\nWorking\n\x1b[F\r\x1b[KdWorking\n"
+        );
     }
 
+    #[cfg(target_os = "linux")]
     #[test]
-    fn ticker() {
+    fn writer_clone() {
+        use std::io::Write;
+
+        let (manager, memfd) = memfd_manager("writer_clone");
+        let writer = manager.create_writer();
+        std::thread::scope(|s| {
+            for i in 0..4 {
+                let mut writer = writer.clone();
+                s.spawn(move || {
+                    for _ in 0..100 {
+                        write!(writer, "thread ").unwrap();
+                        writeln!(writer, "{}", i).unwrap();
+                    }
+                });
+            }
+        });
+        std::mem::drop(manager);
+        let output = read_memfd(memfd);
+        assert_eq!(output.lines().count(), 400);
+        assert!(output.lines().all(|line| line.starts_with("thread ")));
+    }
+
+    #[test]
+    fn lsp_progress() {
+        #[derive(Clone, Default)]
+        struct Buffer(Arc<Mutex<Vec<u8>>>);
+        impl std::io::Write for Buffer {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+        // Take the values of notifications written so far, or the token of create requests
+        let take = |buffer: &Buffer| {
+            let written =
+                String::from_utf8(std::mem::take(&mut *buffer.0.lock().unwrap())).unwrap();
+            let mut contents = vec![];
+            for message in written.split("Content-Length: ").skip(1) {
+                let (len, content) = message.split_once("\r\n\r\n").unwrap();
+                assert_eq!(len.parse::<usize>().unwrap(), content.len());
+                if let Some(token) = content
+                    .strip_prefix(r#"{"jsonrpc":"2.0","id":"#)
+                    .and_then(|rest| rest.split_once(","))
+                {
+                    assert_eq!(
+                        token.1,
+                        format!(
+                            r#""method":"window/workDoneProgress/create","params":{{"token":{}}}}}"#,
+                            token.0
+                        )
+                    );
+                    contents.push(format!("create {}", token.0));
+                    continue;
+                }
+                let value = content.split_once(r#""value":"#).unwrap().1;
+                contents.push(value.strip_suffix("}}").unwrap().to_string());
+            }
+            contents
+        };
+
         let manager = Manager::new(std::time::Duration::from_secs(1));
-        manager.set_ticker(true);
-        let bar = manager.create_bar(
-            100,
-            "Downloading",
-            "{msg}\n[{elapsed}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
-            true,
+        let buffer = Buffer::default();
+        manager.set_lsp_progress(Some(buffer.clone()));
+        let bar = manager.create_bar(4, "Indexing \"src\"", "{msg}", true);
+        manager.draw(true);
+        assert_eq!(
+            take(&buffer),
+            [
+                r#"create "kyuri-0""#,
+                r#"{"kind":"begin","title":"Indexing \"src\"","percentage":0}"#
+            ]
+        );
+        bar.set_pos(1);
+        manager.draw(true);
+        // Unchanged bars are not reported again
+        manager.mark_redraw();
+        manager.draw(true);
+        assert_eq!(
+            take(&buffer),
+            [r#"{"kind":"report","message":"Indexing \"src\"","percentage":25}"#]
+        );
+        let spinner = manager.create_bar(0, "Waiting", "{msg}", true);
+        bar.set_pos(4);
+        manager.draw(true);
+        assert_eq!(
+            take(&buffer),
+            [
+                r#"create "kyuri-1""#,
+                r#"{"kind":"begin","title":"Waiting"}"#,
+                r#"{"kind":"end"}"#
+            ]
         );
+        // Ended when disabled
+        manager.set_lsp_progress(None::<Buffer>);
+        assert_eq!(take(&buffer), [r#"{"kind":"end"}"#]);
+        drop(spinner);
+    }
 
-        std::thread::sleep(std::time::Duration::from_secs(2));
-        std::mem::drop(bar);
+    #[cfg(all(target_os = "linux", feature = "process_title"))]
+    #[test]
+    fn process_title() {
+        let read_comm = || std::fs::read_to_string("/proc/self/comm").unwrap();
+        let original = read_comm();
+        let manager = Manager::new(std::time::Duration::from_secs(1));
+        manager.set_process_title(true);
+        let bar = manager.create_bar(10, "Working", "{msg}", true);
+        bar.set_pos(4);
+        manager.draw(true);
+        assert!(read_comm().trim_end().ends_with(" [40%]"));
+        assert!(read_comm().trim_end().len() <= 15);
+        manager.set_process_title(false);
+        assert_eq!(read_comm(), original);
     }
 
+    #[cfg(all(unix, feature = "control_socket"))]
     #[test]
-    fn alive() {
+    fn control_socket() {
+        use std::io::{BufRead, Write};
+
+        let path = std::env::temp_dir().join(format!("kyuri-control-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
         let manager = Manager::new(std::time::Duration::from_secs(1));
-        let bar = manager.create_bar(
-            100,
-            "Downloading",
-            "{msg}\n[{elapsed}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
-            true,
+        let bar = manager.create_bar(10, "Working", "{msg}: {pos}/{len}", true);
+        bar.set_pos(3);
+        let socket = manager.serve_control_socket(&path).unwrap();
+
+        // An idle client doesn't block others
+        let _idle = std::os::unix::net::UnixStream::connect(&path).unwrap();
+        let mut stream = std::os::unix::net::UnixStream::connect(&path).unwrap();
+        let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+        let mut read_response = || {
+            let mut lines = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end().to_string();
+                if line == "ok" || line.starts_with("error") {
+                    lines.push(line);
+                    return lines;
+                }
+                lines.push(line);
+            }
+        };
+        writeln!(stream, "list").unwrap();
+        assert_eq!(read_response(), ["0 true 3 10 Working", "ok"]);
+        writeln!(stream, "snapshot").unwrap();
+        assert_eq!(read_response(), ["Working: 3/10", "ok"]);
+        writeln!(stream, "hide 0").unwrap();
+        assert_eq!(read_response(), ["ok"]);
+        assert!(!bar.is_visible());
+        writeln!(stream, "interval 10").unwrap();
+        assert_eq!(read_response(), ["ok"]);
+        assert_eq!(
+            manager.inner.interval(),
+            std::time::Duration::from_millis(10)
         );
+        writeln!(stream, "foo").unwrap();
+        assert_eq!(read_response(), ["error: unknown command \"foo\""]);
 
-        assert!(bar.alive());
+        // A client not reading responses doesn't block bars
+        let mut flooding = std::os::unix::net::UnixStream::connect(&path).unwrap();
+        std::thread::spawn(move || {
+            for _ in 0..100_000 {
+                if flooding.write_all(b"list\nsnapshot\n").is_err() {
+                    break;
+                }
+            }
+        });
+        std::thread::sleep(std::time::Duration::from_millis(500));
+        bar.set_pos(4);
+        manager.inner.draw(true);
+        // Nor a command without a newline
+        let mut long = std::os::unix::net::UnixStream::connect(&path).unwrap();
+        long.write_all(&[b'a'; 4096]).unwrap();
+        let mut response = String::new();
+        std::io::BufReader::new(long)
+            .read_line(&mut response)
+            .unwrap();
+        assert_eq!(response, "error: command too long\n");
 
-        std::mem::drop(manager);
-        assert!(!bar.alive());
+        stream.shutdown(std::net::Shutdown::Both).unwrap();
+        // Nor dropping the socket
+        let start = std::time::Instant::now();
+        std::mem::drop(socket);
+        assert!(start.elapsed() < std::time::Duration::from_secs(1));
+        assert!(!path.exists());
+    }
+
+    #[cfg(feature = "http_dashboard")]
+    #[test]
+    fn http_dashboard() {
+        use std::io::{Read, Write};
+
+        let manager = Manager::new(std::time::Duration::from_secs(1));
+        let bar = manager.create_bar(10, "<Working>", "{msg}: {pos}/{len}", true);
+        bar.set_pos(3);
+        let dashboard = manager.serve_http("127.0.0.1:0").unwrap();
+        let request = |request: &str| {
+            let mut stream = std::net::TcpStream::connect(dashboard.local_addr()).unwrap();
+            stream.write_all(request.as_bytes()).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let response = request("GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains(
+            "<div><progress value=\"0.3\"></progress> <code>&lt;Working&gt;: 3/10</code></div>"
+        ));
+        let response = request("GET /snapshot.json HTTP/1.1\r\n\r\n");
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.contains("Content-Type: application/json"));
+        assert!(body.starts_with(r#"{"bars": [{"message": "<Working>", "pos": 3, "len": 10,"#));
+        assert!(request("GET /foo HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 404 "));
+        assert!(request("POST / HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 405 "));
+        // Headers over the limit are rejected
+        let mut oversize = "GET / HTTP/1.1\r\nX-Padding: ".to_string();
+        oversize.extend(std::iter::repeat('a').take(8 * 1024 - oversize.len()));
+        assert!(request(&oversize).starts_with("HTTP/1.1 431 "));
+        // An idle client doesn't block others
+        let idle = std::net::TcpStream::connect(dashboard.local_addr()).unwrap();
+        let start = std::time::Instant::now();
+        assert!(request("GET / HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 200 "));
+        assert!(start.elapsed() < std::time::Duration::from_secs(2));
+        drop(idle);
+        drop(dashboard);
+    }
+
+    #[cfg(all(unix, feature = "terminal_lock"))]
+    #[test]
+    fn terminal_lock() {
+        let path = std::env::temp_dir().join(format!("kyuri-lock-{}", std::process::id()));
+        let first = Manager::new(std::time::Duration::from_secs(1))
+            .force_ansi(true)
+            .with_terminal_lock(Some(&path))
+            .unwrap();
+        let second = Manager::new(std::time::Duration::from_secs(1))
+            .force_ansi(true)
+            .with_terminal_lock(Some(&path))
+            .unwrap();
+        let is_terminal = |m: &Manager| m.inner.is_terminal(&mut m.inner.out.lock().unwrap());
+        assert!(is_terminal(&first));
+        assert!(!is_terminal(&second));
+        std::mem::drop(first);
+        assert!(is_terminal(&second));
+        std::fs::remove_file(&path).unwrap();
     }
 
     #[cfg(target_os = "linux")]
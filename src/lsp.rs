@@ -0,0 +1,145 @@
+//! Report progress as LSP `$/progress` notifications, see `Manager::set_lsp_progress`.
+//!
+//! Each visible bar is a work done progress with token `"kyuri-<id>"`: created with a
+//! `window/workDoneProgress/create` request and "begin" when it's first seen, "report" when its
+//! percentage or message changes, and "end" when it's finished, failed, hidden or dropped.
+//! Messages are framed with a Content-Length header, as in the base protocol.
+
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    sync::{Arc, Mutex},
+};
+
+use crate::{BarState, LockUnpoisoned};
+
+/// What was last sent for a bar, to skip unchanged reports.
+#[derive(PartialEq)]
+struct Reported {
+    percentage: Option<u64>,
+    message: String,
+}
+
+pub(crate) struct LspProgress {
+    writer: Box<dyn Write + Send>,
+    reported: BTreeMap<usize, Reported>,
+}
+
+impl LspProgress {
+    pub(crate) fn new(writer: Box<dyn Write + Send>) -> Self {
+        LspProgress {
+            writer,
+            reported: BTreeMap::new(),
+        }
+    }
+
+    /// Send notifications for bars changed since the last update.
+    pub(crate) fn update(
+        &mut self,
+        states: &BTreeMap<usize, Arc<Mutex<BarState>>>,
+    ) -> std::io::Result<()> {
+        let mut result = Ok(());
+        let mut ended: Vec<usize> = self
+            .reported
+            .keys()
+            .filter(|id| !states.contains_key(id))
+            .copied()
+            .collect();
+        for (&id, state) in states {
+            let state = state.lock_unpoisoned();
+            if !state.visible || state.is_done() {
+                if self.reported.contains_key(&id) {
+                    ended.push(id);
+                }
+                continue;
+            }
+            let percentage = (state.len > 0)
+                .then(|| (state.pos.min(state.len) as u128 * 100 / state.len as u128) as u64);
+            let current = Reported {
+                percentage,
+                message: state.message.clone(),
+            };
+            let value = match self.reported.get(&id) {
+                None => {
+                    result = result.and(self.create(id));
+                    format!(
+                        r#"{{"kind":"begin","title":{}{}}}"#,
+                        json_string(&current.message),
+                        percentage_field(percentage)
+                    )
+                }
+                Some(last) if *last == current => continue,
+                Some(_) => format!(
+                    r#"{{"kind":"report","message":{}{}}}"#,
+                    json_string(&current.message),
+                    percentage_field(percentage)
+                ),
+            };
+            result = result.and(self.send(id, &value));
+            self.reported.insert(id, current);
+        }
+        for id in ended {
+            self.reported.remove(&id);
+            result = result.and(self.send(id, r#"{"kind":"end"}"#));
+        }
+        result.and(self.writer.flush())
+    }
+
+    /// Ask the client to create the token of bar `id`, with the token as the request id.
+    ///
+    /// The response is not waited for, as only a writer is given.
+    fn create(&mut self, id: usize) -> std::io::Result<()> {
+        self.write_message(&format!(
+            r#"{{"jsonrpc":"2.0","id":"kyuri-{0}","method":"window/workDoneProgress/create","params":{{"token":"kyuri-{0}"}}}}"#,
+            id
+        ))
+    }
+
+    fn send(&mut self, id: usize, value: &str) -> std::io::Result<()> {
+        self.write_message(&format!(
+            r#"{{"jsonrpc":"2.0","method":"$/progress","params":{{"token":"kyuri-{}","value":{}}}}}"#,
+            id, value
+        ))
+    }
+
+    fn write_message(&mut self, content: &str) -> std::io::Result<()> {
+        write!(
+            self.writer,
+            "Content-Length: {}\r\n\r\n{}",
+            content.len(),
+            content
+        )
+    }
+}
+
+impl Drop for LspProgress {
+    /// End progresses still shown, so that the editor doesn't keep them forever.
+    fn drop(&mut self) {
+        for id in std::mem::take(&mut self.reported).into_keys() {
+            let _ = self.send(id, r#"{"kind":"end"}"#);
+        }
+        let _ = self.writer.flush();
+    }
+}
+
+fn percentage_field(percentage: Option<u64>) -> String {
+    percentage.map_or(String::new(), |p| format!(r#","percentage":{}"#, p))
+}
+
+pub(crate) fn json_string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
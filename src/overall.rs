@@ -0,0 +1,111 @@
+//! The module contains `OverallBar`, a bar showing the progress of a whole job made of other bars.
+//!
+//! Each member bar contributes its fraction done, weighted by its len or an explicit weight, so
+//! the percentage stays accurate even when sizes of tasks differ wildly:
+//!
+//! ```text
+//! Total [=========           ] 470/1000
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::{Bar, BarState, LockUnpoisoned};
+
+struct Member {
+    id: usize,
+    /// `None` to weight by the len of the member.
+    weight: Option<u64>,
+    /// Position and length when last seen, kept after the member bar is dropped.
+    last: (u64, u64),
+}
+
+/// Members of an overall bar, shared by the bar state and the `OverallBar` handle.
+#[derive(Default)]
+pub(crate) struct Members {
+    members: Mutex<Vec<Member>>,
+}
+
+impl Members {
+    /// Compute the position and length of the overall bar `id`, in units of weight.
+    pub(crate) fn progress(
+        &self,
+        id: usize,
+        states: &BTreeMap<usize, Arc<Mutex<BarState>>>,
+    ) -> (u64, u64) {
+        let mut members = self.members.lock_unpoisoned();
+        let (mut pos, mut len) = (0u128, 0u128);
+        for member in members.iter_mut() {
+            // Itself is never a member, as locking it again would deadlock
+            if member.id == id {
+                continue;
+            }
+            if let Some(state) = states.get(&member.id) {
+                let state = state.lock_unpoisoned();
+                member.last = (state.pos, state.len);
+            }
+            let (member_pos, member_len) = member.last;
+            let weight = member.weight.unwrap_or(member_len) as u128;
+            len += weight;
+            if member_len > 0 {
+                pos += weight * member_pos.min(member_len) as u128 / member_len as u128;
+            }
+        }
+        let clamp = |value: u128| value.min(u64::MAX as u128) as u64;
+        (clamp(pos), clamp(len))
+    }
+}
+
+/// A bar of the overall progress of other bars, created by `Manager::create_overall_bar`.
+///
+/// Members are added with `add` or `add_weighted`, and the weighted sum of their fractions done
+/// becomes the position and length of this bar on each draw. A member dropped, finished or not,
+/// keeps contributing the progress it was last seen with.
+///
+/// The message, template and finishing are its own, set through `Deref` to the `Bar`; the position
+/// and length are not, as the weighted sum replaces them.
+pub struct OverallBar {
+    bar: Bar,
+    members: Arc<Members>,
+}
+
+impl OverallBar {
+    pub(crate) fn new(bar: Bar, members: Arc<Members>) -> Self {
+        OverallBar { bar, members }
+    }
+
+    /// Add `bar` as a member, weighted by its len. This makes an unforced draw.
+    pub fn add(&self, bar: &Bar) {
+        self.add_member(bar, None);
+    }
+
+    /// Add `bar` as a member with an explicit `weight`, no matter its len. This makes an unforced draw.
+    ///
+    /// This is useful when lens are in different units, like files and bytes.
+    pub fn add_weighted(&self, bar: &Bar, weight: u64) {
+        self.add_member(bar, Some(weight));
+    }
+
+    fn add_member(&self, bar: &Bar, weight: Option<u64>) {
+        let last = (bar.get_pos(), bar.get_len());
+        self.members.members.lock_unpoisoned().push(Member {
+            id: bar.id,
+            weight,
+            last,
+        });
+        if let Some(manager) = self.bar.manager.upgrade() {
+            manager.mark_redraw();
+            manager.draw(false);
+        }
+    }
+}
+
+impl std::ops::Deref for OverallBar {
+    type Target = Bar;
+
+    fn deref(&self) -> &Bar {
+        &self.bar
+    }
+}
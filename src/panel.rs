@@ -0,0 +1,88 @@
+//! The module contains `Panel`, a box around the bars in ANSI mode, set by `Manager::set_panel`.
+//!
+//! The top rule shows the title, so bars are visually separated from busy output above them:
+//!
+//! ```text
+//! ┌─ Downloads ──────────────────────┐
+//! a.iso: [=====     ] (50/100)
+//! b.iso: [==        ] (20/100)
+//! └──────────────────────────────────┘
+//! ```
+
+use crate::{char_width, string_width};
+
+/// A box of top and bottom rules around the bars, spanning the width of the terminal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Panel {
+    /// Shown in the top rule, truncated when it does not fit.
+    pub title: String,
+    /// Draw with `+` and `-` instead of Unicode box characters, for terminals and fonts without Unicode support.
+    pub ascii: bool,
+}
+
+impl Panel {
+    /// A panel drawn with Unicode box characters, or with ASCII characters with the `ascii_only` feature.
+    pub fn new(title: &str) -> Self {
+        Panel {
+            title: title.to_string(),
+            ascii: cfg!(feature = "ascii_only"),
+        }
+    }
+
+    /// A panel drawn with ASCII characters.
+    pub fn ascii(title: &str) -> Self {
+        Panel {
+            title: title.to_string(),
+            ascii: true,
+        }
+    }
+
+    fn chars(&self) -> [char; 5] {
+        // Horizontal rule, and corners from top left clockwise
+        match self.ascii {
+            true => ['-', '+', '+', '+', '+'],
+            false => ['─', '┌', '┐', '┘', '└'],
+        }
+    }
+
+    /// The top rule with the title, `width` columns wide, ending with `\n`.
+    pub(crate) fn top(&self, width: usize) -> String {
+        let [rule, top_left, top_right, _, _] = self.chars();
+        let width = width.max(2);
+        let mut line = String::from(top_left);
+        let mut used = 2;
+        if !self.title.is_empty() && width >= 7 {
+            // "─ " before and " " after the title, and at least one rule after it
+            let mut title = String::new();
+            let mut title_width = 0;
+            for c in self.title.chars() {
+                let w = char_width(c);
+                if used + 4 + title_width + w > width {
+                    break;
+                }
+                title.push(c);
+                title_width += w;
+            }
+            line.push(rule);
+            line.push(' ');
+            line.push_str(&title);
+            line.push(' ');
+            used += 3 + string_width(&title);
+        }
+        line.extend(std::iter::repeat(rule).take(width - used));
+        line.push(top_right);
+        line.push('\n');
+        line
+    }
+
+    /// The bottom rule, `width` columns wide, ending with `\n`.
+    pub(crate) fn bottom(&self, width: usize) -> String {
+        let [rule, _, _, bottom_right, bottom_left] = self.chars();
+        let width = width.max(2);
+        let mut line = String::from(bottom_left);
+        line.extend(std::iter::repeat(rule).take(width - 2));
+        line.push(bottom_right);
+        line.push('\n');
+        line
+    }
+}
@@ -0,0 +1,195 @@
+//! The module contains `SavedState`, the state of bars handed over to another process.
+//!
+//! A program that execs itself (like after a self-update) could save the state of its bars with
+//! `Manager::serialize_state`, pass the bytes to the new process (like with a file or an environment variable),
+//! and continue them with `Manager::deserialize_state`:
+//!
+//! ```
+//! let manager = kyuri::Manager::new(std::time::Duration::from_secs(1));
+//! let bar = manager.create_bar(100, "Updating", "{msg}: {pos}/{len}", true);
+//! bar.set_pos(42);
+//! let bytes = manager.serialize_state().unwrap();
+//!
+//! // In the new process
+//! let manager = kyuri::Manager::new(std::time::Duration::from_secs(1));
+//! let bars = manager.deserialize_state(&bytes).unwrap();
+//! assert_eq!(bars[0].get_pos(), 42);
+//! ```
+//!
+//! Templates are saved as their text, and parsed again by the restored bars. State saved by older versions
+//! has no templates, so its bars use the template of the theme until set again.
+//! With the `serde` feature, `SavedState` could also be serialized in other formats,
+//! with `Manager::saved_state` and `Manager::restore_state`.
+
+use std::collections::BTreeMap;
+
+/// "KYRI" and the version of the format.
+const MAGIC: &[u8; 4] = b"KYRI";
+const VERSION: u8 = 2;
+
+/// The state of bars of a manager, in the order of creation.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SavedState {
+    /// Saved bars.
+    pub bars: Vec<SavedBar>,
+}
+
+/// The state of a bar, which a restored bar continues from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SavedBar {
+    /// The position.
+    pub pos: u64,
+    /// The total length.
+    pub len: u64,
+    /// The message.
+    pub message: String,
+    /// The text of the template, or `None` to use the template of the theme.
+    pub template: Option<String>,
+    /// The elapsed time, kept by the restored bar.
+    pub elapsed: std::time::Duration,
+    /// Whether the bar is visible.
+    pub visible: bool,
+    /// Whether the bar is marked as failed.
+    pub failed: bool,
+    /// Fields set by `Bar::set_field`.
+    pub fields: BTreeMap<String, String>,
+}
+
+/// The error when decoding bytes from `Manager::serialize_state`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// Not started with the magic, or from a newer version of kyuri.
+    UnknownFormat,
+    /// Ended in the middle of a value.
+    Truncated,
+    /// A string that is not UTF-8.
+    InvalidUtf8,
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::UnknownFormat => write!(f, "unknown format of saved state"),
+            DecodeError::Truncated => write!(f, "saved state is truncated"),
+            DecodeError::InvalidUtf8 => write!(f, "saved state has a string not in UTF-8"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl SavedState {
+    /// Encode in the compact binary format: integers are little endian, and strings are prefixed by their lengths.
+    ///
+    /// Returns an error of `InvalidInput` when a string or a list is longer than `u32::MAX`.
+    pub fn to_bytes(&self) -> std::io::Result<Vec<u8>> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        put_len(&mut bytes, self.bars.len())?;
+        for bar in &self.bars {
+            bytes.extend_from_slice(&bar.pos.to_le_bytes());
+            bytes.extend_from_slice(&bar.len.to_le_bytes());
+            let elapsed = bar.elapsed.as_millis().min(u64::MAX as u128) as u64;
+            bytes.extend_from_slice(&elapsed.to_le_bytes());
+            bytes.push(
+                u8::from(bar.visible)
+                    | u8::from(bar.failed) << 1
+                    | u8::from(bar.template.is_some()) << 2,
+            );
+            put_str(&mut bytes, &bar.message)?;
+            if let Some(template) = &bar.template {
+                put_str(&mut bytes, template)?;
+            }
+            put_len(&mut bytes, bar.fields.len())?;
+            for (name, value) in &bar.fields {
+                put_str(&mut bytes, name)?;
+                put_str(&mut bytes, value)?;
+            }
+        }
+        Ok(bytes)
+    }
+
+    /// Decode from `to_bytes`, of this version or older ones.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader(bytes);
+        if reader.take(MAGIC.len()) != Ok(MAGIC)
+            || !matches!(reader.take(1), Ok(&[version]) if (1..=VERSION).contains(&version))
+        {
+            return Err(DecodeError::UnknownFormat);
+        }
+        let count = reader.u32()?;
+        let mut bars = Vec::new();
+        for _ in 0..count {
+            let pos = reader.u64()?;
+            let len = reader.u64()?;
+            let elapsed = std::time::Duration::from_millis(reader.u64()?);
+            let flags = reader.take(1)?[0];
+            let message = reader.string()?;
+            // Version 1 never sets the flag of templates
+            let template = if flags & 4 != 0 {
+                Some(reader.string()?)
+            } else {
+                None
+            };
+            let mut fields = BTreeMap::new();
+            for _ in 0..reader.u32()? {
+                let name = reader.string()?;
+                fields.insert(name, reader.string()?);
+            }
+            bars.push(SavedBar {
+                pos,
+                len,
+                message,
+                template,
+                elapsed,
+                visible: flags & 1 != 0,
+                failed: flags & 2 != 0,
+                fields,
+            });
+        }
+        Ok(SavedState { bars })
+    }
+}
+
+fn put_len(bytes: &mut Vec<u8>, len: usize) -> std::io::Result<()> {
+    let len = u32::try_from(len).map_err(|_| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "saved state too large")
+    })?;
+    bytes.extend_from_slice(&len.to_le_bytes());
+    Ok(())
+}
+
+fn put_str(bytes: &mut Vec<u8>, s: &str) -> std::io::Result<()> {
+    put_len(bytes, s.len())?;
+    bytes.extend_from_slice(s.as_bytes());
+    Ok(())
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], DecodeError> {
+        if self.0.len() < n {
+            return Err(DecodeError::Truncated);
+        }
+        let (taken, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, DecodeError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        let len = self.u32()? as usize;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidUtf8)
+    }
+}
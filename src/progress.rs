@@ -0,0 +1,95 @@
+//! The module contains `Progress`, for libraries to report progress without depending on how it's shown.
+//!
+//! A library function could take `&dyn Progress`, and its caller passes a `Bar`, a `SubBar`, or `NoProgress`
+//! when progress is not shown:
+//!
+//! ```
+//! use kyuri::progress::{NoProgress, Progress};
+//!
+//! fn checksum(data: &[u8], progress: &dyn Progress) -> u32 {
+//!     progress.set_message("Checksumming");
+//!     progress.set_len(data.len() as u64);
+//!     let mut sum = 0u32;
+//!     for chunk in data.chunks(1024) {
+//!         sum = chunk.iter().fold(sum, |sum, &b| sum.wrapping_add(b as u32));
+//!         progress.inc(chunk.len() as u64);
+//!     }
+//!     sum
+//! }
+//!
+//! let manager = kyuri::Manager::new(std::time::Duration::from_secs(1));
+//! let bar = manager.create_bar(0, "", "{msg}: {pos}/{len}", true);
+//! checksum(&[1; 4096], &bar);
+//! assert_eq!(bar.get_pos(), 4096);
+//! checksum(&[1; 4096], &NoProgress);
+//! ```
+
+use crate::{subrange::SubBar, Bar};
+
+/// Something progress is reported to, like a `Bar`.
+pub trait Progress: Send + Sync {
+    /// Increment the position by `n`.
+    fn inc(&self, n: u64);
+
+    /// Set the total length.
+    fn set_len(&self, len: u64);
+
+    /// Set the message.
+    fn set_message(&self, message: &str);
+
+    /// Report to a part from `start` to `end` (fractions from 0 to 1) with its own position and length,
+    /// like `Bar::subrange`.
+    fn subrange(&self, start: f64, end: f64) -> Box<dyn Progress + '_>;
+}
+
+impl Progress for Bar {
+    fn inc(&self, n: u64) {
+        Bar::inc(self, n);
+    }
+
+    fn set_len(&self, len: u64) {
+        Bar::set_len(self, len);
+    }
+
+    fn set_message(&self, message: &str) {
+        Bar::set_message(self, message);
+    }
+
+    fn subrange(&self, start: f64, end: f64) -> Box<dyn Progress + '_> {
+        Box::new(Bar::subrange(self, start, end))
+    }
+}
+
+impl Progress for SubBar<'_> {
+    fn inc(&self, n: u64) {
+        SubBar::inc(self, n);
+    }
+
+    fn set_len(&self, len: u64) {
+        SubBar::set_len(self, len);
+    }
+
+    fn set_message(&self, message: &str) {
+        SubBar::set_message(self, message);
+    }
+
+    fn subrange(&self, start: f64, end: f64) -> Box<dyn Progress + '_> {
+        Box::new(SubBar::subrange(self, start, end))
+    }
+}
+
+/// Progress reported to nowhere, for callers not showing it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoProgress;
+
+impl Progress for NoProgress {
+    fn inc(&self, _n: u64) {}
+
+    fn set_len(&self, _len: u64) {}
+
+    fn set_message(&self, _message: &str) {}
+
+    fn subrange(&self, _start: f64, _end: f64) -> Box<dyn Progress + '_> {
+        Box::new(NoProgress)
+    }
+}
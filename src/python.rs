@@ -0,0 +1,169 @@
+//! The module contains `Manager` and `Bar` classes for Python, with the `python` feature.
+//!
+//! They wrap `kyuri::Manager` and `kyuri::Bar` with plain arguments, and raise exceptions instead of
+//! returning errors or panicking. A Python extension crate could add them to its module with `register`:
+//!
+//! ```ignore
+//! #[pyo3::pymodule]
+//! fn kyuri(m: &pyo3::Bound<'_, pyo3::types::PyModule>) -> pyo3::PyResult<()> {
+//!     kyuri::python::register(m)
+//! }
+//! ```
+//!
+//! And be used by Python scripts like:
+//!
+//! ```python
+//! import kyuri
+//!
+//! manager = kyuri.Manager(interval=0.1)
+//! bar = manager.create_bar(100, "Download", "{msg}: {bar} ({pos}/{len})")
+//! bar.inc(10)
+//! bar.finish()
+//! ```
+
+// Errors returned are converted into `PyErr` again by the macros of pyo3
+#![allow(clippy::useless_conversion)]
+
+use pyo3::{
+    exceptions::{PyRuntimeError, PyValueError},
+    prelude::*,
+};
+
+use crate::{ManagerGone, Template};
+
+fn gone(_: ManagerGone) -> PyErr {
+    PyRuntimeError::new_err("manager is dropped")
+}
+
+/// `kyuri.Manager`, drawing to stderr.
+#[pyclass(name = "Manager", module = "kyuri")]
+pub struct PyManager {
+    manager: crate::Manager,
+}
+
+#[pymethods]
+impl PyManager {
+    /// A manager with the minimum interval between unforced draws in seconds.
+    #[new]
+    #[pyo3(signature = (interval = 0.1))]
+    fn new(interval: f64) -> PyResult<Self> {
+        let interval = std::time::Duration::try_from_secs_f64(interval)
+            .map_err(|e| PyValueError::new_err(format!("invalid interval: {}", e)))?;
+        Ok(PyManager {
+            manager: crate::Manager::new(interval),
+        })
+    }
+
+    /// Like `Manager::create_bar`, but an invalid template raises `ValueError`.
+    #[pyo3(signature = (len, message, template, visible = true))]
+    fn create_bar(
+        &self,
+        len: u64,
+        message: &str,
+        template: &str,
+        visible: bool,
+    ) -> PyResult<PyBar> {
+        let template = Template::parse(template)
+            .map_err(|e| PyValueError::new_err(format!("invalid template: {}", e)))?;
+        Ok(PyBar {
+            bar: self.manager.create_bar(len, message, template, visible),
+        })
+    }
+
+    /// Like `Manager::draw`.
+    #[pyo3(signature = (force = false))]
+    fn draw(&self, force: bool) {
+        self.manager.draw(force);
+    }
+
+    /// Like `Manager::log_line`.
+    fn log_line(&self, line: &str) {
+        self.manager.log_line(line);
+    }
+
+    /// Like `Manager::set_ticker`.
+    fn set_ticker(&self, enabled: bool) {
+        self.manager.set_ticker(enabled);
+    }
+}
+
+/// `kyuri.Bar`, created by `Manager.create_bar`, and removed when garbage collected.
+///
+/// Methods raise `RuntimeError` when the manager is dropped.
+#[pyclass(name = "Bar", module = "kyuri")]
+pub struct PyBar {
+    bar: crate::Bar,
+}
+
+#[pymethods]
+impl PyBar {
+    /// Like `Bar::inc`.
+    #[pyo3(signature = (n = 1))]
+    fn inc(&self, n: u64) -> PyResult<()> {
+        self.bar.try_inc(n).map_err(gone)
+    }
+
+    /// Like `Bar::set_pos`.
+    fn set_pos(&self, pos: u64) -> PyResult<()> {
+        self.bar.try_set_pos(pos).map_err(gone)
+    }
+
+    /// Like `Bar::set_len`.
+    fn set_len(&self, len: u64) -> PyResult<()> {
+        self.bar.try_set_len(len).map_err(gone)
+    }
+
+    /// Like `Bar::set_message`.
+    fn set_message(&self, message: &str) -> PyResult<()> {
+        self.bar.try_set_message(message).map_err(gone)
+    }
+
+    /// Like `Bar::finish`.
+    fn finish(&self) -> PyResult<()> {
+        self.bar.try_finish().map_err(gone)
+    }
+
+    /// Like `Bar::fail`.
+    #[pyo3(signature = (failed = true))]
+    fn fail(&self, failed: bool) -> PyResult<()> {
+        self.alive()?;
+        self.bar.fail(failed);
+        Ok(())
+    }
+
+    /// Like `Bar::set_visible`.
+    fn set_visible(&self, visible: bool) -> PyResult<()> {
+        self.alive()?;
+        self.bar.set_visible(visible);
+        Ok(())
+    }
+
+    /// Like `Bar::get_pos`.
+    #[getter]
+    fn pos(&self) -> PyResult<u64> {
+        self.bar.try_get_pos().map_err(gone)
+    }
+
+    /// Like `Bar::get_len`.
+    #[getter]
+    fn len(&self) -> PyResult<u64> {
+        self.alive()?;
+        Ok(self.bar.get_len())
+    }
+}
+
+impl PyBar {
+    fn alive(&self) -> PyResult<()> {
+        match self.bar.alive() {
+            true => Ok(()),
+            false => Err(gone(ManagerGone)),
+        }
+    }
+}
+
+/// Add `Manager` and `Bar` to the module `m`.
+pub fn register(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyManager>()?;
+    m.add_class::<PyBar>()?;
+    Ok(())
+}
@@ -0,0 +1,53 @@
+//! Record output to an asciinema cast (v2), see `Manager::with_recording`.
+//!
+//! The first line is a header, and each following line is an output event with the seconds since the start:
+//!
+//! ```text
+//! {"version": 2, "width": 80, "height": 24, "timestamp": 1700000000}
+//! [0.000000, "o", "Download: [          ] (0/100)\n"]
+//! ```
+
+use std::io::Write;
+
+use crate::{clock, lsp::json_string};
+
+/// The height in the header, as the height of the terminal is not detected.
+const HEIGHT: u16 = 24;
+
+pub(crate) struct Recorder {
+    writer: Box<dyn Write + Send>,
+    started_at: std::time::Instant,
+}
+
+impl Recorder {
+    pub(crate) fn new(mut writer: Box<dyn Write + Send>, width: u16) -> std::io::Result<Self> {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        writeln!(
+            writer,
+            r#"{{"version": 2, "width": {}, "height": {}, "timestamp": {}}}"#,
+            width, HEIGHT, timestamp
+        )?;
+        Ok(Recorder {
+            writer,
+            started_at: clock::now(),
+        })
+    }
+
+    /// Record `data` written to the output. Errors are ignored, so the output is not affected.
+    pub(crate) fn record(&mut self, data: &str) {
+        if data.is_empty() {
+            return;
+        }
+        let time = clock::now().saturating_duration_since(self.started_at);
+        let _ = writeln!(
+            self.writer,
+            r#"[{:.6}, "o", {}]"#,
+            time.as_secs_f64(),
+            // As a terminal would translate newlines
+            json_string(&data.replace('\n', "\r\n"))
+        );
+        let _ = self.writer.flush();
+    }
+}
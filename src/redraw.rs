@@ -0,0 +1,147 @@
+//! The flag telling draws whether anything has changed, shared by bar methods, the ticker and draws.
+//!
+//! Its atomics, the lock serializing draws and the reentrancy marks are replaced by loom's with
+//! `RUSTFLAGS="--cfg loom"`, to check bar methods racing with draws of the real manager with loom:
+//!
+//! ```sh
+//! RUSTFLAGS="--cfg loom" cargo test --release --lib redraw
+//! ```
+//!
+//! Other tests are compiled out with `--cfg loom`, as loom primitives only work inside `loom::model`.
+
+#[cfg(loom)]
+use loom::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex, MutexGuard,
+};
+#[cfg(not(loom))]
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex, MutexGuard,
+};
+
+/// The time of the last draw, locked by draws from start to end.
+pub(crate) struct LastDraw(Mutex<std::time::Instant>);
+
+impl LastDraw {
+    pub(crate) fn new(instant: std::time::Instant) -> Self {
+        LastDraw(Mutex::new(instant))
+    }
+
+    pub(crate) fn lock(&self) -> MutexGuard<'_, std::time::Instant> {
+        self.0
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+pub(crate) struct RedrawFlag(AtomicBool);
+
+impl RedrawFlag {
+    pub(crate) fn new(value: bool) -> Self {
+        RedrawFlag(AtomicBool::new(value))
+    }
+
+    /// Request a redraw, after the change is made.
+    ///
+    /// The change is seen by the draw taking this request, or the request is left for the next draw.
+    pub(crate) fn mark(&self) {
+        // A read-modify-write rather than a store, which loom models more precisely when racing with `take`
+        self.0.fetch_or(true, Ordering::Release);
+    }
+
+    /// Take the request before drawing, returning whether there's any.
+    pub(crate) fn take(&self) -> bool {
+        self.0.swap(false, Ordering::AcqRel)
+    }
+}
+
+#[cfg(all(test, loom))]
+mod tests {
+    use super::RedrawFlag;
+    use crate::{Manager, CLEAR_ANSI};
+    use loom::sync::Arc;
+    use std::io::{Read, Seek};
+
+    /// A manager writing to a new temporary file, and the file to read it back.
+    fn file_manager(name: &str) -> (Manager, std::fs::File) {
+        let path = std::env::temp_dir().join(format!("kyuri-loom-{}-{}", name, std::process::id()));
+        let file = std::fs::File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .unwrap();
+        let reader = file.try_clone().unwrap();
+        let manager = Manager::new(std::time::Duration::from_secs(1)).with_file(file);
+        (manager, reader)
+    }
+
+    fn read_file(mut file: std::fs::File) -> String {
+        file.seek(std::io::SeekFrom::Start(0)).unwrap();
+        let mut output = String::new();
+        file.read_to_string(&mut output).unwrap();
+        output
+    }
+
+    /// A change made by a bar method is never lost: it's drawn, or a redraw is still requested.
+    #[test]
+    fn change_not_lost() {
+        loom::model(|| {
+            let (manager, file) = file_manager("change_not_lost");
+            let bar = Arc::new(manager.create_bar(10, "a", "{msg} {pos}", true));
+
+            let updater = {
+                let bar = bar.clone();
+                loom::thread::spawn(move || bar.set_pos(1))
+            };
+            // The ticker draws
+            manager.inner.draw(true);
+            updater.join().unwrap();
+
+            let output = read_file(file);
+            assert!(
+                manager.inner.need_redraw.take() || output.ends_with("a 1\n"),
+                "{output:?}"
+            );
+        });
+    }
+
+    /// A dropped bar is never left on screen: the draw doesn't see it, or a redraw is still requested.
+    #[test]
+    fn bar_drop_vs_draw() {
+        loom::model(|| {
+            let (manager, file) = file_manager("bar_drop_vs_draw");
+            let manager = Arc::new(manager.force_ansi(true));
+            let bar = manager.create_bar(10, "a", "{msg} {pos}", true);
+            let dropped = manager.create_bar(10, "b", "{msg} {pos}", true);
+
+            let dropper = loom::thread::spawn(move || drop(dropped));
+            manager.inner.draw(true);
+            dropper.join().unwrap();
+
+            let output = read_file(file);
+            let last_frame = output.rsplit(CLEAR_ANSI).next().unwrap();
+            assert!(
+                manager.inner.need_redraw.take() || last_frame == "a 0\n",
+                "{output:?}"
+            );
+            drop(bar);
+        });
+    }
+
+    /// Concurrent draws draw each change once: only one of them takes a request.
+    #[test]
+    fn concurrent_draws() {
+        loom::model(|| {
+            let flag = Arc::new(RedrawFlag::new(true));
+            let other = {
+                let flag = flag.clone();
+                loom::thread::spawn(move || flag.take())
+            };
+            let taken = flag.take();
+            assert!(taken != other.join().unwrap());
+        });
+    }
+}
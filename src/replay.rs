@@ -0,0 +1,110 @@
+//! The module contains `play`, replaying casts recorded by `Manager::with_recording`.
+//!
+//! This is useful for demos, and for debugging rendering issues with recordings attached to bug reports:
+//!
+//! ```no_run
+//! let cast = std::io::BufReader::new(std::fs::File::open("progress.cast").unwrap());
+//! kyuri::replay::play(cast, std::io::stdout(), 2.0).unwrap();
+//! ```
+//!
+//! Other asciinema casts (v2) could be replayed too. Events other than output (like input and markers) are skipped.
+
+use std::io::{BufRead, Write};
+
+/// Write output events of the cast from `reader` to `writer`, waiting between them as recorded,
+/// divided by `speed` (like 2.0 for twice as fast, or `f64::INFINITY` without waiting).
+///
+/// Returns an error of `ErrorKind::InvalidInput` when `speed` is not positive,
+/// and of `ErrorKind::InvalidData` when the cast is malformed.
+pub fn play<R: BufRead, W: Write>(reader: R, mut writer: W, speed: f64) -> std::io::Result<()> {
+    if speed.is_nan() || speed <= 0.0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "speed must be positive",
+        ));
+    }
+    let mut lines = reader.lines();
+    match lines.next().transpose()? {
+        Some(header) if header.trim_start().starts_with('{') => {}
+        _ => return Err(invalid("missing header")),
+    }
+    let mut last_time = 0.0;
+    for line in lines {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (time, kind, data) = parse_event(&line).ok_or_else(|| invalid("malformed event"))?;
+        if kind != "o" {
+            continue;
+        }
+        let delay = (time - last_time).max(0.0) / speed;
+        if delay > 0.0 {
+            writer.flush()?;
+            std::thread::sleep(std::time::Duration::from_secs_f64(delay));
+        }
+        last_time = time;
+        writer.write_all(data.as_bytes())?;
+    }
+    writer.flush()
+}
+
+fn invalid(reason: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, reason.to_string())
+}
+
+/// Parse `[time, "kind", "data"]`.
+fn parse_event(line: &str) -> Option<(f64, String, String)> {
+    let rest = line.trim().strip_prefix('[')?.strip_suffix(']')?;
+    let (time, rest) = rest.split_once(',')?;
+    let time = time.trim().parse::<f64>().ok().filter(|t| t.is_finite())?;
+    let (kind, rest) = parse_string(rest.trim_start())?;
+    let rest = rest.trim_start().strip_prefix(',')?;
+    let (data, rest) = parse_string(rest.trim_start())?;
+    rest.trim().is_empty().then_some((time, kind, data))
+}
+
+/// Parse a JSON string at the start of `s`, and return it with the rest.
+fn parse_string(s: &str) -> Option<(String, &str)> {
+    let mut chars = s.strip_prefix('"')?.char_indices();
+    let mut result = String::new();
+    // A high surrogate waiting for the low one
+    let mut high: Option<u32> = None;
+    while let Some((i, c)) = chars.next() {
+        let c = match c {
+            '"' => return high.is_none().then(|| (result, &s[i + 2..])),
+            '\\' => match chars.next()?.1 {
+                'u' => {
+                    let mut code = 0;
+                    for _ in 0..4 {
+                        code = code * 16 + chars.next()?.1.to_digit(16)?;
+                    }
+                    match (high.take(), code) {
+                        (None, 0xd800..=0xdbff) => {
+                            high = Some(code);
+                            continue;
+                        }
+                        (Some(high), 0xdc00..=0xdfff) => {
+                            char::from_u32(0x10000 + ((high - 0xd800) << 10) + (code - 0xdc00))?
+                        }
+                        (None, code) => char::from_u32(code)?,
+                        (Some(_), _) => return None,
+                    }
+                }
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                'b' => '\u{8}',
+                'f' => '\u{c}',
+                c @ ('"' | '\\' | '/') => c,
+                _ => return None,
+            },
+            c => c,
+        };
+        if high.is_some() {
+            return None;
+        }
+        result.push(c);
+    }
+    None
+}
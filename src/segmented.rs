@@ -0,0 +1,161 @@
+//! The module contains `SegmentedBar`, a bar of a download split into byte ranges (segments),
+//! like one with several connections.
+//!
+//! `{bar}` of a segmented bar fills each cell by the bytes downloaded in its range, like aria2:
+//!
+//! ```text
+//! [=====     ====      ===       ]
+//! ```
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Weak,
+};
+
+use crate::{theme::Theme, Bar, ManagerInner};
+
+/// Lengths and positions of segments, shared by the bar state and `Segment` handles.
+pub(crate) struct Segments {
+    lens: Vec<u64>,
+    positions: Vec<AtomicU64>,
+}
+
+impl Segments {
+    pub(crate) fn new(lens: &[u64]) -> Self {
+        Segments {
+            lens: lens.to_vec(),
+            positions: lens.iter().map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    pub(crate) fn len(&self) -> u64 {
+        self.lens
+            .iter()
+            .fold(0, |total, &len| total.saturating_add(len))
+    }
+
+    /// The sum of positions, which is the position of the bar.
+    pub(crate) fn pos(&self) -> u64 {
+        self.positions.iter().fold(0, |total, pos| {
+            total.saturating_add(pos.load(Ordering::Acquire))
+        })
+    }
+
+    /// Render `{bar}` with `size` cells: a cell is filled when at least half of its bytes are downloaded.
+    pub(crate) fn render_bar(&self, size: usize, theme: &Theme) -> String {
+        // Downloaded ranges, in u128 to avoid overflows
+        let mut ranges = Vec::with_capacity(self.lens.len());
+        let mut start = 0u128;
+        for (len, pos) in self.lens.iter().zip(&self.positions) {
+            let pos = pos.load(Ordering::Acquire).min(*len);
+            ranges.push((start, start + pos as u128));
+            start += *len as u128;
+        }
+        let total = start;
+        let mut result = theme.bar_left.clone();
+        for cell in 0..size as u128 {
+            let cell_start = total * cell / size as u128;
+            // At least one byte, when there are fewer bytes than cells
+            let cell_end = (total * (cell + 1) / size as u128).max(cell_start + 1);
+            let downloaded: u128 = ranges
+                .iter()
+                .map(|&(start, end)| end.min(cell_end).saturating_sub(start.max(cell_start)))
+                .sum();
+            if total == 0 || downloaded * 2 >= cell_end - cell_start {
+                result.push_str(&theme.bar_filled);
+            } else {
+                result.push_str(&theme.bar_empty);
+            }
+        }
+        result.push_str(&theme.bar_right);
+        result
+    }
+}
+
+/// A bar of a download split into segments, created by `Manager::create_segmented_bar`.
+///
+/// Its position is the sum of positions of segments, updated with `Segment` handles from `segment`.
+/// It dereferences to the `Bar`, to set the message or finish it, etc.; but `set_pos` or `inc` on it
+/// would be overwritten by the next draw.
+pub struct SegmentedBar {
+    bar: Bar,
+    segments: Arc<Segments>,
+}
+
+/// A handle for a segment of a `SegmentedBar`, which could be cloned and sent to other threads.
+#[derive(Clone)]
+pub struct Segment {
+    index: usize,
+    segments: Arc<Segments>,
+    manager: Weak<ManagerInner>,
+}
+
+impl SegmentedBar {
+    pub(crate) fn new(bar: Bar, segments: Arc<Segments>) -> Self {
+        SegmentedBar { bar, segments }
+    }
+
+    /// Get the handle of the `index`-th segment.
+    ///
+    /// Panics if `index` is out of range.
+    pub fn segment(&self, index: usize) -> Segment {
+        assert!(
+            index < self.segments.lens.len(),
+            "segment {} out of range",
+            index
+        );
+        Segment {
+            index,
+            segments: self.segments.clone(),
+            manager: self.bar.manager.clone(),
+        }
+    }
+
+    /// Get the number of segments.
+    pub fn segment_count(&self) -> usize {
+        self.segments.lens.len()
+    }
+}
+
+impl std::ops::Deref for SegmentedBar {
+    type Target = Bar;
+
+    fn deref(&self) -> &Bar {
+        &self.bar
+    }
+}
+
+impl Segment {
+    /// Set the position of the segment, in bytes from its start. This makes an unforced draw.
+    ///
+    /// This doesn't lock the bar, so segments could be updated from many threads.
+    pub fn set_pos(&self, pos: u64) {
+        self.segments.positions[self.index].store(pos, Ordering::Release);
+        self.draw();
+    }
+
+    /// Increment the position of the segment by `n`. This makes an unforced draw.
+    pub fn inc(&self, n: u64) {
+        let pos = &self.segments.positions[self.index];
+        let _ = pos.fetch_update(Ordering::AcqRel, Ordering::Acquire, |pos| {
+            Some(pos.saturating_add(n))
+        });
+        self.draw();
+    }
+
+    /// Get the position of the segment.
+    pub fn get_pos(&self) -> u64 {
+        self.segments.positions[self.index].load(Ordering::Acquire)
+    }
+
+    /// Get the length of the segment.
+    pub fn get_len(&self) -> u64 {
+        self.segments.lens[self.index]
+    }
+
+    fn draw(&self) {
+        if let Some(manager) = self.manager.upgrade() {
+            manager.draw(false);
+        }
+    }
+}
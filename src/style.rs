@@ -0,0 +1,319 @@
+//! The module contains color support of templates.
+//!
+//! Templates could contain style tags like `{red}`, `{bold}`, `{color:208}` (256 colors),
+//! `{#ff8800}` (truecolor) and `{reset}`. They are degraded to the nearest color the output supports,
+//! or removed entirely when the output is not a terminal.
+
+/// Colors supported by the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorSupport {
+    /// No colors or styles. Style tags are removed.
+    None,
+    /// 16 colors.
+    Ansi16,
+    /// 256 colors.
+    Ansi256,
+    /// 24-bit colors.
+    TrueColor,
+}
+
+impl ColorSupport {
+    /// Detect the color support of the terminal by `NO_COLOR`, `COLORTERM` and `TERM` environment variables.
+    ///
+    /// Without the `color_detection` feature, this always returns `ColorSupport::Ansi16`.
+    pub fn detect() -> Self {
+        #[cfg(feature = "color_detection")]
+        {
+            if std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()) {
+                return ColorSupport::None;
+            }
+            let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+            if colorterm == "truecolor" || colorterm == "24bit" {
+                return ColorSupport::TrueColor;
+            }
+            let term = std::env::var("TERM").unwrap_or_default();
+            if term == "dumb" {
+                ColorSupport::None
+            } else if term.contains("256color") {
+                ColorSupport::Ansi256
+            } else {
+                ColorSupport::Ansi16
+            }
+        }
+
+        #[cfg(not(feature = "color_detection"))]
+        {
+            ColorSupport::Ansi16
+        }
+    }
+}
+
+/// A foreground color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// One of 16 colors: 0-7 for black, red, green, yellow, blue, magenta, cyan, white, and 8-15 for bright ones.
+    Ansi16(u8),
+    /// One of 256 colors.
+    Ansi256(u8),
+    /// A 24-bit color.
+    Rgb(u8, u8, u8),
+}
+
+/// An effect to draw attention to bars in certain states, set by `Manager::set_stalled_effect`
+/// and `Manager::set_failed_effect`. It's applied to the whole bar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Effect {
+    /// Inverse video.
+    Inverse,
+    /// Change the foreground color.
+    Color(Color),
+    /// Inverse video on alternating draws.
+    PulseInverse,
+    /// Change the foreground color on alternating draws.
+    PulseColor(Color),
+}
+
+impl Effect {
+    /// Return whether the effect changes between draws.
+    pub(crate) fn is_pulse(self) -> bool {
+        matches!(self, Effect::PulseInverse | Effect::PulseColor(_))
+    }
+
+    /// The escape sequence of the effect on the `frame`th draw.
+    pub(crate) fn to_ansi(self, frame: usize, support: ColorSupport) -> Option<String> {
+        let style = match self {
+            Effect::Inverse => Style::Inverse,
+            Effect::Color(color) => Style::Foreground(color),
+            Effect::PulseInverse if frame % 2 == 0 => Style::Inverse,
+            Effect::PulseColor(color) if frame % 2 == 0 => Style::Foreground(color),
+            Effect::PulseInverse | Effect::PulseColor(_) => return None,
+        };
+        style.to_ansi(support)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Style {
+    Reset,
+    Bold,
+    Dim,
+    Inverse,
+    Foreground(Color),
+}
+
+pub(crate) const RESET_ANSI: &str = "\x1b[0m";
+/// Reset the foreground color only.
+pub(crate) const DEFAULT_FOREGROUND_ANSI: &str = "\x1b[39m";
+
+/// A color gradient for the fill of `{bar}`, set by `Bar::set_gradient`.
+///
+/// The fill is colored by interpolating the stops at the completion percentage.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gradient {
+    stops: Vec<(u8, u8, u8)>,
+}
+
+impl Gradient {
+    /// Create a gradient from RGB stops, evenly distributed from 0% to 100%.
+    ///
+    /// Panics if `stops` is empty.
+    pub fn new(stops: &[(u8, u8, u8)]) -> Self {
+        assert!(!stops.is_empty(), "a gradient requires at least one stop");
+        Gradient {
+            stops: stops.to_vec(),
+        }
+    }
+
+    /// Red, then yellow, then green.
+    pub fn red_yellow_green() -> Self {
+        Gradient::new(&[(255, 0, 0), (255, 255, 0), (0, 255, 0)])
+    }
+
+    /// The color at `fraction` (clamped to 0.0..=1.0).
+    pub(crate) fn at(&self, fraction: f64) -> Color {
+        let fraction = if fraction.is_nan() {
+            0.0
+        } else {
+            fraction.clamp(0.0, 1.0)
+        };
+        if self.stops.len() == 1 {
+            let (r, g, b) = self.stops[0];
+            return Color::Rgb(r, g, b);
+        }
+        let scaled = fraction * (self.stops.len() - 1) as f64;
+        let i = (scaled as usize).min(self.stops.len() - 2);
+        let t = scaled - i as f64;
+        let (a, b) = (self.stops[i], self.stops[i + 1]);
+        let lerp = |x: u8, y: u8| (x as f64 + (y as f64 - x as f64) * t).round() as u8;
+        Color::Rgb(lerp(a.0, b.0), lerp(a.1, b.1), lerp(a.2, b.2))
+    }
+}
+
+const ANSI16_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let dr = a.0 as i32 - b.0 as i32;
+    let dg = a.1 as i32 - b.1 as i32;
+    let db = a.2 as i32 - b.2 as i32;
+    (dr * dr + dg * dg + db * db) as u32
+}
+
+fn ansi256_to_rgb(i: u8) -> (u8, u8, u8) {
+    match i {
+        0..=15 => ANSI16_RGB[i as usize],
+        16..=231 => {
+            let i = i - 16;
+            (
+                CUBE_LEVELS[(i / 36) as usize],
+                CUBE_LEVELS[(i / 6 % 6) as usize],
+                CUBE_LEVELS[(i % 6) as usize],
+            )
+        }
+        _ => {
+            let level = 8 + (i - 232) * 10;
+            (level, level, level)
+        }
+    }
+}
+
+fn rgb_to_ansi256(rgb: (u8, u8, u8)) -> u8 {
+    let nearest_level = |c: u8| {
+        (0..6)
+            .min_by_key(|&i| (CUBE_LEVELS[i] as i32 - c as i32).abs())
+            .unwrap() as u8
+    };
+    let cube = 16 + 36 * nearest_level(rgb.0) + 6 * nearest_level(rgb.1) + nearest_level(rgb.2);
+    let average = (rgb.0 as u32 + rgb.1 as u32 + rgb.2 as u32) / 3;
+    let gray = 232 + ((average.saturating_sub(3)) / 10).min(23) as u8;
+    if distance(ansi256_to_rgb(gray), rgb) < distance(ansi256_to_rgb(cube), rgb) {
+        gray
+    } else {
+        cube
+    }
+}
+
+fn rgb_to_ansi16(rgb: (u8, u8, u8)) -> u8 {
+    (0..16)
+        .min_by_key(|&i| distance(ANSI16_RGB[i], rgb))
+        .unwrap() as u8
+}
+
+impl Color {
+    /// Degrade the color to the nearest one supported, or `None` when colors are not supported.
+    pub(crate) fn degrade(self, support: ColorSupport) -> Option<Color> {
+        match (self, support) {
+            (_, ColorSupport::None) => None,
+            (Color::Ansi16(_), _) => Some(self),
+            (Color::Ansi256(i), ColorSupport::Ansi16) => {
+                Some(Color::Ansi16(rgb_to_ansi16(ansi256_to_rgb(i))))
+            }
+            (Color::Ansi256(_), _) => Some(self),
+            (Color::Rgb(r, g, b), ColorSupport::Ansi16) => {
+                Some(Color::Ansi16(rgb_to_ansi16((r, g, b))))
+            }
+            (Color::Rgb(r, g, b), ColorSupport::Ansi256) => {
+                Some(Color::Ansi256(rgb_to_ansi256((r, g, b))))
+            }
+            (Color::Rgb(..), ColorSupport::TrueColor) => Some(self),
+        }
+    }
+}
+
+impl Style {
+    /// Parse a style tag (without braces), like `red`, `bold`, `color:208` or `#ff8800`.
+    pub(crate) fn parse(tag: &str) -> Option<Style> {
+        const NAMES: [&str; 8] = [
+            "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+        ];
+        match tag {
+            "reset" => return Some(Style::Reset),
+            "bold" => return Some(Style::Bold),
+            "dim" => return Some(Style::Dim),
+            "inverse" => return Some(Style::Inverse),
+            _ => {}
+        }
+        if let Some(i) = NAMES.iter().position(|&name| name == tag) {
+            return Some(Style::Foreground(Color::Ansi16(i as u8)));
+        }
+        if let Some(name) = tag.strip_prefix("bright_") {
+            let i = NAMES.iter().position(|&n| n == name)?;
+            return Some(Style::Foreground(Color::Ansi16(i as u8 + 8)));
+        }
+        if let Some(i) = tag.strip_prefix("color:") {
+            return Some(Style::Foreground(Color::Ansi256(i.parse().ok()?)));
+        }
+        if let Some(hex) = tag.strip_prefix('#') {
+            if hex.len() != 6 || !hex.is_ascii() {
+                return None;
+            }
+            let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+            return Some(Style::Foreground(Color::Rgb(
+                component(0)?,
+                component(2)?,
+                component(4)?,
+            )));
+        }
+        None
+    }
+
+    /// The escape sequence of the style, or `None` when it's not supported at all.
+    pub(crate) fn to_ansi(self, support: ColorSupport) -> Option<String> {
+        if support == ColorSupport::None {
+            return None;
+        }
+        Some(match self {
+            Style::Reset => RESET_ANSI.to_string(),
+            Style::Bold => "\x1b[1m".to_string(),
+            Style::Dim => "\x1b[2m".to_string(),
+            Style::Inverse => "\x1b[7m".to_string(),
+            Style::Foreground(color) => match color.degrade(support)? {
+                Color::Ansi16(i) if i < 8 => format!("\x1b[{}m", 30 + i),
+                Color::Ansi16(i) => format!("\x1b[{}m", 90 + i - 8),
+                Color::Ansi256(i) => format!("\x1b[38;5;{}m", i),
+                Color::Rgb(r, g, b) => format!("\x1b[38;2;{};{};{}m", r, g, b),
+            },
+        })
+    }
+}
+
+/// Remove escape sequences (CSI) from `s`, as they take no width in terminal.
+pub(crate) fn strip_ansi(s: &str) -> std::borrow::Cow<'_, str> {
+    if !s.contains('\x1b') {
+        return std::borrow::Cow::Borrowed(s);
+    }
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch == '\x1b' && chars.peek() == Some(&'[') {
+            chars.next();
+            // Parameters and intermediate bytes, until the final byte
+            for ch in chars.by_ref() {
+                if ('\x40'..='\x7e').contains(&ch) {
+                    break;
+                }
+            }
+        } else {
+            result.push(ch);
+        }
+    }
+    std::borrow::Cow::Owned(result)
+}
@@ -0,0 +1,124 @@
+//! The module contains `SubBar`, a part of a bar with its own position and length, created by `Bar::subrange`.
+//!
+//! A job of several phases could be shown as one bar, while each phase reports its own progress from 0 to 100%:
+//!
+//! ```
+//! fn download(progress: &kyuri::subrange::SubBar) {
+//!     progress.set_len(4096);
+//!     for _ in 0..4 {
+//!         progress.inc(1024);
+//!     }
+//! }
+//!
+//! let manager = kyuri::Manager::new(std::time::Duration::from_secs(1));
+//! let bar = manager.create_bar(100, "Installing", "{msg}: {pos}/{len}", true);
+//! // Downloading takes 80% of the bar, and unpacking the rest
+//! download(&bar.subrange(0.0, 0.8));
+//! assert_eq!(bar.get_pos(), 80);
+//! let unpack = bar.subrange(0.8, 1.0);
+//! unpack.set_len(10);
+//! unpack.inc(5);
+//! assert_eq!(bar.get_pos(), 90);
+//! ```
+
+use std::sync::Mutex;
+
+use crate::{Bar, LockUnpoisoned};
+
+/// A range of the parent bar, as a fraction of its length.
+///
+/// The position of the sub-bar from 0 to its length is mapped onto the range, and set as the position of the parent.
+pub struct SubBar<'a> {
+    parent: &'a Bar,
+    start: f64,
+    end: f64,
+    /// Position and length.
+    state: Mutex<(u64, u64)>,
+}
+
+impl<'a> SubBar<'a> {
+    pub(crate) fn new(parent: &'a Bar, start: f64, end: f64) -> Self {
+        // NaN is taken as 0 for the start, and as the start for the end
+        let start = match start.is_nan() {
+            true => 0.0,
+            false => start.clamp(0.0, 1.0),
+        };
+        let end = match end.is_nan() {
+            true => start,
+            false => end.clamp(start, 1.0),
+        };
+        SubBar {
+            parent,
+            start,
+            end,
+            state: Mutex::new((0, 0)),
+        }
+    }
+
+    /// Set the parent to `fraction` of this range.
+    fn report(&self, fraction: f64) {
+        let fraction = self.start + (self.end - self.start) * fraction;
+        let len = self.parent.get_len();
+        self.parent.set_pos((len as f64 * fraction).round() as u64);
+    }
+
+    fn update(&self, f: impl FnOnce(&mut (u64, u64))) {
+        let mut state = self.state.lock_unpoisoned();
+        f(&mut state);
+        let (pos, len) = *state;
+        // Keep the lock while reporting, so reports of concurrent updates are in order
+        let fraction = match len {
+            0 => 0.0,
+            len => pos.min(len) as f64 / len as f64,
+        };
+        self.report(fraction);
+    }
+
+    /// Increment the position by `n`, and set the position of the parent. This makes an unforced draw.
+    pub fn inc(&self, n: u64) {
+        self.update(|(pos, _)| *pos = pos.saturating_add(n));
+    }
+
+    /// Set the position, and set the position of the parent. This makes an unforced draw.
+    pub fn set_pos(&self, pos: u64) {
+        self.update(|state| state.0 = pos);
+    }
+
+    /// Set the length, and set the position of the parent. This makes an unforced draw.
+    pub fn set_len(&self, len: u64) {
+        self.update(|state| state.1 = len);
+    }
+
+    /// Get the position.
+    pub fn get_pos(&self) -> u64 {
+        self.state.lock_unpoisoned().0
+    }
+
+    /// Get the length.
+    pub fn get_len(&self) -> u64 {
+        self.state.lock_unpoisoned().1
+    }
+
+    /// Set the message of the parent.
+    pub fn set_message(&self, message: &str) {
+        self.parent.set_message(message);
+    }
+
+    /// Set the position to the length, and the parent to the end of this range, even when the length is 0.
+    pub fn finish(&self) {
+        let mut state = self.state.lock_unpoisoned();
+        state.0 = state.1;
+        self.report(1.0);
+    }
+
+    /// A range of this range, from `start` to `end` as fractions of it.
+    pub fn subrange(&self, start: f64, end: f64) -> SubBar<'a> {
+        let sub = SubBar::new(self.parent, start, end);
+        let width = self.end - self.start;
+        SubBar {
+            start: self.start + width * sub.start,
+            end: self.start + width * sub.end,
+            ..sub
+        }
+    }
+}
@@ -1,31 +1,260 @@
+use crate::style::Style;
+
 // Use similar tags as indicatif
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) enum TemplatePart {
     Newline,
     Message,
+    /// `{field:NAME}`
+    Field(String),
     /// HH:MM:SS
     Elapsed,
-    /// xx B/KiB/MiB/GiB...
-    Bytes,
+    Thread,
+    ThreadId,
+    /// xx B/KiB/MiB/GiB..., or as specified like `{bytes:unit=MiB:.1}` and `{mib_pos}`
+    Bytes(ByteFormat),
     Pos,
-    TotalBytes,
+    TotalBytes(ByteFormat),
     Total,
     /// xx B/s, xx KiB/s...
-    BytesPerSecond,
+    BytesPerSecond(ByteFormat),
     /// HH:MM:SS
     Eta,
+    /// Sums of all bars in the frame
+    AllBytes(ByteFormat),
+    AllTotalBytes(ByteFormat),
+    AllBytesPerSecond(ByteFormat),
+    ActiveBars,
+    /// `\a`, once when finished
+    Bell,
+    Spinner,
     StateEmoji,
     Bar(usize),
+    Style(Style),
     Text(String),
 }
 
-#[derive(Debug)]
-pub(crate) struct Template {
+/// Names of byte units, each 1024 times the previous one.
+const BYTE_UNITS: [&str; 7] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+/// Prefixes of the shorthand tags with a fixed unit, like `{mib_pos}` and `{mib_total}`.
+const BYTE_UNIT_PREFIXES: [&str; 6] = ["kib_", "mib_", "gib_", "tib_", "pib_", "eib_"];
+
+/// A fixed unit of bytes, as the index in `BYTE_UNITS`, so that values don't jump between units as they grow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ByteUnit(usize);
+
+impl ByteUnit {
+    fn parse(name: &str) -> Option<Self> {
+        BYTE_UNITS
+            .iter()
+            .position(|unit| *unit == name)
+            .map(ByteUnit)
+    }
+
+    /// Format `bytes` in this unit with `precision` decimals, like `0.50 MiB`, or `512 B`.
+    pub(crate) fn format(self, bytes: u64, precision: usize) -> String {
+        match self.0 {
+            0 => format!("{} B", bytes),
+            unit => format!(
+                "{:.*} {}",
+                precision,
+                bytes as f64 / 1024f64.powi(unit as i32),
+                BYTE_UNITS[unit]
+            ),
+        }
+    }
+}
+
+/// The highest precision of byte tags, set by `{bytes:.N}` or `Manager::set_byte_precision`.
+pub const MAX_BYTE_PRECISION: usize = 9;
+
+/// Specs of a byte tag like `{bytes:unit=MiB:.1}`, with `None` for the defaults.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct ByteFormat {
+    /// Scale automatically when `None`.
+    pub(crate) unit: Option<ByteUnit>,
+    /// Decimals, or the default of the manager when `None`.
+    pub(crate) precision: Option<usize>,
+}
+
+type BytePart = fn(ByteFormat) -> TemplatePart;
+
+/// Byte tags supporting specs, except the shorthand tags with a fixed unit.
+const BYTE_TAGS: [(&str, BytePart); 7] = [
+    ("bytes", TemplatePart::Bytes),
+    ("total_bytes", TemplatePart::TotalBytes),
+    ("bytes_per_second", TemplatePart::BytesPerSecond),
+    ("bytes_per_sec", TemplatePart::BytesPerSecond),
+    ("all_bytes", TemplatePart::AllBytes),
+    ("all_total_bytes", TemplatePart::AllTotalBytes),
+    ("all_bytes_per_sec", TemplatePart::AllBytesPerSecond),
+];
+
+/// Parse byte tags with specs separated by `:`, like `{bytes:unit=MiB}`, `{bytes_per_sec:.1}` and `{mib_pos:.0}`.
+///
+/// Each spec is given at most once, and the shorthand tags already have a unit.
+fn byte_tag(tag: &str) -> Option<TemplatePart> {
+    let mut specs = tag.split(':');
+    let name = specs.next()?;
+    let mut format = ByteFormat::default();
+    let part = match BYTE_TAGS.iter().find(|(tag, _)| *tag == name) {
+        Some((_, part)) => *part,
+        None => {
+            let index = BYTE_UNIT_PREFIXES
+                .iter()
+                .position(|prefix| name.starts_with(prefix))?;
+            format.unit = Some(ByteUnit(index + 1));
+            match &name[BYTE_UNIT_PREFIXES[index].len()..] {
+                "pos" => TemplatePart::Bytes,
+                "total" => TemplatePart::TotalBytes,
+                _ => return None,
+            }
+        }
+    };
+    for spec in specs {
+        match (spec.strip_prefix("unit="), spec.strip_prefix('.')) {
+            (Some(unit), _) if format.unit.is_none() => format.unit = Some(ByteUnit::parse(unit)?),
+            // A single digit, so that precision is at most `MAX_BYTE_PRECISION`
+            (_, Some(precision))
+                if format.precision.is_none()
+                    && precision.len() == 1
+                    && precision.as_bytes()[0].is_ascii_digit() =>
+            {
+                format.precision = Some(usize::from(precision.as_bytes()[0] - b'0'));
+            }
+            _ => return None,
+        }
+    }
+    Some(part(format))
+}
+
+/// The longest template in bytes: longer templates are truncated by `Template::new`, and rejected by `Template::parse`.
+pub const MAX_TEMPLATE_LEN: usize = 64 * 1024;
+
+/// The widest bar of `{barNUM}`: wider bars are clamped by `Template::new`, and rejected by `Template::parse`.
+pub const MAX_BAR_WIDTH: usize = 1024;
+
+/// A parsed template of a progress bar. See the crate documentation for supported tags.
+///
+/// `&str` and `String` could be converted into `Template` leniently with `Template::new`,
+/// so they could be used wherever a `Template` is expected.
+#[derive(Debug, Clone)]
+pub struct Template {
     pub(crate) parts: Vec<TemplatePart>,
+    /// The text parsed, after truncation, to save it with the state of bars.
+    source: String,
+}
+
+/// The error when parsing a template strictly with `Template::parse`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    /// A tag that is not supported, like `{foo}`.
+    UnknownTag(String),
+    /// A `{` without the closing `}`.
+    UnclosedTag(String),
+    /// A template longer than `MAX_TEMPLATE_LEN` bytes, with its length.
+    TooLong(usize),
+    /// A `{barNUM}` wider than `MAX_BAR_WIDTH`.
+    BarTooWide(String),
+}
+
+impl std::fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TemplateError::UnknownTag(tag) => write!(f, "unknown tag {{{}}}", tag),
+            TemplateError::UnclosedTag(tag) => write!(f, "unclosed tag {{{}", tag),
+            TemplateError::TooLong(len) => write!(
+                f,
+                "template of {} bytes is longer than {} bytes",
+                len, MAX_TEMPLATE_LEN
+            ),
+            TemplateError::BarTooWide(tag) => {
+                write!(f, "{{{}}} is wider than {}", tag, MAX_BAR_WIDTH)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+impl From<&str> for Template {
+    fn from(template: &str) -> Self {
+        Template::new(template)
+    }
+}
+
+impl From<&String> for Template {
+    fn from(template: &String) -> Self {
+        Template::new(template)
+    }
+}
+
+impl From<String> for Template {
+    fn from(template: String) -> Self {
+        Template::new(&template)
+    }
+}
+
+impl From<&Template> for Template {
+    fn from(template: &Template) -> Self {
+        template.clone()
+    }
 }
 
 impl Template {
-    pub(crate) fn new(template: &str) -> Self {
+    /// Parse a template leniently: unknown tags and unclosed `{` are kept as text.
+    ///
+    /// Templates longer than `MAX_TEMPLATE_LEN` are truncated, and bars wider than `MAX_BAR_WIDTH` are clamped.
+    pub fn new(template: &str) -> Self {
+        match Self::parse_inner(template, false) {
+            Ok(template) => template,
+            Err(_) => unreachable!("lenient parsing never fails"),
+        }
+    }
+
+    /// Parse a template strictly: unknown tags and unclosed `{` are errors.
+    pub fn parse(template: &str) -> Result<Self, TemplateError> {
+        Self::parse_inner(template, true)
+    }
+
+    /// Parse the template in the environment variable `var` strictly, or use `default` when it's not set.
+    ///
+    /// When the variable is not valid Unicode or not a valid template, `on_warning` is called with the reason,
+    /// and `default` is used, like `Manager::with_templates_from`.
+    pub fn from_env<F: FnOnce(&str)>(
+        var: &str,
+        default: impl Into<Template>,
+        on_warning: F,
+    ) -> Self {
+        let template = match std::env::var(var) {
+            Ok(template) => Self::parse(&template).map_err(|e| e.to_string()),
+            Err(std::env::VarError::NotPresent) => return default.into(),
+            Err(e) => Err(e.to_string()),
+        };
+        template.unwrap_or_else(|e| {
+            on_warning(&format!("{}: {}", var, e));
+            default.into()
+        })
+    }
+
+    /// The text the template was parsed from.
+    pub(crate) fn source(&self) -> &str {
+        &self.source
+    }
+
+    fn parse_inner(template: &str, strict: bool) -> Result<Self, TemplateError> {
+        let template = if template.len() <= MAX_TEMPLATE_LEN {
+            template
+        } else if strict {
+            return Err(TemplateError::TooLong(template.len()));
+        } else {
+            let mut end = MAX_TEMPLATE_LEN;
+            while !template.is_char_boundary(end) {
+                end -= 1;
+            }
+            &template[..end]
+        };
         enum Fragment {
             Text(String),
             Tag(String),
@@ -57,6 +286,8 @@ impl Template {
 
                         if found_closing_brace {
                             fragments.push(Tag(tag_content));
+                        } else if strict {
+                            return Err(TemplateError::UnclosedTag(tag_content));
                         } else {
                             current_text.push('{');
                             current_text.push_str(&tag_content);
@@ -102,31 +333,340 @@ impl Template {
                     "elapsed" => results.push(TemplatePart::Elapsed),
                     // indicatif tag
                     "elapsed_precise" => results.push(TemplatePart::Elapsed),
-                    "bytes" => results.push(TemplatePart::Bytes),
+                    "bytes" => results.push(TemplatePart::Bytes(ByteFormat::default())),
+                    "thread" => results.push(TemplatePart::Thread),
+                    "thread_id" => results.push(TemplatePart::ThreadId),
                     "pos" => results.push(TemplatePart::Pos),
-                    "total_bytes" => results.push(TemplatePart::TotalBytes),
+                    "total_bytes" => results.push(TemplatePart::TotalBytes(ByteFormat::default())),
                     "total" => results.push(TemplatePart::Total),
                     "len" => results.push(TemplatePart::Total),
-                    "bytes_per_second" => results.push(TemplatePart::BytesPerSecond),
+                    "bytes_per_second" => {
+                        results.push(TemplatePart::BytesPerSecond(ByteFormat::default()))
+                    }
                     // indicatif tag
-                    "bytes_per_sec" => results.push(TemplatePart::BytesPerSecond),
+                    "bytes_per_sec" => {
+                        results.push(TemplatePart::BytesPerSecond(ByteFormat::default()))
+                    }
                     "eta" => results.push(TemplatePart::Eta),
+                    "all_bytes" => results.push(TemplatePart::AllBytes(ByteFormat::default())),
+                    "all_total_bytes" => {
+                        results.push(TemplatePart::AllTotalBytes(ByteFormat::default()))
+                    }
+                    "all_bytes_per_sec" => {
+                        results.push(TemplatePart::AllBytesPerSecond(ByteFormat::default()))
+                    }
+                    "active_bars" => results.push(TemplatePart::ActiveBars),
                     s if s.starts_with("bar") => {
                         let bar_len = if s.len() == 3 {
                             20
                         } else {
-                            s[3..].parse().unwrap_or(20)
+                            match s[3..].parse::<usize>() {
+                                Ok(bar_len) if bar_len > MAX_BAR_WIDTH && strict => {
+                                    return Err(TemplateError::BarTooWide(tag))
+                                }
+                                Ok(bar_len) => bar_len.min(MAX_BAR_WIDTH),
+                                // Too large for usize
+                                Err(_) if s[3..].bytes().all(|b| b.is_ascii_digit()) => {
+                                    if strict {
+                                        return Err(TemplateError::BarTooWide(tag));
+                                    }
+                                    MAX_BAR_WIDTH
+                                }
+                                Err(_) if strict => return Err(TemplateError::UnknownTag(tag)),
+                                Err(_) => 20,
+                            }
                         };
                         results.push(TemplatePart::Bar(bar_len));
                     }
                     "state_emoji" => results.push(TemplatePart::StateEmoji),
-                    _ => {
-                        push_text(&mut results, &format!("{{{tag}}}"));
+                    "bell" => results.push(TemplatePart::Bell),
+                    "spinner" => results.push(TemplatePart::Spinner),
+                    s if s.starts_with("field:") && s.len() > "field:".len() => {
+                        results.push(TemplatePart::Field(s["field:".len()..].to_string()))
                     }
+                    s => match byte_tag(s).or_else(|| Style::parse(s).map(TemplatePart::Style)) {
+                        Some(part) => results.push(part),
+                        None if strict => return Err(TemplateError::UnknownTag(tag)),
+                        None => push_text(&mut results, &format!("{{{tag}}}")),
+                    },
                 },
             }
         }
 
-        Template { parts: results }
+        Ok(Template {
+            parts: results,
+            source: template.to_string(),
+        })
+    }
+}
+
+/// Create a `Template` from a string literal checked at compile time: unknown tags and unclosed `{` fail the build,
+/// like errors of `Template::parse`.
+///
+/// The template is parsed once, and a clone is returned each time.
+///
+/// ```
+/// let manager = kyuri::Manager::new(std::time::Duration::from_secs(1));
+/// let bar = manager.create_bar(100, "Processing", kyuri::template!("{msg}: {bar} ({pos}/{len})"), true);
+/// ```
+///
+/// ```compile_fail
+/// let template = kyuri::template!("{msg}: {barr}");
+/// ```
+#[macro_export]
+macro_rules! template {
+    ($template:expr) => {{
+        const TEMPLATE: &str = $template;
+        const _: () = $crate::__validate_template(TEMPLATE);
+        static PARSED: ::std::sync::OnceLock<$crate::Template> = ::std::sync::OnceLock::new();
+        PARSED
+            .get_or_init(|| $crate::Template::new(TEMPLATE))
+            .clone()
+    }};
+}
+
+/// Panic (at compile time in a const context) when `template` is rejected by `check`.
+#[doc(hidden)]
+pub const fn validate(template: &str) {
+    match check(template) {
+        Ok(()) => {}
+        Err(CheckError::UnknownTag) => panic!("unknown tag in template"),
+        Err(CheckError::UnclosedTag) => panic!("unclosed tag in template"),
+        Err(CheckError::TooLong) => panic!("template is longer than MAX_TEMPLATE_LEN"),
+        Err(CheckError::BarTooWide) => panic!("{{barNUM}} in template is wider than MAX_BAR_WIDTH"),
+    }
+}
+
+/// Like `TemplateError`, but without the tags, which could not be formatted in a const context.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum CheckError {
+    UnknownTag,
+    UnclosedTag,
+    TooLong,
+    BarTooWide,
+}
+
+/// Check `template` like `Template::parse` does, in a const context.
+pub(crate) const fn check(template: &str) -> Result<(), CheckError> {
+    let bytes = template.as_bytes();
+    if bytes.len() > MAX_TEMPLATE_LEN {
+        return Err(CheckError::TooLong);
+    }
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'{' {
+            i += 1;
+            continue;
+        }
+        if i + 1 < bytes.len() && bytes[i + 1] == b'{' {
+            i += 2;
+            continue;
+        }
+        let start = i + 1;
+        let mut end = start;
+        while end < bytes.len() && bytes[end] != b'}' {
+            end += 1;
+        }
+        if end == bytes.len() {
+            return Err(CheckError::UnclosedTag);
+        }
+        if let Err(e) = check_tag(bytes, start, end) {
+            return Err(e);
+        }
+        i = end + 1;
+    }
+    Ok(())
+}
+
+/// Check the tag in `bytes[start..end]`.
+const fn check_tag(bytes: &[u8], start: usize, end: usize) -> Result<(), CheckError> {
+    const TAGS: [&str; 26] = [
+        "msg",
+        "message",
+        "elapsed",
+        "elapsed_precise",
+        "bytes",
+        "thread",
+        "thread_id",
+        "pos",
+        "total_bytes",
+        "total",
+        "len",
+        "bytes_per_second",
+        "bytes_per_sec",
+        "eta",
+        "all_bytes",
+        "all_total_bytes",
+        "all_bytes_per_sec",
+        "active_bars",
+        "state_emoji",
+        "bell",
+        "spinner",
+        "bar",
+        // Styles
+        "reset",
+        "bold",
+        "dim",
+        "inverse",
+    ];
+    const COLORS: [&str; 8] = [
+        "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+    ];
+    let mut i = 0;
+    while i < TAGS.len() {
+        if equals(bytes, start, end, TAGS[i].as_bytes()) {
+            return Ok(());
+        }
+        i += 1;
+    }
+    let mut i = 0;
+    while i < COLORS.len() {
+        let color = COLORS[i].as_bytes();
+        if equals(bytes, start, end, color)
+            || (starts_with(bytes, start, end, b"bright_")
+                && equals(bytes, start + b"bright_".len(), end, color))
+        {
+            return Ok(());
+        }
+        i += 1;
+    }
+    if check_byte_tag(bytes, start, end) {
+        return Ok(());
+    }
+    if starts_with(bytes, start, end, b"field:") {
+        return match end > start + b"field:".len() {
+            true => Ok(()),
+            false => Err(CheckError::UnknownTag),
+        };
+    }
+    if starts_with(bytes, start, end, b"bar") {
+        return match number(bytes, start + b"bar".len(), end) {
+            Some(width) if width <= MAX_BAR_WIDTH as u128 => Ok(()),
+            Some(_) => Err(CheckError::BarTooWide),
+            None => Err(CheckError::UnknownTag),
+        };
+    }
+    if starts_with(bytes, start, end, b"color:") {
+        return match number(bytes, start + b"color:".len(), end) {
+            Some(color) if color <= u8::MAX as u128 => Ok(()),
+            _ => Err(CheckError::UnknownTag),
+        };
+    }
+    if starts_with(bytes, start, end, b"#") && end - start == 7 {
+        let mut i = start + 1;
+        while i < end && bytes[i].is_ascii_hexdigit() {
+            i += 1;
+        }
+        if i == end {
+            return Ok(());
+        }
+    }
+    Err(CheckError::UnknownTag)
+}
+
+/// Check byte tags with specs in `bytes[start..end]`, like `byte_tag`.
+const fn check_byte_tag(bytes: &[u8], start: usize, end: usize) -> bool {
+    let mut name_end = start;
+    while name_end < end && bytes[name_end] != b':' {
+        name_end += 1;
+    }
+    let mut known = false;
+    let mut i = 0;
+    while i < BYTE_TAGS.len() {
+        known = known || equals(bytes, start, name_end, BYTE_TAGS[i].0.as_bytes());
+        i += 1;
+    }
+    // Shorthand tags have a unit already
+    let mut unit = false;
+    let mut i = 0;
+    while i < BYTE_UNIT_PREFIXES.len() {
+        let prefix = BYTE_UNIT_PREFIXES[i].as_bytes();
+        if starts_with(bytes, start, name_end, prefix)
+            && (equals(bytes, start + prefix.len(), name_end, b"pos")
+                || equals(bytes, start + prefix.len(), name_end, b"total"))
+        {
+            known = true;
+            unit = true;
+        }
+        i += 1;
+    }
+    if !known {
+        return false;
+    }
+    let mut precision = false;
+    let mut spec_end = name_end;
+    while spec_end < end {
+        // Skip the `:`
+        let spec_start = spec_end + 1;
+        spec_end = spec_start;
+        while spec_end < end && bytes[spec_end] != b':' {
+            spec_end += 1;
+        }
+        if !unit && starts_with(bytes, spec_start, spec_end, b"unit=") {
+            let mut j = 0;
+            while j < BYTE_UNITS.len() {
+                unit = unit
+                    || equals(
+                        bytes,
+                        spec_start + b"unit=".len(),
+                        spec_end,
+                        BYTE_UNITS[j].as_bytes(),
+                    );
+                j += 1;
+            }
+            if !unit {
+                return false;
+            }
+        } else if !precision
+            && spec_end - spec_start == 2
+            && bytes[spec_start] == b'.'
+            && bytes[spec_start + 1].is_ascii_digit()
+        {
+            precision = true;
+        } else {
+            return false;
+        }
+    }
+    true
+}
+
+const fn equals(bytes: &[u8], start: usize, end: usize, expected: &[u8]) -> bool {
+    end - start == expected.len() && starts_with(bytes, start, end, expected)
+}
+
+const fn starts_with(bytes: &[u8], start: usize, end: usize, prefix: &[u8]) -> bool {
+    if end - start < prefix.len() {
+        return false;
+    }
+    let mut i = 0;
+    while i < prefix.len() {
+        if bytes[start + i] != prefix[i] {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// Parse `bytes[start..end]` like `str::parse` for unsigned integers, saturating at `u128::MAX`.
+const fn number(bytes: &[u8], start: usize, end: usize) -> Option<u128> {
+    let start = match starts_with(bytes, start, end, b"+") {
+        true => start + 1,
+        false => start,
+    };
+    if start == end {
+        return None;
+    }
+    let mut value: u128 = 0;
+    let mut i = start;
+    while i < end {
+        if !bytes[i].is_ascii_digit() {
+            return None;
+        }
+        value = value
+            .saturating_mul(10)
+            .saturating_add((bytes[i] - b'0') as u128);
+        i += 1;
     }
+    Some(value)
 }
@@ -0,0 +1,29 @@
+//! An advisory lock shared by processes writing to the same terminal, see `Manager::with_terminal_lock`.
+
+use std::os::fd::AsRawFd;
+
+pub(crate) struct TerminalLock {
+    file: std::fs::File,
+    held: bool,
+}
+
+impl TerminalLock {
+    pub(crate) fn new(path: &std::path::Path) -> std::io::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(path != std::path::Path::new("/dev/tty"))
+            .truncate(false)
+            .open(path)?;
+        Ok(TerminalLock { file, held: false })
+    }
+
+    /// Try to take the lock without blocking, and return whether it's held.
+    pub(crate) fn try_hold(&mut self) -> bool {
+        if !self.held {
+            self.held =
+                unsafe { libc::flock(self.file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0;
+        }
+        self.held
+    }
+}
@@ -2,7 +2,9 @@
 
 use super::DEFAULT_WIDTH;
 use windows_sys::Win32::Foundation::INVALID_HANDLE_VALUE;
-use windows_sys::Win32::System::Console::{SMALL_RECT, COORD, CONSOLE_SCREEN_BUFFER_INFO, GetConsoleScreenBufferInfo};
+use windows_sys::Win32::System::Console::{
+    GetConsoleScreenBufferInfo, CONSOLE_SCREEN_BUFFER_INFO, COORD, SMALL_RECT,
+};
 
 pub(crate) fn get_width<T: std::os::windows::io::AsRawHandle + ?Sized>(f: &T) -> u16 {
     let handle = f.as_raw_handle();
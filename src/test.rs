@@ -0,0 +1,86 @@
+//! Helpers for golden tests of progress UIs built with kyuri.
+//!
+//! ```
+//! use kyuri::{test::{render_frames, Step}, Bar};
+//! use std::time::Duration;
+//!
+//! let frames = render_frames(
+//!     |manager| manager.create_bar(100, "Downloading", "{msg} {pos}/{len} [{elapsed}]", true),
+//!     [
+//!         Step::new(|bar: &mut Bar| bar.set_pos(50)).after(Duration::from_secs(1)),
+//!         Step::new(|bar: &mut Bar| bar.set_pos(100)).after(Duration::from_secs(1)),
+//!     ],
+//! );
+//! assert_eq!(
+//!     frames,
+//!     [
+//!         "Downloading 0/100 [0:00:00]\n",
+//!         "Downloading 50/100 [0:00:01]\n",
+//!         "Downloading 100/100 [0:00:02]\n",
+//!     ]
+//! );
+//! ```
+
+use std::time::Duration;
+
+use crate::{clock::MockClock, style::ColorSupport, Manager};
+
+/// A step of `render_frames`.
+pub struct Step<T> {
+    advance: Duration,
+    action: Box<dyn FnOnce(&mut T)>,
+}
+
+impl<T> Step<T> {
+    /// Run `action` with the value returned by the setup of `render_frames`, like the bars created.
+    pub fn new(action: impl FnOnce(&mut T) + 'static) -> Self {
+        Step {
+            advance: Duration::ZERO,
+            action: Box::new(action),
+        }
+    }
+
+    /// Only advance the clock by `duration`.
+    pub fn wait(duration: Duration) -> Self {
+        Step::new(|_| {}).after(duration)
+    }
+
+    /// Advance the clock by `duration` before running the action.
+    pub fn after(mut self, duration: Duration) -> Self {
+        self.advance += duration;
+        self
+    }
+}
+
+/// Render frames of a progress UI deterministically: one frame after `setup`, and one after each step.
+///
+/// `setup` creates bars with the `Manager`, which is in ANSI mode without colors, and writes nowhere.
+/// A frame is what the bars (and log lines of `Manager::log_line`) look like on the terminal, each line ending with `\n`.
+///
+/// The clock of bars is mocked: it starts when this is called, and only advances by `Step::after`,
+/// so that tags like `{elapsed}`, `{eta}` and `{bytes_per_sec}` are deterministic.
+/// The mocked clock only works on the current thread, so bars should be updated on it.
+pub fn render_frames<T>(
+    setup: impl FnOnce(&Manager) -> T,
+    steps: impl IntoIterator<Item = Step<T>>,
+) -> Vec<String> {
+    let clock = MockClock::new();
+    let null = if cfg!(windows) { "NUL" } else { "/dev/null" };
+    let manager = Manager::new(Duration::from_secs(1))
+        .with_file(
+            std::fs::OpenOptions::new()
+                .write(true)
+                .open(null)
+                .expect("failed to open the null device"),
+        )
+        .force_ansi(true)
+        .with_color_support(Some(ColorSupport::None));
+    let mut value = setup(&manager);
+    let mut frames = vec![manager.inner.render_frame()];
+    for step in steps {
+        clock.advance(step.advance);
+        (step.action)(&mut value);
+        frames.push(manager.inner.render_frame());
+    }
+    frames
+}
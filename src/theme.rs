@@ -0,0 +1,131 @@
+//! The module contains `Theme`, presets bundling the bar style, state icons and a default template.
+
+/// A theme bundling the style of `{bar}`, icons of `{state_emoji}`, frames of `{spinner}`, and a default template.
+///
+/// Apply it with `Manager::with_theme`. Fields could be changed to customize a preset.
+/// Presets other than the default are not affected by the `ascii_only` feature, as they are chosen explicitly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Theme {
+    /// Drawn at the start of `{bar}`.
+    pub bar_left: String,
+    /// Drawn at the end of `{bar}`, when it's not overflowed.
+    pub bar_right: String,
+    /// Drawn for each filled cell of `{bar}`.
+    pub bar_filled: String,
+    /// Drawn for each empty cell of `{bar}`.
+    pub bar_empty: String,
+    /// Drawn for each overflowed cell of `{bar}`, when pos > len.
+    pub bar_overflowed: String,
+    /// `{state_emoji}` when pos == len.
+    pub icon_finished: String,
+    /// `{state_emoji}` when pos == 0.
+    pub icon_new: String,
+    /// `{state_emoji}` when pos > len.
+    pub icon_overflowed: String,
+    /// `{state_emoji}` when 0 < pos < len.
+    pub icon_in_progress: String,
+    /// `{state_emoji}` when the bar is marked as failed by `Bar::fail`.
+    pub icon_failed: String,
+    /// Frames of `{spinner}`, unless a bar has its own ones set by `Bar::set_spinner_frames`.
+    pub spinner_frames: Vec<String>,
+    /// The template returned by `Manager::default_template`.
+    pub template: String,
+}
+
+impl Default for Theme {
+    /// The default look of kyuri.
+    ///
+    /// With the `ascii_only` feature, icons and spinner frames are those of `Theme::ascii`.
+    fn default() -> Self {
+        let theme = Theme {
+            bar_left: "[".to_string(),
+            bar_right: "]".to_string(),
+            bar_filled: "=".to_string(),
+            bar_empty: " ".to_string(),
+            bar_overflowed: "!".to_string(),
+            icon_finished: "✅".to_string(),
+            icon_new: "🆕".to_string(),
+            icon_overflowed: "💥".to_string(),
+            icon_in_progress: "⏳".to_string(),
+            icon_failed: "❌".to_string(),
+            spinner_frames: frames(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"]),
+            template: "{msg}: {bar} ({pos}/{len})".to_string(),
+        };
+        #[cfg(feature = "ascii_only")]
+        let theme = Theme {
+            bar_filled: theme.bar_filled,
+            bar_empty: theme.bar_empty,
+            template: theme.template,
+            ..Theme::ascii()
+        };
+        theme
+    }
+}
+
+impl Theme {
+    /// ASCII only, for terminals and fonts without Unicode support.
+    pub fn ascii() -> Self {
+        Theme {
+            bar_left: "[".to_string(),
+            bar_right: "]".to_string(),
+            bar_filled: "#".to_string(),
+            bar_empty: "-".to_string(),
+            bar_overflowed: "!".to_string(),
+            icon_finished: "[OK]".to_string(),
+            icon_new: "[..]".to_string(),
+            icon_overflowed: "[!!]".to_string(),
+            icon_in_progress: "[>>]".to_string(),
+            icon_failed: "[XX]".to_string(),
+            spinner_frames: frames(&["|", "/", "-", "\\"]),
+            template: "{state_emoji} {msg}: {bar} ({pos}/{len})".to_string(),
+        }
+    }
+
+    /// Heavy Unicode blocks, with elapsed time and ETA.
+    pub fn unicode_heavy() -> Self {
+        Theme {
+            bar_left: "▕".to_string(),
+            bar_right: "▏".to_string(),
+            bar_filled: "█".to_string(),
+            bar_empty: "░".to_string(),
+            bar_overflowed: "▓".to_string(),
+            icon_finished: "✅".to_string(),
+            icon_new: "🆕".to_string(),
+            icon_overflowed: "💥".to_string(),
+            icon_in_progress: "⏳".to_string(),
+            icon_failed: "❌".to_string(),
+            spinner_frames: frames(&["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"]),
+            template: "{state_emoji} {msg} {bar} {pos}/{len} [{elapsed} < {eta}]".to_string(),
+        }
+    }
+
+    /// A thin line without brackets.
+    pub fn minimal() -> Self {
+        Theme {
+            bar_left: String::new(),
+            bar_right: String::new(),
+            bar_filled: "━".to_string(),
+            bar_empty: "─".to_string(),
+            bar_overflowed: "!".to_string(),
+            icon_finished: "✓".to_string(),
+            icon_new: "·".to_string(),
+            icon_overflowed: "!".to_string(),
+            icon_in_progress: "…".to_string(),
+            icon_failed: "✗".to_string(),
+            spinner_frames: frames(&["◜", "◝", "◞", "◟"]),
+            template: "{msg} {bar} {pos}/{len}".to_string(),
+        }
+    }
+
+    /// ASCII only and without `{bar}` in the template, for CI logs.
+    pub fn ci() -> Self {
+        Theme {
+            template: "{state_emoji} {msg}: {pos}/{len} ({elapsed})".to_string(),
+            ..Theme::ascii()
+        }
+    }
+}
+
+fn frames(frames: &[&str]) -> Vec<String> {
+    frames.iter().map(|frame| frame.to_string()).collect()
+}
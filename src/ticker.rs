@@ -1,34 +1,83 @@
 use std::{
     sync::{Arc, Condvar, Mutex},
     thread,
+    time::Instant,
 };
 
-use crate::ManagerInner;
+use crate::{LockUnpoisoned, ManagerInner};
+
+#[derive(Default)]
+struct Signal {
+    stopped: bool,
+    /// Set by `nudge`, to draw a bar event without waiting for the next tick.
+    nudged: bool,
+    /// Set by `reschedule`, when the interval is changed.
+    rescheduled: bool,
+}
 
 pub(crate) struct Ticker {
     // thread join requires ownership of the thread, so an Option is used to take it out
     thread: Option<thread::JoinHandle<()>>,
-    condvar: Arc<(Mutex<bool>, Condvar)>,
+    condvar: Arc<(Mutex<Signal>, Condvar)>,
 }
 
 impl Ticker {
     pub(crate) fn new(manager: Arc<ManagerInner>) -> Self {
-        let condvar = Arc::new((Mutex::new(false), Condvar::new()));
+        let condvar = Arc::new((Mutex::new(Signal::default()), Condvar::new()));
 
         let condvar2 = Arc::clone(&condvar);
         let manager = Arc::downgrade(&manager);
         let thread = thread::spawn(move || {
-            while let Some(manager) = manager.upgrade() {
-                let interval = manager.interval;
+            // Ticks are scheduled at fixed points, so that a slow draw does not delay all following ticks
+            let mut next = Instant::now();
+            let mut interval = match manager.upgrade() {
+                Some(manager) => manager.interval(),
+                None => return,
+            };
+            next += interval;
+            loop {
+                let timeout = next.saturating_duration_since(Instant::now());
                 let (lock, cvar) = &*condvar2;
-                let done = cvar
-                    .wait_timeout_while(lock.lock().unwrap(), interval, |stopped| !*stopped)
-                    .unwrap();
-                if !done.1.timed_out() {
+                let (mut signal, _) = cvar
+                    .wait_timeout_while(lock.lock_unpoisoned(), timeout, |signal| {
+                        !signal.stopped && !signal.nudged && !signal.rescheduled
+                    })
+                    .unwrap_or_else(std::sync::PoisonError::into_inner);
+                if signal.stopped {
+                    break;
+                }
+                let nudged = std::mem::take(&mut signal.nudged);
+                let rescheduled = std::mem::take(&mut signal.rescheduled);
+                std::mem::drop(signal);
+                // Don't keep the manager alive while waiting
+                let Some(manager) = manager.upgrade() else {
                     break;
+                };
+                if rescheduled {
+                    // Count the new interval from the last tick, which could be due already
+                    let last = next.checked_sub(interval).unwrap_or(next);
+                    interval = manager.interval();
+                    next = last + interval;
+                    if !nudged {
+                        continue;
+                    }
+                }
+                if nudged {
+                    manager.draw_event_now();
+                    continue;
                 }
                 // When ticker is on, unforced draw is ignored.
                 manager.draw(true);
+                // When drawing took longer than the interval (like a slow terminal),
+                // skip the ticks missed instead of drawing them back to back
+                let behind = Instant::now().saturating_duration_since(next);
+                let missed = behind.as_nanos() / interval.as_nanos().max(1);
+                if missed > 0 {
+                    manager.add_skipped_frames(missed as u64);
+                    next += interval * missed.min(u32::MAX as u128) as u32;
+                }
+                interval = manager.interval();
+                next += interval;
             }
         });
         Self {
@@ -39,7 +88,21 @@ impl Ticker {
 
     pub(crate) fn stop(&self) {
         let (lock, cvar) = &*self.condvar;
-        *lock.lock().unwrap() = true;
+        lock.lock_unpoisoned().stopped = true;
+        cvar.notify_one();
+    }
+
+    /// Wake up the ticker thread to pick up the new interval.
+    pub(crate) fn reschedule(&self) {
+        let (lock, cvar) = &*self.condvar;
+        lock.lock_unpoisoned().rescheduled = true;
+        cvar.notify_one();
+    }
+
+    /// Wake up the ticker thread to draw a bar event now.
+    pub(crate) fn nudge(&self) {
+        let (lock, cvar) = &*self.condvar;
+        lock.lock_unpoisoned().nudged = true;
         cvar.notify_one();
     }
 }
@@ -48,7 +111,11 @@ impl Drop for Ticker {
     fn drop(&mut self) {
         self.stop();
         if let Some(t) = self.thread.take() {
-            t.join().unwrap();
+            // The manager could be dropped by the ticker thread itself, when it's the last one holding it
+            if t.thread().id() != thread::current().id() {
+                // A panic in the ticker thread is not propagated to the dropping thread
+                let _ = t.join();
+            }
         }
     }
 }
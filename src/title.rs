@@ -0,0 +1,63 @@
+//! Show aggregate progress in the process title, see `Manager::set_process_title`.
+//!
+//! On Linux, /proc/self/comm is the name of the main thread, which is shown by ps and top.
+//! It could be written from any thread of the process, and is limited to 15 bytes.
+
+const COMM_LEN: usize = 15;
+
+pub(crate) struct ProcessTitle {
+    /// The name before kyuri changed it, restored when disabled.
+    original: String,
+    last: String,
+}
+
+impl ProcessTitle {
+    pub(crate) fn new() -> Self {
+        let original = read_comm().unwrap_or_default();
+        ProcessTitle {
+            last: original.clone(),
+            original,
+        }
+    }
+
+    /// Set the title to "name [NN%]", skipping when unchanged.
+    pub(crate) fn update(&mut self, percent: u64) {
+        let suffix = format!(" [{}%]", percent);
+        let mut name = self.original.clone();
+        while name.len() + suffix.len() > COMM_LEN {
+            name.pop();
+        }
+        let title = format!("{}{}", name, suffix);
+        if title != self.last {
+            write_comm(&title);
+            self.last = title;
+        }
+    }
+}
+
+impl Drop for ProcessTitle {
+    fn drop(&mut self) {
+        if self.last != self.original {
+            write_comm(&self.original);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn read_comm() -> Option<String> {
+    let comm = std::fs::read_to_string("/proc/self/comm").ok()?;
+    Some(comm.trim_end_matches('\n').to_string())
+}
+
+#[cfg(target_os = "linux")]
+fn write_comm(title: &str) {
+    let _ = std::fs::write("/proc/self/comm", title);
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_comm() -> Option<String> {
+    None
+}
+
+#[cfg(not(target_os = "linux"))]
+fn write_comm(_: &str) {}
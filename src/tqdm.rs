@@ -0,0 +1,67 @@
+//! Render bars like tqdm when output is not a terminal, see `Manager::with_tqdm_style`.
+//!
+//! ```text
+//!  50%|█████     | 50/100 [00:10<00:10, 5 it/s]
+//! ```
+//!
+//! Bars without len are rendered like `50it [00:10, 5 it/s]`.
+
+use crate::BarState;
+
+const BAR_WIDTH: u64 = 10;
+
+#[cfg(not(feature = "ascii_only"))]
+const FILLED: &str = "█";
+#[cfg(feature = "ascii_only")]
+const FILLED: &str = "#";
+
+pub(crate) fn render(state: &BarState) -> String {
+    let mut result = String::new();
+    if !state.message.is_empty() {
+        result.push_str(&state.message);
+        result.push_str(": ");
+    }
+    let elapsed = format_duration(state.elapsed());
+    let rate = format_rate(state.per_sec());
+    if state.len == 0 {
+        result.push_str(&format!("{}it [{}, {}]", state.pos, elapsed, rate));
+        return result;
+    }
+    let pos = state.pos.min(state.len) as u128;
+    let percent = pos * 100 / state.len as u128;
+    let filled = (pos * BAR_WIDTH as u128 / state.len as u128) as usize;
+    let eta = state.eta().map_or("?".to_string(), format_duration);
+    result.push_str(&format!(
+        "{:3}%|{}{}| {}/{} [{}<{}, {}]",
+        percent,
+        FILLED.repeat(filled),
+        " ".repeat(BAR_WIDTH as usize - filled),
+        state.pos,
+        state.len,
+        elapsed,
+        eta,
+        rate
+    ));
+    result
+}
+
+/// MM:SS, or H:MM:SS from an hour.
+fn format_duration(duration: std::time::Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs % 3600 / 60, secs % 60)
+    } else {
+        format!("{:02}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+/// Items per second, or seconds per item when slower than one per second.
+fn format_rate(per_sec: f64) -> String {
+    if per_sec <= 0.0 {
+        "? it/s".to_string()
+    } else if per_sec < 1.0 {
+        format!("{:.2} s/it", 1.0 / per_sec)
+    } else {
+        format!("{:.0} it/s", per_sec)
+    }
+}
@@ -0,0 +1,70 @@
+//! The module contains `BarsWidget`, drawing bars of a manager in a [ratatui](https://ratatui.rs) application,
+//! with the `ratatui` feature.
+//!
+//! Bars are created and updated as before, and the widget renders them as they are when the application draws:
+//!
+//! ```
+//! use ratatui::{buffer::Buffer, layout::Rect, widgets::Widget};
+//!
+//! // Keep the manager from drawing by itself, as the terminal belongs to the application
+//! let manager = kyuri::Manager::new(std::time::Duration::from_secs(1))
+//!     .with_file(std::fs::File::create("/dev/null").unwrap());
+//! let bar = manager.create_bar(100, "Download", "{msg}: {pos}/{len}", true);
+//! bar.set_pos(30);
+//!
+//! let mut buffer = Buffer::empty(Rect::new(0, 0, 20, 1));
+//! kyuri::tui::BarsWidget::new(&manager).render(buffer.area, &mut buffer);
+//! assert_eq!(buffer, Buffer::with_lines(vec!["Download: 30/100    "]));
+//! ```
+
+use ratatui::{buffer::Buffer, layout::Rect, style::Style, widgets::Widget};
+
+use crate::{style::strip_ansi, LockUnpoisoned, Manager};
+
+/// Visible bars of a manager, one per row, truncated to the area.
+///
+/// Bars are rendered like in ANSI mode without colors, so the widget could be styled by the application.
+#[derive(Clone, Copy)]
+pub struct BarsWidget<'a> {
+    manager: &'a Manager,
+    style: Style,
+}
+
+impl<'a> BarsWidget<'a> {
+    /// Render bars of `manager`, without a style.
+    pub fn new(manager: &'a Manager) -> Self {
+        BarsWidget {
+            manager,
+            style: Style::default(),
+        }
+    }
+
+    /// The style of all rows.
+    pub fn style(mut self, style: Style) -> Self {
+        self.style = style;
+        self
+    }
+}
+
+impl Widget for BarsWidget<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        buf.set_style(area, self.style);
+        let inner = &self.manager.inner;
+        let states = inner.states.lock_unpoisoned();
+        let options = inner.plain_options(&states);
+        let visible = states
+            .values()
+            .map(|state| state.lock_unpoisoned())
+            .filter(|state| state.visible);
+        for (row, state) in (area.top()..area.bottom()).zip(visible) {
+            let line = state.render(&options);
+            buf.set_stringn(
+                area.x,
+                row,
+                strip_ansi(&line),
+                area.width as usize,
+                self.style,
+            );
+        }
+    }
+}
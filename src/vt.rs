@@ -0,0 +1,148 @@
+//! A virtual terminal for tests, applying the output of a manager to a grid of cells,
+//! so tests could assert on what the screen looks like in the end.
+//!
+//! Characters wrap at the end of lines (after the last column is written, like xterm),
+//! and `\n`, `\r`, `\b`, and CSI sequences moving the cursor (`A`, `B`, `E`, `F`, `G`) or erasing (`K`, `J`)
+//! are handled. Other control characters (like `\a`) and CSI sequences (like styles) are ignored.
+
+use crate::char_width;
+
+/// The second cell of a wide character.
+const WIDE_TAIL: char = '\0';
+
+pub(crate) struct VirtualTerminal {
+    width: usize,
+    rows: Vec<Vec<char>>,
+    row: usize,
+    col: usize,
+}
+
+impl VirtualTerminal {
+    pub(crate) fn new(width: usize) -> Self {
+        VirtualTerminal {
+            width: width.max(1),
+            rows: Vec::new(),
+            row: 0,
+            col: 0,
+        }
+    }
+
+    /// The row of the cursor, from 0.
+    pub(crate) fn row(&self) -> usize {
+        self.row
+    }
+
+    fn line(&mut self) -> &mut Vec<char> {
+        if self.rows.len() <= self.row {
+            self.rows.resize(self.row + 1, Vec::new());
+        }
+        &mut self.rows[self.row]
+    }
+
+    fn up(&mut self, n: usize) {
+        self.row = self
+            .row
+            .checked_sub(n)
+            .expect("cursor moved above the output");
+    }
+
+    pub(crate) fn write(&mut self, s: &str) {
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\x1b' => {
+                    assert_eq!(chars.next(), Some('['), "only CSI sequences are expected");
+                    let mut params = String::new();
+                    let final_byte = chars.by_ref().find(|c| {
+                        if ('\x40'..='\x7e').contains(c) {
+                            return true;
+                        }
+                        params.push(*c);
+                        false
+                    });
+                    self.csi(&params, final_byte.expect("unterminated CSI sequence"));
+                }
+                '\n' => {
+                    self.row += 1;
+                    self.col = 0;
+                }
+                '\r' => self.col = 0,
+                '\x08' => self.col = self.col.min(self.width - 1).saturating_sub(1),
+                c if c.is_control() => {}
+                c => self.put(c),
+            }
+        }
+    }
+
+    fn csi(&mut self, params: &str, final_byte: char) {
+        let n = params.parse().unwrap_or(1).max(1);
+        match final_byte {
+            'A' => self.up(n),
+            'B' => self.row += n,
+            'E' => {
+                self.row += n;
+                self.col = 0;
+            }
+            'F' => {
+                self.up(n);
+                self.col = 0;
+            }
+            'G' => self.col = (n - 1).min(self.width - 1),
+            'K' => {
+                let col = self.col.min(self.width - 1);
+                let line = self.line();
+                match params {
+                    "" | "0" => line.truncate(col),
+                    "1" => {
+                        // Including the cursor
+                        let end = (col + 1).min(line.len());
+                        line[..end].fill(' ');
+                    }
+                    _ => line.clear(),
+                }
+            }
+            'J' => {
+                let col = self.col.min(self.width - 1);
+                self.line().truncate(col);
+                self.rows.truncate(self.row + 1);
+            }
+            _ => {}
+        }
+    }
+
+    fn put(&mut self, c: char) {
+        let width = char_width(c);
+        if width == 0 {
+            return;
+        }
+        // Including the pending wrap after a full line
+        if self.col + width > self.width {
+            self.row += 1;
+            self.col = 0;
+        }
+        let col = self.col;
+        let line = self.line();
+        if line.len() < col + width {
+            line.resize(col + width, ' ');
+        }
+        line[col] = c;
+        line[col + 1..col + width].fill(WIDE_TAIL);
+        self.col += width;
+    }
+
+    /// Lines on the screen, without trailing spaces and trailing empty lines.
+    pub(crate) fn lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self
+            .rows
+            .iter()
+            .map(|row| {
+                let line: String = row.iter().filter(|&&c| c != WIDE_TAIL).collect();
+                line.trim_end().to_string()
+            })
+            .collect();
+        while lines.last().is_some_and(|line| line.is_empty()) {
+            lines.pop();
+        }
+        lines
+    }
+}
@@ -1,65 +1,225 @@
-//! The modules contains `KyuriWriter`, a wrapper used with other libraries.
+//! The modules contains `KyuriWriter`, a wrapper used with other libraries, and `KyuriWriterBuilder` to configure it.
 
-use std::sync::{Arc, Mutex, Weak};
+use std::{
+    io::Write,
+    sync::{Arc, Mutex, Weak},
+};
 
-use crate::{ManagerInner, Out};
+use crate::{LockUnpoisoned, ManagerInner, Out};
+
+/// Where a `KyuriWriter` writes to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriterTarget {
+    /// The output of the manager, the same as the progress bars.
+    Output,
+    /// Stdout, regardless of the output of the manager.
+    Stdout,
+    /// Stderr, regardless of the output of the manager.
+    Stderr,
+}
+
+/// A builder for `KyuriWriter`, created by `Manager::writer_builder`.
+pub struct KyuriWriterBuilder {
+    manager: Arc<ManagerInner>,
+    target: WriterTarget,
+    timestamp: bool,
+    level: Option<String>,
+}
+
+impl KyuriWriterBuilder {
+    pub(crate) fn new(manager: Arc<ManagerInner>) -> Self {
+        KyuriWriterBuilder {
+            manager,
+            target: WriterTarget::Output,
+            timestamp: false,
+            level: None,
+        }
+    }
+
+    /// Set where the writer writes to. Default is `WriterTarget::Output`.
+    ///
+    /// Progress bars are still hidden while writing to another target, as they may share the same terminal.
+    pub fn target(mut self, target: WriterTarget) -> Self {
+        self.target = target;
+        self
+    }
+
+    /// Prefix each line with the UNIX timestamp (`[1700000000.123] `). Default is false.
+    pub fn timestamp(mut self, timestamp: bool) -> Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    /// Prefix each line with a level (`[WARN] `), after the timestamp. Default is no level.
+    pub fn level(mut self, level: &str) -> Self {
+        self.level = Some(level.to_string());
+        self
+    }
+
+    /// Build the `KyuriWriter`.
+    pub fn build(self) -> KyuriWriter {
+        KyuriWriter {
+            manager: Arc::downgrade(&self.manager),
+            out: self.manager.out.clone(),
+            target: self.target,
+            timestamp: self.timestamp,
+            level: self.level,
+            at_line_start: true,
+            buffer: Vec::new(),
+        }
+    }
+}
 
 /// A writer wrapping the output writer, that can be used to write to the output.
 ///
+/// Partial lines are buffered until a newline or `flush`, so that libraries writing a line in several
+/// small writes would not cause progress bars to be redrawn for each of them.
+/// The remaining partial line is written when the writer is dropped.
+///
+/// It could be cloned, so that several components could each own a writer without an external `Mutex`.
+/// Each clone has its own partial line buffer, and writes from different clones are serialized by the manager.
+///
 /// When the manager is dropped, the writer will continue to write to the original output writer.
 pub struct KyuriWriter {
     manager: Weak<ManagerInner>,
     // A copy of the output writer, to use when the manager is dropped
     out: Arc<Mutex<Box<dyn Out>>>,
+    target: WriterTarget,
+    timestamp: bool,
+    level: Option<String>,
+    at_line_start: bool,
+    // Partial line not written yet
+    buffer: Vec<u8>,
 }
 
-impl KyuriWriter {
-    pub(crate) fn new(manager: Arc<ManagerInner>) -> Self {
+/// Writes to `inner`, with a prefix at the start of each line.
+struct PrefixWriter<'a> {
+    inner: &'a mut dyn Write,
+    prefix: &'a str,
+    at_line_start: &'a mut bool,
+}
+
+impl Write for PrefixWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.prefix.is_empty() {
+            return self.inner.write(buf);
+        }
+        let mut prefixed = Vec::with_capacity(buf.len() + self.prefix.len());
+        for &byte in buf {
+            if *self.at_line_start {
+                prefixed.extend_from_slice(self.prefix.as_bytes());
+            }
+            prefixed.push(byte);
+            *self.at_line_start = byte == b'\n';
+        }
+        self.inner.write_all(&prefixed)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl Clone for KyuriWriter {
+    fn clone(&self) -> Self {
         KyuriWriter {
-            manager: Arc::downgrade(&manager),
-            out: manager.out.clone(),
+            manager: self.manager.clone(),
+            out: self.out.clone(),
+            target: self.target,
+            timestamp: self.timestamp,
+            level: self.level.clone(),
+            at_line_start: true,
+            buffer: Vec::new(),
         }
     }
 }
 
-impl std::io::Write for KyuriWriter {
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        if let Some(manager) = self.manager.upgrade() {
-            manager.suspend(|out| out.write(buf))
-        } else {
-            self.out.lock().unwrap().write(buf)
+impl KyuriWriter {
+    pub(crate) fn new(manager: Arc<ManagerInner>) -> Self {
+        KyuriWriterBuilder::new(manager).build()
+    }
+
+    fn prefix(&self) -> String {
+        let mut prefix = String::new();
+        if self.timestamp {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default();
+            prefix.push_str(&format!("[{}.{:03}] ", now.as_secs(), now.subsec_millis()));
+        }
+        if let Some(level) = &self.level {
+            prefix.push_str(&format!("[{}] ", level));
         }
+        prefix
     }
 
-    fn flush(&mut self) -> std::io::Result<()> {
+    /// Whether the manager is drawing or suspended on this thread, where writing would deadlock.
+    fn is_reentrant(&self) -> bool {
+        self.manager
+            .upgrade()
+            .is_some_and(|manager| manager.is_rendering())
+    }
+
+    /// Run `f` with the target writer, hiding progress bars when the manager is alive.
+    fn with_target<F: FnOnce(&mut dyn Write) -> std::io::Result<R>, R>(
+        &mut self,
+        f: F,
+    ) -> std::io::Result<R> {
+        let prefix = self.prefix();
+        let target = self.target;
+        let at_line_start = &mut self.at_line_start;
+        let run = |out: &mut dyn Write| {
+            let mut writer = PrefixWriter {
+                inner: out,
+                prefix: &prefix,
+                at_line_start,
+            };
+            f(&mut writer)
+        };
+        let run_with_target = |out: &mut Box<dyn Out>| match target {
+            WriterTarget::Output => run(out),
+            WriterTarget::Stdout => run(&mut std::io::stdout().lock()),
+            WriterTarget::Stderr => run(&mut std::io::stderr().lock()),
+        };
         if let Some(manager) = self.manager.upgrade() {
-            manager.suspend(|out| out.flush())
+            manager.suspend(run_with_target)
         } else {
-            self.out.lock().unwrap().flush()
+            run_with_target(&mut self.out.lock_unpoisoned())
         }
     }
+}
 
-    fn write_vectored(&mut self, bufs: &[std::io::IoSlice<'_>]) -> std::io::Result<usize> {
-        if let Some(manager) = self.manager.upgrade() {
-            manager.suspend(|out| out.write_vectored(bufs))
-        } else {
-            self.out.lock().unwrap().write_vectored(bufs)
+impl std::io::Write for KyuriWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.is_reentrant() {
+            // Inside `suspend` or a draw of the manager on this thread: keep the lines until the next write
+            return Ok(buf.len());
         }
+        if let Some(last_newline) = self.buffer.iter().rposition(|&b| b == b'\n') {
+            let lines: Vec<u8> = self.buffer.drain(..=last_newline).collect();
+            self.with_target(|out| out.write_all(&lines))?;
+        }
+        Ok(buf.len())
     }
 
-    fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
-        if let Some(manager) = self.manager.upgrade() {
-            manager.suspend(|out| out.write_all(buf))
-        } else {
-            self.out.lock().unwrap().write_all(buf)
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.is_reentrant() {
+            return Ok(());
         }
+        let buffer = std::mem::take(&mut self.buffer);
+        self.with_target(|out| {
+            out.write_all(&buffer)?;
+            out.flush()
+        })
     }
+}
 
-    fn write_fmt(&mut self, fmt: std::fmt::Arguments<'_>) -> std::io::Result<()> {
-        if let Some(manager) = self.manager.upgrade() {
-            manager.suspend(|out| out.write_fmt(fmt))
-        } else {
-            self.out.lock().unwrap().write_fmt(fmt)
+impl Drop for KyuriWriter {
+    fn drop(&mut self) {
+        if !self.buffer.is_empty() {
+            let _ = self.flush();
         }
     }
 }